@@ -0,0 +1,48 @@
+use std::collections::VecDeque;
+use super::*;
+
+const MAX_ACTIVITY: usize = 1000;
+
+#[derive(Debug, Clone)]
+pub struct ActivityEntry {
+    pub decode: Decode,
+    pub needs: Option<Needs>,
+    // false when the display filter hid this decode
+    pub shown: bool,
+}
+
+// recent decodes kept for the band activity and RX frequency views
+#[derive(Debug, Default)]
+pub struct ActivityLog {
+    pub band_activity: VecDeque<ActivityEntry>,
+    pub rx_frequency: VecDeque<ActivityEntry>,
+}
+
+impl ActivityLog {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push(&mut self, entry: ActivityEntry, in_rx_window: bool) {
+        if in_rx_window {
+            self.rx_frequency.push_back(entry.clone());
+            if self.rx_frequency.len() > MAX_ACTIVITY {
+                self.rx_frequency.pop_front();
+            }
+        }
+        self.band_activity.push_back(entry);
+        if self.band_activity.len() > MAX_ACTIVITY {
+            self.band_activity.pop_front();
+        }
+    }
+
+    // same window numbers as the Clear message: 0 band activity, 1 rx frequency, 2 both
+    pub fn clear(&mut self, window: u8) {
+        if window == 0 || window == 2 {
+            self.band_activity.clear();
+        }
+        if window == 1 || window == 2 {
+            self.rx_frequency.clear();
+        }
+    }
+}
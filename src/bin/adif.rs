@@ -0,0 +1,203 @@
+use std::collections::HashMap;
+use std::fs::OpenOptions;
+use std::io::{self, Write};
+use std::path::Path;
+use chrono::{DateTime, Utc};
+use super::*;
+
+pub mod adx;
+pub mod qso;
+pub use adx::*;
+pub use qso::*;
+
+const PROGRAM_ID: &str = "wsjtxrust";
+
+// WSJT-X names for modes that ADIF 3.1 files under MFSK as a submode
+const MFSK_SUBMODES: [&str; 5] = ["FT4", "FST4", "FST4W", "Q65", "JS8"];
+
+// reads the <FIELD:LEN>value records of an ADIF .adi document, field names are uppercased
+pub fn parse_adif_records(text: &str) -> Vec<HashMap<String, String>> {
+    parse_adi_bytes(text.as_bytes())
+}
+
+// either kind of ADIF file, ADX is told apart by its XML declaration or root element
+pub fn parse_adif_document(bytes: &[u8]) -> Vec<HashMap<String, String>> {
+    let start = String::from_utf8_lossy(&bytes[..bytes.len().min(64)]).to_uppercase();
+    let start = start.trim_start_matches('\u{feff}').trim_start();
+    if start.starts_with("<?XML") || start.starts_with("<ADX") {
+        parse_adx_records(&String::from_utf8_lossy(bytes))
+    } else {
+        parse_adi_bytes(bytes)
+    }
+}
+
+// lengths count bytes, so a log that isn't UTF-8 is parsed before its values are decoded
+fn parse_adi_bytes(bytes: &[u8]) -> Vec<HashMap<String, String>> {
+    let mut records = Vec::new();
+    let mut record = HashMap::new();
+    let mut position = find_tag(bytes, 0, "EOH").unwrap_or(0);
+    while let Some(start) = bytes[position..].iter().position(|b| *b == b'<').map(|offset| position + offset) {
+        let end = match bytes[start..].iter().position(|b| *b == b'>') {
+            Some(offset) => start + offset,
+            None => break,
+        };
+        let tag = String::from_utf8_lossy(&bytes[start + 1..end]).to_string();
+        let mut specifier = tag.split(':');
+        let name = specifier.next().unwrap_or("").trim().to_uppercase();
+        let length = specifier.next().and_then(|length| length.trim().parse::<usize>().ok());
+        position = end + 1;
+        match (name.as_str(), length) {
+            ("EOR", _) if !record.is_empty() => {
+                records.push(std::mem::take(&mut record));
+            }
+            (_, Some(length)) => {
+                let value_end = (position + length).min(bytes.len());
+                let value = String::from_utf8_lossy(&bytes[position..value_end]).to_string();
+                record.insert(name, value);
+                position = value_end;
+            }
+            _ => {}
+        }
+    }
+    // LoggedADIF payloads carry a single record that may not be terminated
+    if !record.is_empty() {
+        records.push(record);
+    }
+    records
+}
+
+fn find_tag(bytes: &[u8], from: usize, tag: &str) -> Option<usize> {
+    let needle = format!("<{}>", tag);
+    bytes[from..]
+        .windows(needle.len())
+        .position(|window| window.eq_ignore_ascii_case(needle.as_bytes()))
+        .map(|index| from + index + needle.len())
+}
+
+pub fn adif_field(name: &str, value: &str) -> String {
+    format!("<{}:{}>{}", name, value.len(), value)
+}
+
+pub fn adif_header(now: DateTime<Utc>) -> String {
+    format!("ADIF log written by {} {}\n{} {} {} {} <EOH>\n",
+        PROGRAM_ID, env!("CARGO_PKG_VERSION"),
+        adif_field("ADIF_VER", "3.1.0"),
+        adif_field("PROGRAMID", PROGRAM_ID),
+        adif_field("PROGRAMVERSION", env!("CARGO_PKG_VERSION")),
+        adif_field("CREATED_TIMESTAMP", &now.format("%Y%m%d %H%M%S").to_string()))
+}
+
+// MODE and SUBMODE for a WSJT-X mode name
+pub fn adif_mode(mode: &str) -> (String, Option<String>) {
+    let mode = mode.to_uppercase();
+    if MFSK_SUBMODES.contains(&mode.as_str()) {
+        ("MFSK".to_string(), Some(mode))
+    } else {
+        (mode, None)
+    }
+}
+
+// TX_PWR is a number of watts, WSJT-X passes on whatever was typed, e.g. "100W"
+fn watts(tx_power: &str) -> Option<String> {
+    let number: String = tx_power.trim().chars().take_while(|c| c.is_ascii_digit() || *c == '.').collect();
+    number.parse::<f64>().ok().map(|_| number)
+}
+
+// one QSO from a LogData message as an ADIF record, empty fields are left out
+pub fn logdata_to_adif(logdata: &LogData, iaru_region: u8) -> String {
+    let (mode, submode) = adif_mode(&logdata.mode);
+    let frequency_mhz = format!("{:.6}", logdata.tx_frequency_hz as f64 / 1_000_000.0);
+    let fields = [
+        ("CALL", non_empty(&logdata.dx_call).map(|call| call.to_uppercase())),
+        ("GRIDSQUARE", non_empty(&logdata.dx_grid).map(|grid| grid.to_string())),
+        ("MODE", Some(mode)),
+        ("SUBMODE", submode),
+        ("RST_SENT", non_empty(&logdata.report_sent).map(|report| report.to_string())),
+        ("RST_RCVD", non_empty(&logdata.report_received).map(|report| report.to_string())),
+        ("QSO_DATE", Some(logdata.date_time_on.format("%Y%m%d").to_string())),
+        ("TIME_ON", Some(logdata.date_time_on.format("%H%M%S").to_string())),
+        ("QSO_DATE_OFF", Some(logdata.date_time_off.format("%Y%m%d").to_string())),
+        ("TIME_OFF", Some(logdata.date_time_off.format("%H%M%S").to_string())),
+        ("BAND", band_for_frequency(logdata.tx_frequency_hz, iaru_region).map(|band| band.to_string())),
+        ("FREQ", Some(frequency_mhz).filter(|_| logdata.tx_frequency_hz > 0)),
+        ("STATION_CALLSIGN", non_empty(&logdata.my_call).map(|call| call.to_uppercase())),
+        ("MY_GRIDSQUARE", non_empty(&logdata.my_grid).map(|grid| grid.to_string())),
+        ("TX_PWR", non_empty(&logdata.tx_power).and_then(watts)),
+        ("STX_STRING", non_empty(&logdata.exchange_sent).map(|exchange| exchange.to_string())),
+        ("SRX_STRING", non_empty(&logdata.exchange_received).map(|exchange| exchange.to_string())),
+        ("NAME", non_empty(&logdata.name).map(|name| name.to_string())),
+        ("COMMENT", non_empty(&logdata.comments).map(|comments| comments.to_string())),
+        ("OPERATOR", non_empty(&logdata.operator_call).map(|call| call.to_uppercase())),
+        ("PROP_MODE", non_empty(&logdata.adif_propagation_mode).map(|mode| mode.to_string())),
+    ];
+    let mut record: Vec<String> = fields.iter()
+        .filter_map(|(name, value)| value.as_ref().map(|value| adif_field(name, value)))
+        .collect();
+    record.push("<EOR>".to_string());
+    record.join(" ")
+}
+
+// appends a record, starting the file with a header when it is new or empty
+pub fn append_adif_record(path: &Path, record: &str) -> io::Result<()> {
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+    if file.metadata()?.len() == 0 {
+        write!(file, "{}", adif_header(Utc::now()))?;
+    }
+    writeln!(file, "{}", record)
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+    use super::*;
+
+    #[test]
+    fn logdata_round_trip() {
+        let logdata = LogData {
+            message_type: 5,
+            id: "WSJT-X".to_string(),
+            date_time_on: Utc.with_ymd_and_hms(2024, 3, 9, 7, 5, 3).unwrap(),
+            date_time_off: Utc.with_ymd_and_hms(2024, 3, 9, 7, 6, 0).unwrap(),
+            dx_call: "ea4xyz".to_string(),
+            dx_grid: "IN80".to_string(),
+            tx_frequency_hz: 14_080_000,
+            mode: "FT4".to_string(),
+            report_sent: "-07".to_string(),
+            report_received: "+02".to_string(),
+            tx_power: "100W".to_string(),
+            name: "José".to_string(),
+            my_call: "K1ABC".to_string(),
+            my_grid: "FN42".to_string(),
+            ..Default::default()
+        };
+        let record = logdata_to_adif(&logdata, 2);
+        assert!(record.starts_with("<CALL:6>EA4XYZ <GRIDSQUARE:4>IN80 <MODE:4>MFSK <SUBMODE:3>FT4 "));
+        assert!(record.contains("<QSO_DATE:8>20240309 <TIME_ON:6>070503 <QSO_DATE_OFF:8>20240309 <TIME_OFF:6>070600 "));
+        assert!(record.contains("<FREQ:9>14.080000 "));
+        assert!(record.contains("<NAME:5>José "));
+        assert!(!record.contains("COMMENT"));
+        assert!(record.ends_with("<EOR>"));
+
+        // back through the reader that LoggedADIF and adif_history go through, behind a header as in the log file
+        let text = adif_header(Utc::now()) + &record + "\n";
+        assert!(text.contains("<ADIF_VER:5>3.1.0"));
+        let import = AdifImport::from_text(&text);
+        assert!(import.rejected.is_empty() && import.warnings.is_empty(), "{:?}", import);
+        let qso = &import.qsos[0];
+        assert_eq!(qso.call, "EA4XYZ");
+        assert_eq!((qso.mode.as_str(), qso.mode_name()), ("MFSK", "FT4"));
+        assert_eq!(qso.band.as_deref(), Some("20m"));
+        assert_eq!(qso.freq_mhz, Some(14.08));
+        assert_eq!(qso.time_on().map(|time| Utc.from_utc_datetime(&time)), Some(logdata.date_time_on));
+        assert_eq!(qso.tx_pwr, Some(100.0));
+        assert_eq!(qso.fields["NAME"], "José");
+        assert_eq!(qso.station_callsign.as_deref(), Some("K1ABC"));
+        assert_eq!(qso.fields.len(), 16);
+    }
+
+    #[test]
+    fn modes() {
+        assert_eq!(adif_mode("FT8"), ("FT8".to_string(), None));
+        assert_eq!(adif_mode("q65"), ("MFSK".to_string(), Some("Q65".to_string())));
+    }
+}
@@ -0,0 +1,165 @@
+use std::collections::HashMap;
+
+// reads the <RECORD> elements of an ADX (ADIF XML) document into the same field maps as the .adi reader,
+// APP fields become APP_<PROGRAMID>_<FIELDNAME> and USERDEF fields their FIELDNAME as they are named in .adi
+pub fn parse_adx_records(text: &str) -> Vec<HashMap<String, String>> {
+    // ASCII uppercasing keeps byte offsets the same in both strings
+    let upper = text.to_ascii_uppercase();
+    let mut records = Vec::new();
+    let mut position = 0;
+    while let Some(start) = upper[position..].find("<RECORD>").map(|offset| position + offset + "<RECORD>".len()) {
+        let end = upper[start..].find("</RECORD>").map(|offset| start + offset).unwrap_or(text.len());
+        let record = parse_record(&text[start..end], &upper[start..end]);
+        if !record.is_empty() {
+            records.push(record);
+        }
+        position = end;
+    }
+    records
+}
+
+fn parse_record(text: &str, upper: &str) -> HashMap<String, String> {
+    let mut record = HashMap::new();
+    let mut position = 0;
+    while let Some(start) = upper[position..].find('<').map(|offset| position + offset) {
+        let tag_end = match upper[start..].find('>') {
+            Some(offset) => start + offset,
+            None => break,
+        };
+        position = tag_end + 1;
+        let tag = &text[start + 1..tag_end];
+        // closing tags, comments, processing instructions and empty elements carry no value
+        if tag.starts_with('/') || tag.starts_with('!') || tag.starts_with('?') || tag.ends_with('/') {
+            continue;
+        }
+        let element = tag.split_whitespace().next().unwrap_or("").to_ascii_uppercase();
+        let closing = format!("</{}>", element);
+        let value_end = match upper[position..].find(&closing) {
+            Some(offset) => position + offset,
+            None => continue,
+        };
+        let value = unescape(text[position..value_end].trim());
+        position = value_end + closing.len();
+        let name = match element.as_str() {
+            "APP" => match (attribute(tag, "PROGRAMID"), attribute(tag, "FIELDNAME")) {
+                (Some(program), Some(field)) => format!("APP_{}_{}", program, field).to_uppercase(),
+                _ => continue,
+            },
+            "USERDEF" => match attribute(tag, "FIELDNAME") {
+                Some(field) => field.to_uppercase(),
+                None => continue,
+            },
+            _ => element,
+        };
+        if !name.is_empty() && !value.is_empty() {
+            record.insert(name, value);
+        }
+    }
+    record
+}
+
+// NAME="value" or NAME='value' inside a start tag
+fn attribute(tag: &str, name: &str) -> Option<String> {
+    let upper = tag.to_ascii_uppercase();
+    let mut position = 0;
+    while let Some(found) = upper[position..].find(name).map(|offset| position + offset) {
+        position = found + name.len();
+        let preceded = found == 0 || upper.as_bytes()[found - 1].is_ascii_whitespace();
+        let rest = tag[position..].trim_start();
+        if !preceded || !rest.starts_with('=') {
+            continue;
+        }
+        let rest = rest[1..].trim_start();
+        let quote = rest.chars().next().filter(|quote| *quote == '"' || *quote == '\'')?;
+        let value = &rest[1..];
+        return value.find(quote).map(|end| unescape(&value[..end]));
+    }
+    None
+}
+
+fn unescape(text: &str) -> String {
+    if !text.contains('&') {
+        return text.to_string();
+    }
+    let mut result = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find('&') {
+        result.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let end = match rest.find(';') {
+            Some(end) if end <= 10 => end,
+            _ => {
+                result.push('&');
+                rest = &rest[1..];
+                continue;
+            }
+        };
+        let entity = &rest[1..end];
+        let decoded = match entity {
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "amp" => Some('&'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            _ => entity.strip_prefix("#x").or_else(|| entity.strip_prefix("#X"))
+                .and_then(|hex| u32::from_str_radix(hex, 16).ok())
+                .or_else(|| entity.strip_prefix('#').and_then(|decimal| decimal.parse().ok()))
+                .and_then(char::from_u32),
+        };
+        match decoded {
+            Some(character) => {
+                result.push(character);
+                rest = &rest[end + 1..];
+            }
+            // not an entity we know, kept as it was
+            None => {
+                result.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    result.push_str(rest);
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn records() {
+        let records = parse_adx_records(r#"<?xml version="1.0" encoding="UTF-8"?>
+<ADX>
+  <HEADER><ADIF_VER>3.1.4</ADIF_VER></HEADER>
+  <RECORDS>
+    <RECORD>
+      <call>K1ABC</call>
+      <QSO_DATE>20240101</QSO_DATE>
+      <NAME>Tom &amp; Jerry &#x41;&#66; &nbsp;</NAME>
+      <APP PROGRAMID="wsjtxrust" FIELDNAME="instance" TYPE="S">WSJT-X</APP>
+      <USERDEF FIELDNAME='sweaters'>3</USERDEF>
+      <COMMENT/>
+      <!-- <CALL>N0PE</CALL> -->
+    </RECORD>
+    <RECORD></RECORD>
+    <RECORD><CALL>G4XYZ</CALL></RECORD>
+  </RECORDS>
+</ADX>"#);
+        assert_eq!(records.len(), 2);
+        let first = &records[0];
+        assert_eq!(first["CALL"], "K1ABC");
+        assert_eq!(first["QSO_DATE"], "20240101");
+        assert_eq!(first["NAME"], "Tom & Jerry AB &nbsp;");
+        assert_eq!(first["APP_WSJTXRUST_INSTANCE"], "WSJT-X");
+        assert_eq!(first["SWEATERS"], "3");
+        assert_eq!(first.len(), 5);
+        assert_eq!(records[1]["CALL"], "G4XYZ");
+    }
+
+    #[test]
+    fn unfinished_documents() {
+        assert!(parse_adx_records("").is_empty());
+        assert!(parse_adx_records("<ADX><RECORDS><RECORD><CALL>K1ABC").is_empty());
+        assert_eq!(parse_adx_records("<RECORD><CALL>K1ABC</CALL>")[0]["CALL"], "K1ABC");
+    }
+}
@@ -0,0 +1,285 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+use chrono::{Datelike, NaiveDate, NaiveDateTime, NaiveTime};
+use super::*;
+
+// the BAND enumeration of ADIF 3.1
+const ADIF_BANDS: &[&str] = &[
+    "2190m", "630m", "560m", "160m", "80m", "60m", "40m", "30m", "20m", "17m", "15m", "12m", "10m", "8m", "6m",
+    "5m", "4m", "2m", "1.25m", "70cm", "33cm", "23cm", "13cm", "9cm", "6cm", "3cm", "1.25cm", "6mm", "4mm",
+    "2.5mm", "2mm", "1mm", "submm",
+];
+
+// one QSO of an ADIF log, fields that failed validation are None and noted in the import's warnings
+#[derive(Debug, Clone)]
+pub struct AdifQso {
+    pub call: String,
+    pub qso_date: Option<NaiveDate>,
+    pub time_on: Option<NaiveTime>,
+    pub qso_date_off: Option<NaiveDate>,
+    pub time_off: Option<NaiveTime>,
+    pub band: Option<String>,
+    pub freq_mhz: Option<f64>,
+    pub mode: String,
+    pub submode: Option<String>,
+    pub gridsquare: Option<String>,
+    pub rst_sent: Option<String>,
+    pub rst_rcvd: Option<String>,
+    pub dxcc: Option<u32>,
+    pub country: Option<String>,
+    pub state: Option<String>,
+    pub cq_zone: Option<u8>,
+    pub itu_zone: Option<u8>,
+    pub continent: Option<String>,
+    pub station_callsign: Option<String>,
+    pub operator: Option<String>,
+    pub my_gridsquare: Option<String>,
+    pub tx_pwr: Option<f64>,
+    pub stx_string: Option<String>,
+    pub srx_string: Option<String>,
+    pub prop_mode: Option<String>,
+    pub confirmed: bool,
+    // every field of the record as read, including the ones not broken out above
+    pub fields: HashMap<String, String>,
+}
+
+// the one callsign and grid check, used for ADIF, decodes, spots and the web API
+pub fn valid_callsign(call: &str) -> bool {
+    (3..=13).contains(&call.len())
+        && call.chars().all(|c| c.is_ascii_alphanumeric() || c == '/')
+        && call.chars().any(|c| c.is_ascii_digit())
+        && call.chars().any(|c| c.is_ascii_alphabetic())
+}
+
+// 2, 4, 6 or 8 character Maidenhead locator, ADIF allows no other lengths
+pub fn valid_grid(grid: &str) -> bool {
+    let bytes = grid.as_bytes();
+    if !matches!(bytes.len(), 2 | 4 | 6 | 8) {
+        return false;
+    }
+    bytes.iter().enumerate().all(|(index, b)| match index {
+        0 | 1 => (b'A'..=b'R').contains(&b.to_ascii_uppercase()),
+        4 | 5 => (b'A'..=b'X').contains(&b.to_ascii_uppercase()),
+        _ => b.is_ascii_digit(),
+    })
+}
+
+// ADIF dates start in 1930
+fn parse_date(value: &str) -> Option<NaiveDate> {
+    NaiveDate::parse_from_str(value, "%Y%m%d").ok().filter(|date| date.year() >= 1930)
+}
+
+// HHMM or HHMMSS
+fn parse_time(value: &str) -> Option<NaiveTime> {
+    match value.len() {
+        4 => NaiveTime::parse_from_str(value, "%H%M").ok(),
+        6 => NaiveTime::parse_from_str(value, "%H%M%S").ok(),
+        _ => None,
+    }
+}
+
+// an optional field, a value that doesn't parse is dropped with a warning
+fn validated<T>(record: &HashMap<String, String>, name: &str, call: &str, warnings: &mut Vec<String>, parse: impl Fn(&str) -> Option<T>) -> Option<T> {
+    let value = record.get(name).map(|value| value.trim()).filter(|value| !value.is_empty())?;
+    let parsed = parse(value);
+    if parsed.is_none() {
+        warnings.push(format!("{}: {} '{}' ignored", call, name, value));
+    }
+    parsed
+}
+
+impl AdifQso {
+    // a record without a usable CALL is rejected, anything else invalid is dropped with a warning
+    pub fn from_record(record: &HashMap<String, String>) -> Result<(Self, Vec<String>), String> {
+        let mut warnings = Vec::new();
+        let text = |name: &str| record.get(name).map(|value| value.trim()).filter(|value| !value.is_empty());
+        let call = text("CALL").ok_or("no CALL")?.to_uppercase();
+        if !valid_callsign(&call) {
+            return Err(format!("CALL '{}' is not a callsign", call));
+        }
+        let band = validated(record, "BAND", &call, &mut warnings, |band| Some(band.to_lowercase()).filter(|band| ADIF_BANDS.contains(&band.as_str())));
+        let gridsquare = validated(record, "GRIDSQUARE", &call, &mut warnings, |grid| Some(grid.to_uppercase()).filter(|grid| valid_grid(grid)));
+        let my_gridsquare = validated(record, "MY_GRIDSQUARE", &call, &mut warnings, |grid| Some(grid.to_uppercase()).filter(|grid| valid_grid(grid)));
+        let continent = validated(record, "CONT", &call, &mut warnings, |continent| Some(continent.to_uppercase())
+            .filter(|continent| ["NA", "SA", "EU", "AF", "OC", "AS", "AN"].contains(&continent.as_str())));
+        let qso_date = validated(record, "QSO_DATE", &call, &mut warnings, parse_date);
+        let time_on = validated(record, "TIME_ON", &call, &mut warnings, parse_time);
+        let qso_date_off = validated(record, "QSO_DATE_OFF", &call, &mut warnings, parse_date);
+        let time_off = validated(record, "TIME_OFF", &call, &mut warnings, parse_time);
+        let freq_mhz = validated(record, "FREQ", &call, &mut warnings, |value| value.parse::<f64>().ok().filter(|mhz| *mhz > 0.0));
+        let dxcc = validated(record, "DXCC", &call, &mut warnings, |value| value.parse::<u32>().ok());
+        let cq_zone = validated(record, "CQZ", &call, &mut warnings, |value| value.parse::<u8>().ok().filter(|zone| (1..=40).contains(zone)));
+        let itu_zone = validated(record, "ITUZ", &call, &mut warnings, |value| value.parse::<u8>().ok().filter(|zone| (1..=90).contains(zone)));
+        let tx_pwr = validated(record, "TX_PWR", &call, &mut warnings, |value| value.parse::<f64>().ok().filter(|watts| *watts >= 0.0));
+        let confirmed = ["QSL_RCVD", "LOTW_QSL_RCVD", "EQSL_QSL_RCVD"]
+            .iter()
+            .any(|field| text(field).map(|value| value.eq_ignore_ascii_case("Y")).unwrap_or(false));
+        let qso = Self {
+            qso_date,
+            time_on,
+            qso_date_off,
+            time_off,
+            band,
+            freq_mhz,
+            mode: text("MODE").map(|mode| mode.to_uppercase()).unwrap_or_default(),
+            submode: text("SUBMODE").map(|submode| submode.to_uppercase()),
+            gridsquare,
+            rst_sent: text("RST_SENT").map(|rst| rst.to_string()),
+            rst_rcvd: text("RST_RCVD").map(|rst| rst.to_string()),
+            dxcc,
+            country: text("COUNTRY").map(|country| country.to_string()),
+            state: text("STATE").map(|state| state.to_uppercase()),
+            cq_zone,
+            itu_zone,
+            continent,
+            station_callsign: text("STATION_CALLSIGN").map(|call| call.to_uppercase()),
+            operator: text("OPERATOR").map(|call| call.to_uppercase()),
+            my_gridsquare,
+            tx_pwr,
+            stx_string: text("STX_STRING").map(|exchange| exchange.to_string()),
+            srx_string: text("SRX_STRING").map(|exchange| exchange.to_string()),
+            prop_mode: text("PROP_MODE").map(|mode| mode.to_uppercase()),
+            confirmed,
+            fields: record.clone(),
+            call,
+        };
+        Ok((qso, warnings))
+    }
+
+    // the BAND field, or the band FREQ falls in
+    pub fn band(&self, iaru_region: u8) -> Option<String> {
+        self.band.clone().or_else(|| {
+            let frequency_hz = (self.freq_mhz? * 1_000_000.0).round() as u64;
+            band_for_frequency(frequency_hz, iaru_region).map(|band| band.to_string())
+        })
+    }
+
+    // WSJT-X logs FT4 as MODE MFSK with SUBMODE FT4, the submode is the one that tells modes apart
+    pub fn mode_name(&self) -> &str {
+        self.submode.as_deref().unwrap_or(&self.mode)
+    }
+
+    pub fn time_on(&self) -> Option<NaiveDateTime> {
+        Some(self.qso_date?.and_time(self.time_on.unwrap_or_default()))
+    }
+}
+
+// the QSOs of an ADIF file, with what was wrong with the rest
+#[derive(Debug, Default)]
+pub struct AdifImport {
+    pub qsos: Vec<AdifQso>,
+    pub rejected: Vec<String>,
+    pub warnings: Vec<String>,
+}
+
+impl AdifImport {
+    pub fn from_records(records: Vec<HashMap<String, String>>) -> Self {
+        let mut import = Self::default();
+        for (index, record) in records.iter().enumerate() {
+            match AdifQso::from_record(record) {
+                Ok((qso, warnings)) => {
+                    import.qsos.push(qso);
+                    import.warnings.extend(warnings);
+                }
+                Err(e) => import.rejected.push(format!("record {}: {}", index + 1, e)),
+            }
+        }
+        import
+    }
+
+    pub fn from_text(text: &str) -> Self {
+        Self::from_records(parse_adif_records(text))
+    }
+
+    // .adi or .adx, worked out from the contents
+    pub fn from_file(path: &Path) -> io::Result<Self> {
+        Ok(Self::from_records(parse_adif_document(&fs::read(path)?)))
+    }
+}
+
+impl std::fmt::Display for AdifImport {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{} QSOs", self.qsos.len())?;
+        if !self.rejected.is_empty() {
+            write!(f, ", {} records rejected", self.rejected.len())?;
+        }
+        if !self.warnings.is_empty() {
+            write!(f, ", {} invalid fields ignored", self.warnings.len())?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(fields: &[(&str, &str)]) -> HashMap<String, String> {
+        fields.iter().map(|(name, value)| (name.to_string(), value.to_string())).collect()
+    }
+
+    #[test]
+    fn callsigns() {
+        for (call, valid) in [
+            ("K1ABC", true), ("VK9/K1ABC", true), ("K1ABC/P", true), ("3DA0RU", true), ("F4", false),
+            ("K1ABC-5", false), ("<K1ABC>", false), ("ABCDEF", false), ("123456", false), ("VK9/K1ABC/MM1", true), ("VK9/K1ABCD/MM1", false),
+        ] {
+            assert_eq!(valid_callsign(call), valid, "{}", call);
+        }
+    }
+
+    #[test]
+    fn grids() {
+        for (grid, valid) in [
+            ("FN", true), ("fn42", true), ("FN42ab", true), ("FN42AB12", true), ("RR99XX99", true),
+            ("SN42", false), ("FN4", false), ("FN42A", false), ("FN42YA", false), ("FNA2", false), ("FN42AB1C", false), ("", false),
+        ] {
+            assert_eq!(valid_grid(grid), valid, "{}", grid);
+        }
+    }
+
+    #[test]
+    fn dates_and_times() {
+        for (date, valid) in [("19300101", true), ("20240229", true), ("19291231", false), ("20230229", false), ("2024-01-01", false)] {
+            assert_eq!(parse_date(date).is_some(), valid, "{}", date);
+        }
+        for (time, valid) in [("1234", true), ("123456", true), ("2400", false), ("12345", false), ("1260", false)] {
+            assert_eq!(parse_time(time).is_some(), valid, "{}", time);
+        }
+    }
+
+    #[test]
+    fn invalid_fields_are_dropped() {
+        let (qso, warnings) = AdifQso::from_record(&record(&[
+            ("CALL", "k1abc"), ("QSO_DATE", "19290101"), ("TIME_ON", "1200"), ("BAND", "20M"), ("GRIDSQUARE", "ZZ99"),
+            ("CQZ", "41"), ("ITUZ", "90"), ("CONT", "na"), ("MODE", "MFSK"), ("SUBMODE", "ft4"), ("LOTW_QSL_RCVD", "Y"),
+        ])).unwrap();
+        assert_eq!(qso.call, "K1ABC");
+        assert_eq!((qso.qso_date, qso.time_on), (None, NaiveTime::from_hms_opt(12, 0, 0)));
+        assert_eq!(qso.band.as_deref(), Some("20m"));
+        assert_eq!((qso.cq_zone, qso.itu_zone), (None, Some(90)));
+        assert_eq!((qso.gridsquare.as_deref(), qso.continent.as_deref()), (None, Some("NA")));
+        assert_eq!(qso.mode_name(), "FT4");
+        assert!(qso.confirmed);
+        assert_eq!(warnings, [
+            "K1ABC: GRIDSQUARE 'ZZ99' ignored",
+            "K1ABC: QSO_DATE '19290101' ignored",
+            "K1ABC: CQZ '41' ignored",
+        ]);
+    }
+
+    #[test]
+    fn records_without_a_call_are_rejected() {
+        let import = AdifImport::from_records(vec![
+            record(&[("CALL", "K1ABC"), ("FREQ", "14.074")]),
+            record(&[("GRIDSQUARE", "FN42")]),
+            record(&[("CALL", "NOCALL")]),
+        ]);
+        assert_eq!(import.qsos.len(), 1);
+        assert_eq!(import.qsos[0].band(2).as_deref(), Some("20m"));
+        assert_eq!(import.rejected, ["record 2: no CALL", "record 3: CALL 'NOCALL' is not a callsign"]);
+        assert_eq!(import.to_string(), "1 QSOs, 2 records rejected");
+    }
+}
@@ -0,0 +1,338 @@
+use std::collections::HashMap;
+use std::fs::OpenOptions;
+use std::io::{self, Write};
+use std::path::Path;
+use std::process::Command;
+use std::thread;
+use chrono::{DateTime, Duration, Utc};
+use colored::Color;
+use regex::Regex;
+use serde_derive::Deserialize;
+use super::*;
+
+// an entry of "alerts" in config.json, every criterion that is set must match and lists match any of their values
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct AlertRule {
+    // keys the rule's cooldowns, so it has to be unique, unnamed rules are called "alert 1", "alert 2" and so on
+    pub name: String,
+    // wildcards with * and ?, matched against either call in the message
+    pub callsign: Vec<String>,
+    pub callsign_regex: Option<String>,
+    // DXCC prefix or entity name of the sending station
+    pub dxcc: Vec<String>,
+    // grid prefixes, "FN" for a field or "FN31" for a square
+    pub grid: Vec<String>,
+    pub state: Vec<String>,
+    pub continent: Vec<String>,
+    // only CQs, and with cq_modifier only CQs directed at one of these, e.g. "DX" or "POTA"
+    pub cq: bool,
+    pub cq_modifier: Vec<String>,
+    pub band: Vec<String>,
+    pub min_snr: Option<i32>,
+    // "any" for anything needed, or one of call, grid, dxcc, state, zone, continent
+    pub new: Option<String>,
+    // actions
+    pub bell: bool,
+    // console highlight behind the call, a colored colour name such as "red" or "bright blue"
+    pub highlight: Option<String>,
+    // file a line is appended to, relative to the config file
+    pub log: Option<String>,
+    // run through the shell with WSJTX_RULE, WSJTX_CALL, WSJTX_MESSAGE, WSJTX_SNR and WSJTX_BAND set
+    pub command: Option<String>,
+    // actions for the same rule and station run at most once in this many seconds
+    pub cooldown_seconds: i64,
+}
+
+impl Default for AlertRule {
+    fn default() -> Self {
+        Self {
+            name: String::new(),
+            callsign: Vec::new(),
+            callsign_regex: None,
+            dxcc: Vec::new(),
+            grid: Vec::new(),
+            state: Vec::new(),
+            continent: Vec::new(),
+            cq: false,
+            cq_modifier: Vec::new(),
+            band: Vec::new(),
+            min_snr: None,
+            new: None,
+            bell: false,
+            highlight: None,
+            log: None,
+            command: None,
+            cooldown_seconds: 300,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum NewOne {
+    Any,
+    Category(WorkedCategory),
+}
+
+#[derive(Debug)]
+struct Alert {
+    rule: AlertRule,
+    callsigns: Vec<Regex>,
+    callsign_regex: Option<Regex>,
+    highlight: Option<Color>,
+    new: Option<NewOne>,
+}
+
+// a rule that matched a decode, and the call it matched on
+#[derive(Debug, Clone)]
+pub struct AlertMatch {
+    pub rule: String,
+    pub call: String,
+    pub highlight: Option<Color>,
+}
+
+fn wildcard_regex(pattern: &str) -> Result<Regex, String> {
+    let escaped = regex::escape(&pattern.to_uppercase()).replace(r"\*", ".*").replace(r"\?", ".");
+    Regex::new(&format!("^{}$", escaped)).map_err(|e| e.to_string())
+}
+
+fn any_of(values: &[String], value: Option<&str>, matches: impl Fn(&str, &str) -> bool) -> bool {
+    values.is_empty() || value.map(|value| values.iter().any(|wanted| matches(wanted, value))).unwrap_or(false)
+}
+
+impl Alert {
+    fn compile(rule: AlertRule) -> Result<Self, String> {
+        let callsigns = rule.callsign.iter().map(|pattern| wildcard_regex(pattern)).collect::<Result<_, _>>()?;
+        let callsign_regex = match &rule.callsign_regex {
+            Some(pattern) => Some(Regex::new(&format!("(?i){}", pattern)).map_err(|e| format!("Alert '{}': {}", rule.name, e))?),
+            None => None,
+        };
+        let highlight = match &rule.highlight {
+            Some(name) => Some(name.parse::<Color>().map_err(|_| format!("Alert '{}': unknown colour '{}'", rule.name, name))?),
+            None => None,
+        };
+        let new = match rule.new.as_deref() {
+            None => None,
+            Some("any") => Some(NewOne::Any),
+            Some(name) => Some(NewOne::Category(WorkedCategory::parse(name)
+                .ok_or(format!("Alert '{}': unknown category '{}', use any, call, grid, dxcc, state, zone or continent", rule.name, name))?)),
+        };
+        Ok(Self { rule, callsigns, callsign_regex, highlight, new })
+    }
+
+    // the call this rule matched on, the sender unless a callsign pattern picked the other station
+    fn matches(&self, context: &FilterContext, app_state: &AppState) -> Option<String> {
+        let rule = &self.rule;
+        let from = context.message.from_call()?;
+        let call = if self.callsigns.is_empty() && self.callsign_regex.is_none() {
+            from.to_string()
+        } else {
+            std::iter::once(from).chain(context.message.to_calls()).find(|call| {
+                (self.callsigns.is_empty() || self.callsigns.iter().any(|pattern| pattern.is_match(call)))
+                    && self.callsign_regex.as_ref().map(|regex| regex.is_match(call)).unwrap_or(true)
+            })?.to_string()
+        };
+        let entity = context.needs.as_ref().and_then(|needs| needs.entity.as_ref());
+        let grid = context.message.grid();
+        let dxcc_matches = rule.dxcc.is_empty() || entity.map(|entity| rule.dxcc.iter()
+            .any(|wanted| wanted.eq_ignore_ascii_case(&entity.prefix) || wanted.eq_ignore_ascii_case(&entity.name)))
+            .unwrap_or(false);
+        if !dxcc_matches
+            || !any_of(&rule.grid, grid, |wanted, grid| grid.to_uppercase().starts_with(&wanted.to_uppercase()))
+            || !any_of(&rule.continent, entity.map(|entity| entity.continent.as_str()), |wanted, continent| wanted.eq_ignore_ascii_case(continent))
+            || !any_of(&rule.band, context.decode.band, |wanted, band| wanted.eq_ignore_ascii_case(band))
+            || rule.min_snr.map(|min_snr| context.decode.snr < min_snr).unwrap_or(false)
+        {
+            return None;
+        }
+        if rule.cq || !rule.cq_modifier.is_empty() {
+            let modifier = match &context.message {
+                Ft8Message::Cq { modifier, .. } => modifier.as_deref(),
+                _ => return None,
+            };
+            if !any_of(&rule.cq_modifier, modifier, |wanted, modifier| wanted.eq_ignore_ascii_case(modifier)) {
+                return None;
+            }
+        }
+        let needed = match (self.new, context.needs.as_ref()) {
+            (None, _) => true,
+            (Some(NewOne::Any), Some(needs)) => needs.headline().is_some(),
+            (Some(NewOne::Category(category)), Some(needs)) => needs.is_needed(category),
+            (Some(_), None) => false,
+        };
+        // the reverse geocode is the expensive part, so state goes last
+        if !needed || (!rule.state.is_empty() && !any_of(&rule.state, grid.and_then(|grid| grid_state(grid, app_state)), |wanted, state| wanted.eq_ignore_ascii_case(state))) {
+            return None;
+        }
+        Some(call)
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Alerts {
+    alerts: Vec<Alert>,
+    // the watch list compiled once, for the WSJT-X highlights as well as its own rule
+    watch_list: Vec<Regex>,
+    last_fired: HashMap<(String, String), DateTime<Utc>>,
+}
+
+impl Alerts {
+    // the watch list becomes a rule of its own, ringing the bell with the inverse highlight the console always used
+    pub fn new(rules: &[AlertRule], watch_list: &[String]) -> Result<Self, String> {
+        let watch_list_patterns = watch_list.iter().map(|pattern| wildcard_regex(pattern)).collect::<Result<_, _>>()?;
+        let mut alerts = Vec::new();
+        if !watch_list.is_empty() {
+            alerts.push(Alert::compile(AlertRule {
+                name: "watch list".to_string(),
+                callsign: watch_list.to_vec(),
+                bell: true,
+                highlight: Some("white".to_string()),
+                ..AlertRule::default()
+            })?);
+        }
+        for (index, rule) in rules.iter().enumerate() {
+            let mut rule = rule.clone();
+            if rule.name.trim().is_empty() {
+                rule.name = format!("alert {}", index + 1);
+            }
+            if alerts.iter().any(|alert: &Alert| alert.rule.name == rule.name) {
+                return Err(format!("Two alerts are named '{}', each rule needs a name of its own", rule.name));
+            }
+            alerts.push(Alert::compile(rule)?);
+        }
+        Ok(Self { alerts, watch_list: watch_list_patterns, last_fired: HashMap::new() })
+    }
+
+    // takes the rules of a reloaded set, keeping the cooldowns that are already running
+    pub fn replace_rules(&mut self, reloaded: Alerts) {
+        self.alerts = reloaded.alerts;
+        self.watch_list = reloaded.watch_list;
+    }
+
+    // whether a call is on the watch list, wildcards included
+    pub fn watched(&self, call: &str) -> bool {
+        self.watch_list.iter().any(|pattern| pattern.is_match(&call.to_uppercase()))
+    }
+
+    // every matching rule is returned for highlighting, actions only run once the rule's cooldown has passed
+    pub fn check(&mut self, context: &FilterContext, app_state: &AppState, now: DateTime<Utc>) -> Vec<AlertMatch> {
+        // a cooldown that has run out, or whose rule went in a reload, holds nothing back
+        let alerts = &self.alerts;
+        self.last_fired.retain(|(name, _), fired| {
+            alerts.iter().find(|alert| alert.rule.name == *name)
+                .map(|alert| now - *fired < Duration::seconds(alert.rule.cooldown_seconds))
+                .unwrap_or(false)
+        });
+        let mut matches = Vec::new();
+        for alert in &self.alerts {
+            let call = match alert.matches(context, app_state) {
+                Some(call) => call,
+                None => continue,
+            };
+            let key = (alert.rule.name.clone(), call.clone());
+            let cooled_down = self.last_fired.get(&key)
+                .map(|fired| now - *fired >= Duration::seconds(alert.rule.cooldown_seconds))
+                .unwrap_or(true);
+            if cooled_down {
+                self.last_fired.insert(key, now);
+                run_actions(&alert.rule, &call, context.decode, app_state);
+            }
+            matches.push(AlertMatch { rule: alert.rule.name.clone(), call, highlight: alert.highlight });
+        }
+        matches
+    }
+}
+
+fn alert_line(rule: &AlertRule, call: &str, decode: &Decode) -> String {
+    format!("{} [{}] {} {} {} {} {}", Utc::now().format("%Y-%m-%d %H:%M:%S"), rule.name, call,
+        decode.band.unwrap_or("-"), decode.rf_frequency_hz.map(format_frequency_mhz).unwrap_or_default(), decode.snr, decode.message)
+}
+
+fn append_line(path: &Path, line: &str) -> io::Result<()> {
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+    writeln!(file, "{}", line)
+}
+
+// also used by the called-me alert, name is what WSJTX_RULE is set to
+pub fn run_alert_command(command: &str, name: &str, call: &str, decode: &Decode) -> io::Result<()> {
+    let mut shell = if cfg!(windows) {
+        let mut shell = Command::new("cmd");
+        shell.arg("/C");
+        shell
+    } else {
+        let mut shell = Command::new("sh");
+        shell.arg("-c");
+        shell
+    };
+    let mut child = shell.arg(command)
+        .env("WSJTX_RULE", name)
+        .env("WSJTX_CALL", call)
+        .env("WSJTX_MESSAGE", &decode.message)
+        .env("WSJTX_SNR", decode.snr.to_string())
+        .env("WSJTX_BAND", decode.band.unwrap_or(""))
+        .spawn()?;
+    // reap it without holding up decoding
+    thread::spawn(move || child.wait());
+    Ok(())
+}
+
+fn run_actions(rule: &AlertRule, call: &str, decode: &Decode, app_state: &AppState) {
+    if rule.bell {
+        print!("\x07");
+        let _ = io::stdout().flush();
+    }
+    if let Some(path) = &rule.log {
+        let path = relative_to_config(&app_state.config_path, path);
+        if let Err(e) = append_line(&path, &alert_line(rule, call, decode)) {
+            if !app_state.tui {
+                eprintln!("Could not write alert log {}: {}", path.display(), e);
+            }
+        }
+    }
+    if let Some(command) = &rule.command {
+        if let Err(e) = run_alert_command(command, &rule.name, call, decode) {
+            if !app_state.tui {
+                eprintln!("Could not run alert command for {}: {}", rule.name, e);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rule(name: &str) -> AlertRule {
+        AlertRule { name: name.to_string(), ..AlertRule::default() }
+    }
+
+    #[test]
+    fn rule_names() {
+        let alerts = Alerts::new(&[rule(""), rule("new ones"), rule("")], &["K1ABC".to_string()]).unwrap();
+        let names: Vec<&str> = alerts.alerts.iter().map(|alert| alert.rule.name.as_str()).collect();
+        assert_eq!(names, ["watch list", "alert 1", "new ones", "alert 3"]);
+        let error = Alerts::new(&[rule("dx"), rule("dx")], &[]).unwrap_err();
+        assert_eq!(error, "Two alerts are named 'dx', each rule needs a name of its own");
+        assert!(Alerts::new(&[rule("watch list")], &["K1ABC".to_string()]).is_err());
+    }
+
+    #[test]
+    fn unnamed_rules_cool_down_apart() {
+        let app_state = AppState::for_test(r#"{"alerts": [
+            {"callsign": ["K1ABC"], "log": "first.log", "cooldown_seconds": 300},
+            {"callsign": ["K1*"], "log": "second.log", "cooldown_seconds": 60}
+        ]}"#);
+        let decode = Decode { id: "WSJT-X".to_string(), message: "CQ K1ABC FN42".to_string(), ..Default::default() };
+        let context = FilterContext::new(&decode, &app_state);
+        let start = Utc::now();
+        for seconds in [0, 30, 90] {
+            let matches = app_state.alerts.lock().unwrap().check(&context, &app_state, start + Duration::seconds(seconds));
+            assert_eq!(matches.len(), 2);
+        }
+        // logged next to the config, the first rule once in its 300 s, the second again after its 60 s
+        let directory = app_state.config_path.parent().unwrap();
+        let lines = |name| std::fs::read_to_string(directory.join(name)).unwrap().lines().count();
+        assert_eq!((lines("first.log"), lines("second.log")), (1, 2));
+        assert_eq!(app_state.alerts.lock().unwrap().last_fired.len(), 2);
+    }
+}
@@ -1,22 +1,193 @@
 use std::fs;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex, RwLock};
 use std::io::{self, BufRead};
-use std::path::Path;
+use std::net::UdpSocket;
+use std::path::{Path, PathBuf};
+use chrono::{DateTime, Duration, Utc};
+use reverse_geocoder::ReverseGeocoder;
+use super::*;
 pub struct AppState {
-    pub designated_callsigns: Arc<Vec<String>>,
+    // swapped whole on reload, take a copy of the Arc with designated_callsigns() and config()
+    designated_callsigns: RwLock<Arc<Vec<String>>>,
+    config: RwLock<Arc<Config>>,
+    pub config_path: PathBuf,
+    pub dxcc: DxccTable,
+    pub geocoder: ReverseGeocoder,
+    pub worked_before: Mutex<WorkedBefore>,
+    pub instances: Mutex<Instances>,
+    pub activity: Mutex<ActivityLog>,
+    // the socket WSJT-X talks to us on, replies go back out through it
+    pub socket: UdpSocket,
+    // when the terminal UI owns the screen nothing else may print
+    pub tui: bool,
+    // the last line passed to log(), shown in the terminal UI's status bar
+    pub last_log: Mutex<Option<String>>,
+    pub display_filter: Mutex<Option<Filter>>,
+    pub qso_tracker: Mutex<QsoTracker>,
+    pub web_clients: Mutex<WebClients>,
+    // the last time someone used the console, the TUI or the control API, or worked WSJT-X itself, server start counts
+    pub last_control: Mutex<DateTime<Utc>>,
+    pub alerts: Mutex<Alerts>,
+    pub wsjtx_highlights: Mutex<WsjtxHighlights>,
+    pub callers: Mutex<PendingCallers>,
+    // None when no database is configured
+    pub store: Mutex<Option<Store>>,
+    // while WSJT-X is in a contest mode
+    pub contest: Mutex<Option<Contest>>,
+    pub auto_answer: Mutex<AutoAnswer>,
+    pub hunt: Mutex<Hunt>,
+    pub tx_supervisor: Mutex<TxSupervisor>,
+    pub rotation: Mutex<Rotation>,
+    pub clear_frequency: Mutex<ClearFrequency>,
+    pub hound: Mutex<HoundView>,
+    pub psk_reporter: Mutex<PskReporter>,
+    pub wspr_uploads: Mutex<WsprUploads>,
+    pub cluster: Mutex<Cluster>,
 }
 
+// one callsign per line, blank lines are skipped
+pub fn read_watch_list(path: &Path) -> io::Result<Vec<String>> {
+    let file = fs::File::open(path)?;
+    Ok(io::BufReader::new(file)
+        .lines()
+        .map_while(Result::ok)
+        .map(|line| line.trim().to_string())
+        .filter(|line| !line.is_empty())
+        .collect())
+}
+
+impl AppState {
+    pub fn new(socket: UdpSocket, config_path: &Path) -> io::Result<Self> {
+        let config = Config::load(config_path)?;
+        let dxcc = DxccTable::load(config.cty_file.as_deref())?;
+        let mut worked_before = WorkedBefore::new();
+        if let Some(adif_history) = &config.adif_history {
+            let import = AdifImport::from_file(Path::new(adif_history))?;
+            worked_before.add_adif(&import, &dxcc, config.iaru_region);
+            println!("Imported {} from {}", import, adif_history);
+            // a few examples are enough to find what's wrong with a log
+            for problem in import.rejected.iter().chain(&import.warnings).take(5) {
+                println!("    {}", problem);
+            }
+        }
+        let display_filter = match &config.display_filter {
+            Some(text) => Some(Filter::parse(text).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?),
+            None => None,
+        };
+        let designated_callsigns = read_watch_list(&config.watch_list_path(config_path))?;
+        let alerts = Alerts::new(&config.alerts, &designated_callsigns)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        config.wsjtx_highlights.validate().map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        config.auto_answer.categories().map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        config.tx_supervisor.validate().map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        config.rotation.validate().map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        config.psk_reporter.validate().map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        config.wspr.validate().map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        let store = match config.database_path(config_path) {
+            Some(path) => Some(Store::open(&path)
+                .map_err(|e| io::Error::other(format!("{}: {}", path.display(), e)))?),
+            None => None,
+        };
+        let hunt = Hunt::new(&config.hunt);
+        Ok(Self {
+            designated_callsigns: RwLock::new(Arc::new(designated_callsigns)),
+            config: RwLock::new(Arc::new(config)),
+            config_path: config_path.to_path_buf(),
+            dxcc,
+            geocoder: ReverseGeocoder::new(),
+            worked_before: Mutex::new(worked_before),
+            instances: Mutex::new(Instances::new()),
+            activity: Mutex::new(ActivityLog::new()),
+            socket,
+            tui: false,
+            last_log: Mutex::new(None),
+            display_filter: Mutex::new(display_filter),
+            qso_tracker: Mutex::new(QsoTracker::new()),
+            web_clients: Mutex::new(WebClients::new()),
+            last_control: Mutex::new(Utc::now()),
+            alerts: Mutex::new(alerts),
+            wsjtx_highlights: Mutex::new(WsjtxHighlights::new()),
+            callers: Mutex::new(PendingCallers::new()),
+            store: Mutex::new(store),
+            contest: Mutex::new(None),
+            auto_answer: Mutex::new(AutoAnswer::new()),
+            hunt: Mutex::new(hunt),
+            tx_supervisor: Mutex::new(TxSupervisor::new()),
+            rotation: Mutex::new(Rotation::new()),
+            clear_frequency: Mutex::new(ClearFrequency::new()),
+            hound: Mutex::new(HoundView::new()),
+            psk_reporter: Mutex::new(PskReporter::new()),
+            wspr_uploads: Mutex::new(WsprUploads::new()),
+            cluster: Mutex::new(Cluster::new()),
+        })
+    }
+
+    pub fn config(&self) -> Arc<Config> {
+        Arc::clone(&self.config.read().unwrap())
+    }
+
+    pub fn designated_callsigns(&self) -> Arc<Vec<String>> {
+        Arc::clone(&self.designated_callsigns.read().unwrap())
+    }
+
+    pub fn watch_list_path(&self) -> PathBuf {
+        self.config().watch_list_path(&self.config_path)
+    }
+
+    // swaps in a reloaded config and watch list, together with the alerts and filter built from them
+    pub fn replace_config(&self, config: Config, designated_callsigns: Vec<String>, alerts: Alerts, display_filter: Option<Filter>) {
+        let filter_changed = config.display_filter != self.config().display_filter;
+        self.alerts.lock().unwrap().replace_rules(alerts);
+        if filter_changed {
+            *self.display_filter.lock().unwrap() = display_filter;
+        }
+        *self.designated_callsigns.write().unwrap() = Arc::new(designated_callsigns);
+        *self.config.write().unwrap() = Arc::new(config);
+    }
+
+    // runs a database write or query when there is a database, failures are logged
+    pub fn with_store<T>(&self, action: impl FnOnce(&mut Store) -> rusqlite::Result<T>) -> Option<T> {
+        let mut store = self.store.lock().unwrap();
+        match action(store.as_mut()?) {
+            Ok(value) => Some(value),
+            Err(e) => {
+                drop(store);
+                self.log(&format!("Database error: {}", e));
+                None
+            }
+        }
+    }
+
+    pub fn control_activity(&self) {
+        *self.last_control.lock().unwrap() = Utc::now();
+    }
+
+    // how long nobody has been at the controls, an open dashboard only counts once it is used
+    pub fn out_of_contact(&self, now: DateTime<Utc>) -> Duration {
+        now - *self.last_control.lock().unwrap()
+    }
+
+    // server events for the operator, printed with a timestamp unless the terminal UI owns the screen
+    pub fn log(&self, text: &str) {
+        let line = format!("{} {}", Utc::now().format("%H:%M:%S"), text);
+        if !self.tui {
+            println!("{}", line);
+        }
+        *self.last_log.lock().unwrap() = Some(line);
+    }
+}
+
+#[cfg(test)]
 impl AppState {
-    pub fn new() -> io::Result<Self> {
-        let callsigns_path = Path::new("callsigns.txt");
-        let callsigns_file = fs::File::open(&callsigns_path)?;
-        let callsigns_reader = io::BufReader::new(callsigns_file);
-        let designated_callsigns = Arc::new(
-            callsigns_reader
-                .lines()
-                .filter_map(io::Result::ok)
-                .collect()
-        );
-        Ok(Self { designated_callsigns })
-    }
-}
\ No newline at end of file
+    // a state on a free port with the config given, in a directory of its own with an empty watch list
+    pub fn for_test(config: &str) -> Self {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        static COUNT: AtomicUsize = AtomicUsize::new(0);
+        let directory = std::env::temp_dir().join(format!("wsjtx-test-{}-{}", std::process::id(), COUNT.fetch_add(1, Ordering::SeqCst)));
+        fs::create_dir_all(&directory).unwrap();
+        fs::write(directory.join("config.json"), config).unwrap();
+        fs::write(directory.join("callsigns.txt"), "").unwrap();
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        AppState::new(socket, &directory.join("config.json")).unwrap()
+    }
+}
@@ -0,0 +1,303 @@
+use std::collections::HashMap;
+use chrono::{DateTime, Duration, Utc};
+use serde_derive::Deserialize;
+use super::*;
+
+// a station we answered isn't answered again for this long, whether or not the QSO happened
+const ANSWERED_HOLDOFF_MINUTES: i64 = 10;
+// CQ modifiers that name an activity rather than who may answer
+const ACTIVITY_MODIFIERS: &[&str] = &["TEST", "POTA", "SOTA", "WWFF", "IOTA", "BOTA", "QRP", "FD", "RU", "WW", "NCCC", "MM", "SK"];
+const CONTINENTS: &[&str] = &["NA", "SA", "EU", "AF", "AS", "OC", "AN"];
+
+// "auto_answer" in config.json, off unless enabled here or with the "auto on" console command
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct AutoAnswerConfig {
+    pub enabled: bool,
+    pub min_snr: i32,
+    pub max_distance_km: Option<f64>,
+    // the needs worth answering a CQ for, any of call, grid, dxcc, state, zone or continent
+    pub needed: Vec<String>,
+}
+
+impl Default for AutoAnswerConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            min_snr: -20,
+            max_distance_km: None,
+            needed: ["dxcc", "continent", "zone", "state", "grid"].iter().map(|name| name.to_string()).collect(),
+        }
+    }
+}
+
+impl AutoAnswerConfig {
+    pub fn categories(&self) -> Result<Vec<WorkedCategory>, String> {
+        self.needed.iter()
+            .map(|name| WorkedCategory::parse(name)
+                .ok_or(format!("auto_answer: unknown category '{}', use call, grid, dxcc, state, zone or continent", name)))
+            .collect()
+    }
+}
+
+// how much each need is worth, a new one on this band and mode only counts half
+fn need_points(category: WorkedCategory) -> i32 {
+    match category {
+        WorkedCategory::Dxcc => 100,
+        WorkedCategory::Continent => 80,
+        WorkedCategory::CqZone => 60,
+        WorkedCategory::State => 40,
+        WorkedCategory::Grid => 20,
+        WorkedCategory::Call => 10,
+    }
+}
+
+// whether a CQ with this modifier is open to us, "CQ DX" wants other continents, "CQ EU" only that continent,
+// "CQ JA" only that prefix and "CQ 145" is a call back on another frequency
+pub fn directed_cq_eligible(modifier: Option<&str>, own_call: &str, own_continent: Option<&str>, dx_continent: Option<&str>) -> bool {
+    let modifier = match modifier {
+        Some(modifier) => modifier.to_uppercase(),
+        None => return true,
+    };
+    if modifier == "DX" {
+        return matches!((own_continent, dx_continent), (Some(own), Some(dx)) if own != dx);
+    }
+    if CONTINENTS.contains(&modifier.as_str()) {
+        return own_continent == Some(modifier.as_str());
+    }
+    if modifier.chars().all(|c| c.is_ascii_digit()) {
+        return false;
+    }
+    ACTIVITY_MODIFIERS.contains(&modifier.as_str()) || own_call.starts_with(&modifier)
+}
+
+#[derive(Debug, Clone)]
+pub struct CqCandidate {
+    pub call: String,
+    pub decode: Decode,
+    pub score: i32,
+    pub reason: String,
+}
+
+impl std::fmt::Display for CqCandidate {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{:<10} {:>3} dB  score {:>3}  {}", self.call, self.decode.snr, self.score, self.reason)
+    }
+}
+
+// the CQs heard this period, answered with the best one once WSJT-X has finished decoding
+#[derive(Debug, Default)]
+pub struct AutoAnswer {
+    // set by the console, otherwise the config decides
+    pub enabled_override: Option<bool>,
+    candidates: Vec<CqCandidate>,
+    answered: HashMap<String, DateTime<Utc>>,
+    pub last_answer: Option<CqCandidate>,
+}
+
+impl AutoAnswer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn enabled(&self, config: &AutoAnswerConfig) -> bool {
+        self.enabled_override.unwrap_or(config.enabled)
+    }
+
+    fn recently_answered(&self, call: &str, now: DateTime<Utc>) -> bool {
+        self.answered.get(call).map(|answered| now - *answered < Duration::minutes(ANSWERED_HOLDOFF_MINUTES)).unwrap_or(false)
+    }
+
+    // the best CQ of the period just decoded, the rest are dropped as they will have moved on
+    fn take_best(&mut self, id: &str) -> Option<CqCandidate> {
+        let latest = self.candidates.iter().filter(|candidate| candidate.decode.id == id).map(|candidate| candidate.decode.time).max()?;
+        let best = self.candidates.iter()
+            .filter(|candidate| candidate.decode.id == id && candidate.decode.time == latest)
+            .max_by_key(|candidate| candidate.score)
+            .cloned();
+        self.candidates.retain(|candidate| candidate.decode.id != id);
+        best
+    }
+}
+
+impl std::fmt::Display for AutoAnswer {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        if let Some(answer) = &self.last_answer {
+            writeln!(f, "Last answered: {}", answer)?;
+        }
+        for candidate in &self.candidates {
+            writeln!(f, "Candidate: {}", candidate)?;
+        }
+        Ok(())
+    }
+}
+
+// scores a CQ for auto-answer, None when it isn't one we would answer
+fn score_cq(context: &FilterContext, config: &AutoAnswerConfig, categories: &[WorkedCategory], app_state: &AppState) -> Option<(i32, String)> {
+    let modifier = match &context.message {
+        Ft8Message::Cq { modifier, .. } => modifier.as_deref(),
+        _ => return None,
+    };
+    let decode = context.decode;
+    if decode.snr < config.min_snr {
+        return None;
+    }
+    let distance = context.distance_km();
+    if let (Some(max), Some(distance)) = (config.max_distance_km, distance) {
+        if distance > max {
+            return None;
+        }
+    }
+    let needs = context.needs.as_ref()?;
+    let own_call = context.own_call.as_deref()?;
+    let own_continent = app_state.dxcc.lookup(own_call).map(|entity| entity.continent.as_str());
+    let dx_continent = needs.entity.as_ref().map(|entity| entity.continent.as_str());
+    if !directed_cq_eligible(modifier, own_call, own_continent, dx_continent) {
+        return None;
+    }
+    let (points, reasons): (Vec<i32>, Vec<String>) = categories.iter()
+        .filter_map(|category| match needs.status(*category)? {
+            NeedStatus::New => Some((need_points(*category), format!("new {}", category))),
+            NeedStatus::NewBandMode => Some((need_points(*category) / 2, format!("{} band/mode", category))),
+            _ => None,
+        })
+        .unzip();
+    if points.is_empty() {
+        return None;
+    }
+    // stronger and further away both help, neither outweighs a better need
+    let snr_points = (decode.snr + 24).clamp(0, 30);
+    let distance_points = distance.map(|km| (km / 500.0) as i32).unwrap_or(0).min(30);
+    Some((points.iter().sum::<i32>() + snr_points + distance_points, reasons.join(", ")))
+}
+
+// called for every decode, keeps the CQs worth answering until the period has been decoded
+pub fn consider_cq(context: &FilterContext, app_state: &AppState) {
+    let config = app_state.config();
+    let decode = context.decode;
+    if !decode.new || decode.off_air || decode.low_confidence {
+        return;
+    }
+    let call = match &context.message {
+        Ft8Message::Cq { call, .. } => call,
+        _ => return,
+    };
+    let mut auto_answer = app_state.auto_answer.lock().unwrap();
+    if !auto_answer.enabled(&config.auto_answer) || auto_answer.recently_answered(call, Utc::now()) {
+        return;
+    }
+    // the config was checked when it was loaded
+    let categories = config.auto_answer.categories().unwrap_or_default();
+    if let Some((score, reason)) = score_cq(context, &config.auto_answer, &categories, app_state) {
+        auto_answer.candidates.push(CqCandidate { call: call.clone(), decode: decode.clone(), score, reason });
+    }
+}
+
+// our own QSO, our own CQ or a call to a hunted station is still going
+fn qso_in_progress(status: &Status, app_state: &AppState) -> bool {
+    status.transmitting
+        || status.tx_enabled
+        || app_state.hunt.lock().unwrap().attempt.is_some()
+        || app_state.qso_tracker.lock().unwrap().own_qso_in_progress(&status.id, Utc::now())
+}
+
+// once a period has been decoded, answers the best CQ with a Reply that repeats its decode
+pub fn answer_best_cq(status: &Status, app_state: &AppState) {
+    if status.decoding {
+        return;
+    }
+    let best = app_state.auto_answer.lock().unwrap().take_best(&status.id);
+    let best = match best {
+        Some(best) => best,
+        None => return,
+    };
+    if qso_in_progress(status, app_state) {
+        return;
+    }
+    let reply = Reply::from_decode(&best.decode, 0);
+    match send_to_instance(app_state, &best.decode.id, encode_reply(&reply)) {
+        Ok(()) => {
+            app_state.log(&format!("Auto-answering {}: {}", best.decode.message, best));
+            let mut auto_answer = app_state.auto_answer.lock().unwrap();
+            auto_answer.answered.insert(best.call.clone(), Utc::now());
+            auto_answer.answered.retain(|_, answered| Utc::now() - *answered < Duration::minutes(ANSWERED_HOLDOFF_MINUTES));
+            auto_answer.last_answer = Some(best);
+        }
+        Err(e) => app_state.log(&format!("Auto-answer reply failed: {}", e)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::NaiveTime;
+    use super::*;
+
+    #[test]
+    fn directed_cqs() {
+        // we are K1ABC in North America, they are in Europe
+        for (modifier, eligible) in [
+            (None, true), (Some("DX"), true), (Some("NA"), true), (Some("eu"), false), (Some("K"), true),
+            (Some("K1"), true), (Some("JA"), false), (Some("POTA"), true), (Some("TEST"), true), (Some("145"), false),
+        ] {
+            assert_eq!(directed_cq_eligible(modifier, "K1ABC", Some("NA"), Some("EU")), eligible, "{:?}", modifier);
+        }
+        assert!(!directed_cq_eligible(Some("DX"), "K1ABC", Some("NA"), Some("NA")));
+        assert!(!directed_cq_eligible(Some("DX"), "K1ABC", Some("NA"), None));
+    }
+
+    // a 20m FT8 CQ decoded by one instance at 12:00:<second>, heard by K1ABC in FN42
+    fn hear(app_state: &AppState, id: &str, second: u32, snr: i32, message: &str) {
+        let decode = Decode {
+            id: id.to_string(),
+            new: true,
+            time: NaiveTime::from_hms_opt(12, 0, second).unwrap(),
+            snr,
+            mode: "~".to_string(),
+            message: message.to_string(),
+            band: Some("20m"),
+            ..Default::default()
+        };
+        let mut context = FilterContext::new(&decode, app_state);
+        context.own_call = Some("K1ABC".to_string());
+        context.own_grid = Some("FN42".to_string());
+        consider_cq(&context, app_state);
+    }
+
+    #[test]
+    fn best_cq_of_the_period() {
+        let app_state = AppState::for_test(r#"{"auto_answer": {"enabled": true}}"#);
+        let worked = WorkedQso {
+            call: "G4ABC".to_string(),
+            grid: Some("IO91".to_string()),
+            band: Some("20m".to_string()),
+            mode: "FT8".to_string(),
+            state: None,
+            confirmed: false,
+        };
+        app_state.worked_before.lock().unwrap().add_qso(&worked, &app_state.dxcc);
+        // the period before, a better CQ that will have moved on
+        hear(&app_state, "WSJT-X", 0, 0, "CQ VK2ABC QF56");
+        // England worked, only a new grid and a strong signal
+        hear(&app_state, "WSJT-X", 15, 10, "CQ G4XYZ IO80");
+        // a new DXCC, weaker
+        hear(&app_state, "WSJT-X", 15, -15, "CQ DX DL1ABC JO62");
+        // not for us, too weak, or nothing needed
+        hear(&app_state, "WSJT-X", 15, 0, "CQ EU F5ABC JN18");
+        hear(&app_state, "WSJT-X", 15, -22, "CQ JA1XYZ PM95");
+        hear(&app_state, "WSJT-X", 15, 5, "CQ G4DEF IO91");
+        // another instance's CQs are its own
+        hear(&app_state, "WSJT-X - 2", 15, 0, "CQ VK3ABC QF22");
+        let mut auto_answer = app_state.auto_answer.lock().unwrap();
+        let calls: Vec<&str> = auto_answer.candidates.iter().map(|candidate| candidate.call.as_str()).collect();
+        assert_eq!(calls, ["VK2ABC", "G4XYZ", "DL1ABC", "VK3ABC"]);
+        let best = auto_answer.take_best("WSJT-X").unwrap();
+        assert_eq!((best.call.as_str(), best.reason.as_str()), ("DL1ABC", "new DXCC, new GRID"));
+        assert!(auto_answer.take_best("WSJT-X").is_none());
+        assert_eq!(auto_answer.take_best("WSJT-X - 2").unwrap().call, "VK3ABC");
+        // answered a few minutes ago, not again
+        auto_answer.answered.insert("DL1ABC".to_string(), Utc::now() - Duration::minutes(3));
+        drop(auto_answer);
+        hear(&app_state, "WSJT-X", 30, -15, "CQ DX DL1ABC JO62");
+        assert!(app_state.auto_answer.lock().unwrap().candidates.is_empty());
+    }
+}
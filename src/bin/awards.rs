@@ -0,0 +1,130 @@
+use serde_derive::Serialize;
+use super::*;
+
+// an award and the worked-before category it counts
+struct Award {
+    name: &'static str,
+    category: WorkedCategory,
+    // how many it takes for the award, None for grid chasing outside VUCC
+    total: Option<usize>,
+    counts: fn(&str) -> bool,
+}
+
+const AWARDS: &[Award] = &[
+    Award { name: "DXCC", category: WorkedCategory::Dxcc, total: Some(100), counts: |_| true },
+    Award { name: "WAS", category: WorkedCategory::State, total: Some(50), counts: is_us_state_code },
+    Award { name: "WAZ", category: WorkedCategory::CqZone, total: Some(40), counts: |_| true },
+    // Antarctica doesn't count for WAC
+    Award { name: "WAC", category: WorkedCategory::Continent, total: Some(6), counts: |continent| continent != "AN" },
+    Award { name: "Grids", category: WorkedCategory::Grid, total: None, counts: |_| true },
+];
+
+// VUCC grids needed on each band it is issued for
+fn vucc_total(band: &str) -> Option<usize> {
+    match band {
+        "6m" | "2m" => Some(100),
+        "1.25m" | "70cm" => Some(50),
+        "33cm" | "23cm" => Some(25),
+        _ => None,
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct AwardProgress {
+    pub award: &'static str,
+    // None for all bands or all modes
+    pub band: Option<String>,
+    pub mode: Option<String>,
+    pub worked: usize,
+    pub confirmed: usize,
+    pub total: Option<usize>,
+}
+
+impl std::fmt::Display for AwardProgress {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let progress = format!("{}/{}", self.worked, self.confirmed);
+        match self.total {
+            Some(total) => write!(f, "{:>9} of {:<4}", progress, total),
+            None => write!(f, "{:>9}        ", progress),
+        }
+    }
+}
+
+// every award on one band and mode, or across all of them
+pub fn award_progress(worked_before: &WorkedBefore, band: Option<&str>, mode: Option<&str>) -> Vec<AwardProgress> {
+    AWARDS.iter().map(|award| {
+        let (worked, confirmed) = worked_before.progress(award.category, band, mode, award.counts);
+        // on a VHF band the grids are VUCC
+        let (name, total) = match band.and_then(vucc_total) {
+            Some(total) if award.category == WorkedCategory::Grid => ("VUCC", Some(total)),
+            _ => (award.name, award.total),
+        };
+        AwardProgress { award: name, band: band.map(|band| band.to_string()), mode: mode.map(|mode| mode.to_string()), worked, confirmed, total }
+    }).collect()
+}
+
+// all bands, then each band worked, for one mode or all of them
+pub fn all_award_progress(worked_before: &WorkedBefore, mode: Option<&str>) -> Vec<AwardProgress> {
+    let (bands, _) = worked_before.slots();
+    let mut progress = award_progress(worked_before, None, mode);
+    for band in &bands {
+        progress.extend(award_progress(worked_before, Some(band), mode));
+    }
+    progress
+}
+
+pub fn awards_report(worked_before: &WorkedBefore, mode: Option<&str>) -> String {
+    let mut lines = vec![
+        format!("Awards for {}, worked/confirmed", mode.unwrap_or("all modes")),
+        format!("{:<6} {}", "Band", AWARDS.iter().map(|award| format!("{:<18}", award.name)).collect::<String>()).trim_end().to_string(),
+    ];
+    let progress = all_award_progress(worked_before, mode);
+    for row in progress.chunks(AWARDS.len()) {
+        let band = row[0].band.as_deref().unwrap_or("All");
+        let cells: String = row.iter().map(|award| match award.award {
+            "VUCC" => format!("{} VUCC", award),
+            _ => award.to_string(),
+        }).collect::<Vec<_>>().join(" ");
+        lines.push(format!("{:<6} {}", band, cells).trim_end().to_string());
+    }
+    lines.join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn grids(progress: &[AwardProgress]) -> (&str, usize, usize, Option<usize>) {
+        let grids = progress.iter().find(|award| award.award == "VUCC" || award.award == "Grids").unwrap();
+        (grids.award, grids.worked, grids.confirmed, grids.total)
+    }
+
+    #[test]
+    fn vucc_counts_its_own_band() {
+        let dxcc = DxccTable::builtin();
+        let mut worked_before = WorkedBefore::new();
+        for (grid, band, confirmed) in [("FN42", "20m", true), ("FN43", "20m", true), ("FN31", "6m", false), ("FN20", "2m", true)] {
+            let qso = WorkedQso {
+                call: "W1AW".to_string(),
+                grid: Some(grid.to_string()),
+                band: Some(band.to_string()),
+                mode: "FT8".to_string(),
+                state: None,
+                confirmed,
+            };
+            worked_before.add_qso(&qso, &dxcc);
+        }
+        // HF grids count towards grid chasing but never VUCC
+        assert_eq!(grids(&award_progress(&worked_before, Some("6m"), None)), ("VUCC", 1, 0, Some(100)));
+        assert_eq!(grids(&award_progress(&worked_before, Some("2m"), Some("FT8"))), ("VUCC", 1, 1, Some(100)));
+        assert_eq!(grids(&award_progress(&worked_before, Some("70cm"), None)), ("VUCC", 0, 0, Some(50)));
+        assert_eq!(grids(&award_progress(&worked_before, Some("20m"), None)), ("Grids", 2, 2, None));
+        assert_eq!(grids(&award_progress(&worked_before, None, None)), ("Grids", 4, 3, None));
+        let progress = all_award_progress(&worked_before, None);
+        let vucc: Vec<&str> = progress.iter()
+            .filter(|award| award.award == "VUCC")
+            .filter_map(|award| award.band.as_deref())
+            .collect();
+        assert_eq!(vucc, ["6m", "2m"]);
+    }
+}
@@ -0,0 +1,153 @@
+// amateur band edges in Hz for each IARU region, 160m through 70cm
+struct BandEdge {
+    name: &'static str,
+    low_hz: u64,
+    high_hz: u64,
+}
+
+const fn edge(name: &'static str, low_hz: u64, high_hz: u64) -> BandEdge {
+    BandEdge { name, low_hz, high_hz }
+}
+
+const REGION_1: &[BandEdge] = &[
+    edge("160m", 1_810_000, 2_000_000),
+    edge("80m", 3_500_000, 3_800_000),
+    edge("60m", 5_351_500, 5_366_500),
+    edge("40m", 7_000_000, 7_200_000),
+    edge("30m", 10_100_000, 10_150_000),
+    edge("20m", 14_000_000, 14_350_000),
+    edge("17m", 18_068_000, 18_168_000),
+    edge("15m", 21_000_000, 21_450_000),
+    edge("12m", 24_890_000, 24_990_000),
+    edge("10m", 28_000_000, 29_700_000),
+    edge("6m", 50_000_000, 54_000_000),
+    edge("4m", 70_000_000, 70_500_000),
+    edge("2m", 144_000_000, 146_000_000),
+    edge("70cm", 430_000_000, 440_000_000),
+];
+
+const REGION_2: &[BandEdge] = &[
+    edge("160m", 1_800_000, 2_000_000),
+    edge("80m", 3_500_000, 4_000_000),
+    edge("60m", 5_330_500, 5_406_400),
+    edge("40m", 7_000_000, 7_300_000),
+    edge("30m", 10_100_000, 10_150_000),
+    edge("20m", 14_000_000, 14_350_000),
+    edge("17m", 18_068_000, 18_168_000),
+    edge("15m", 21_000_000, 21_450_000),
+    edge("12m", 24_890_000, 24_990_000),
+    edge("10m", 28_000_000, 29_700_000),
+    edge("6m", 50_000_000, 54_000_000),
+    edge("2m", 144_000_000, 148_000_000),
+    edge("1.25m", 222_000_000, 225_000_000),
+    edge("70cm", 420_000_000, 450_000_000),
+];
+
+const REGION_3: &[BandEdge] = &[
+    edge("160m", 1_800_000, 2_000_000),
+    edge("80m", 3_500_000, 3_900_000),
+    edge("60m", 5_351_500, 5_366_500),
+    edge("40m", 7_000_000, 7_300_000),
+    edge("30m", 10_100_000, 10_150_000),
+    edge("20m", 14_000_000, 14_350_000),
+    edge("17m", 18_068_000, 18_168_000),
+    edge("15m", 21_000_000, 21_450_000),
+    edge("12m", 24_890_000, 24_990_000),
+    edge("10m", 28_000_000, 29_700_000),
+    edge("6m", 50_000_000, 54_000_000),
+    edge("2m", 144_000_000, 148_000_000),
+    edge("70cm", 430_000_000, 440_000_000),
+];
+
+fn band_plan(region: u8) -> &'static [BandEdge] {
+    match region {
+        1 => REGION_1,
+        3 => REGION_3,
+        _ => REGION_2,
+    }
+}
+
+pub fn band_for_frequency(frequency_hz: u64, region: u8) -> Option<&'static str> {
+    band_plan(region)
+        .iter()
+        .find(|band| frequency_hz >= band.low_hz && frequency_hz <= band.high_hz)
+        .map(|band| band.name)
+}
+
+pub fn format_frequency_mhz(frequency_hz: u64) -> String {
+    format!("{:.6}", frequency_hz as f64 / 1_000_000.0)
+}
+
+// "20m", "70cm" or "1.25m" as metres, for putting bands in order, 0 for anything else
+pub fn band_wavelength_m(band: &str) -> f64 {
+    let (number, scale) = if let Some(number) = band.strip_suffix("mm") {
+        (number, 0.001)
+    } else if let Some(number) = band.strip_suffix("cm") {
+        (number, 0.01)
+    } else if let Some(number) = band.strip_suffix('m') {
+        (number, 1.0)
+    } else {
+        (band, 0.0)
+    };
+    number.parse::<f64>().map(|number| number * scale).unwrap_or(0.0)
+}
+
+// roughly how much audio a signal takes up above its offset, by mode name
+pub fn signal_width_hz(mode: &str) -> u64 {
+    match mode {
+        "FT4" => 90,
+        "JT65" => 180,
+        "Q65" | "JT9" | "FST4" => 100,
+        "MSK144" => 2_400,
+        _ => 50,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn band_edges() {
+        // frequency, then the band in regions 1, 2 and 3
+        for (frequency_hz, bands) in [
+            (1_805_000, [None, Some("160m"), Some("160m")]),
+            (1_810_000, [Some("160m"), Some("160m"), Some("160m")]),
+            (2_000_001, [None, None, None]),
+            (3_500_000, [Some("80m"), Some("80m"), Some("80m")]),
+            (3_499_999, [None, None, None]),
+            (3_800_000, [Some("80m"), Some("80m"), Some("80m")]),
+            (3_850_000, [None, Some("80m"), Some("80m")]),
+            (3_950_000, [None, Some("80m"), None]),
+            (4_000_000, [None, Some("80m"), None]),
+            // the US channels, and the WRC-15 band the rest of the world has
+            (5_330_500, [None, Some("60m"), None]),
+            (5_357_000, [Some("60m"), Some("60m"), Some("60m")]),
+            (5_366_500, [Some("60m"), Some("60m"), Some("60m")]),
+            (5_403_500, [None, Some("60m"), None]),
+            (5_406_401, [None, None, None]),
+            (7_074_000, [Some("40m"), Some("40m"), Some("40m")]),
+            (7_200_000, [Some("40m"), Some("40m"), Some("40m")]),
+            (7_250_000, [None, Some("40m"), Some("40m")]),
+            (7_300_001, [None, None, None]),
+            (14_000_000, [Some("20m"), Some("20m"), Some("20m")]),
+            (13_999_999, [None, None, None]),
+            (14_350_000, [Some("20m"), Some("20m"), Some("20m")]),
+            (14_350_001, [None, None, None]),
+            (29_700_000, [Some("10m"), Some("10m"), Some("10m")]),
+            (50_313_000, [Some("6m"), Some("6m"), Some("6m")]),
+            (70_154_000, [Some("4m"), None, None]),
+            (146_500_000, [None, Some("2m"), Some("2m")]),
+            (223_500_000, [None, Some("1.25m"), None]),
+            (420_000_000, [None, Some("70cm"), None]),
+            (432_065_000, [Some("70cm"), Some("70cm"), Some("70cm")]),
+            (0, [None, None, None]),
+        ] {
+            for (region, band) in (1..=3).zip(bands) {
+                assert_eq!(band_for_frequency(frequency_hz, region), band, "{} Hz in region {}", frequency_hz, region);
+            }
+        }
+        // anything but 1 or 3 is taken as region 2
+        assert_eq!(band_for_frequency(3_950_000, 0), Some("80m"));
+    }
+}
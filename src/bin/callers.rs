@@ -0,0 +1,146 @@
+use std::cmp::Reverse;
+use std::io::{self, Write};
+use chrono::{DateTime, Duration, Utc};
+use colored::*;
+use serde_derive::Deserialize;
+use super::*;
+
+// callers that haven't called again for this long have given up
+const CALLER_EXPIRY_MINUTES: i64 = 3;
+
+// "called_me" in config.json, what happens when a new station calls us
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct CalledMeConfig {
+    pub bell: bool,
+    // run through the shell like an alert rule command, with WSJTX_RULE set to "called me"
+    pub command: Option<String>,
+}
+
+impl Default for CalledMeConfig {
+    fn default() -> Self {
+        Self { bell: true, command: None }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Caller {
+    pub call: String,
+    // the latest decode from them, so they can be answered with a Reply
+    pub decode: Decode,
+    pub needs: Option<Needs>,
+    pub first_heard: DateTime<Utc>,
+    pub last_heard: DateTime<Utc>,
+    pub times_called: u32,
+}
+
+impl Caller {
+    // needed entities first in headline order, then the strongest signal
+    fn priority(&self) -> (usize, NeedStatus, Reverse<i32>) {
+        let headline = self.needs.as_ref().and_then(|needs| needs.headline());
+        let rank = match headline {
+            Some((WorkedCategory::Dxcc, _)) => 0,
+            Some((WorkedCategory::Continent, _)) => 1,
+            Some((WorkedCategory::CqZone, _)) => 2,
+            Some((WorkedCategory::State, _)) => 3,
+            Some((WorkedCategory::Grid, _)) => 4,
+            Some((WorkedCategory::Call, _)) => 5,
+            None => 6,
+        };
+        (rank, headline.map(|(_, status)| status).unwrap_or(NeedStatus::Confirmed), Reverse(self.decode.snr))
+    }
+
+    pub fn entity_name(&self) -> &str {
+        self.needs.as_ref().and_then(|needs| needs.entity.as_ref()).map(|entity| entity.name.as_str()).unwrap_or("")
+    }
+
+    pub fn label(&self) -> String {
+        self.needs.as_ref().map(|needs| needs.label()).unwrap_or_default()
+    }
+}
+
+impl std::fmt::Display for Caller {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{:<10} {:>3} dB  {:<24} {:<20} called {}x, last {}", self.call, self.decode.snr, self.entity_name(),
+            self.label(), self.times_called, self.last_heard.format("%H:%M:%S"))
+    }
+}
+
+// stations calling us that we haven't answered yet
+#[derive(Debug, Default)]
+pub struct PendingCallers {
+    callers: Vec<Caller>,
+}
+
+impl PendingCallers {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // true when the caller is new to the queue, which is when the alert goes off
+    pub fn heard(&mut self, call: &str, decode: &Decode, needs: Option<&Needs>, now: DateTime<Utc>) -> bool {
+        if let Some(caller) = self.callers.iter_mut().find(|caller| caller.call == call) {
+            caller.decode = decode.clone();
+            caller.last_heard = now;
+            caller.times_called += 1;
+            return false;
+        }
+        self.callers.push(Caller {
+            call: call.to_string(),
+            decode: decode.clone(),
+            needs: needs.cloned(),
+            first_heard: now,
+            last_heard: now,
+            times_called: 1,
+        });
+        true
+    }
+
+    // once we are working or have logged a caller they leave the queue
+    pub fn remove(&mut self, call: &str) {
+        self.callers.retain(|caller| caller.call != call);
+    }
+
+    pub fn prune(&mut self, now: DateTime<Utc>) {
+        self.callers.retain(|caller| now - caller.last_heard < Duration::minutes(CALLER_EXPIRY_MINUTES));
+    }
+
+    // best first, the order to answer them in
+    pub fn sorted(&self) -> Vec<Caller> {
+        let mut callers = self.callers.clone();
+        callers.sort_by_key(|caller| caller.priority());
+        callers
+    }
+}
+
+impl std::fmt::Display for PendingCallers {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        if self.callers.is_empty() {
+            return writeln!(f, "Nobody waiting");
+        }
+        for (index, caller) in self.sorted().iter().enumerate() {
+            writeln!(f, "{:>2}. {}", index + 1, caller)?;
+        }
+        Ok(())
+    }
+}
+
+pub fn called_me_alert(caller: &str, decode: &Decode, needs: Option<&Needs>, app_state: &AppState) {
+    let config = app_state.config();
+    if config.called_me.bell {
+        // two bells, so it doesn't sound like an ordinary alert rule
+        print!("\x07\x07");
+        let _ = io::stdout().flush();
+    }
+    if let Some(command) = &config.called_me.command {
+        if let Err(e) = run_alert_command(command, "called me", caller, decode) {
+            app_state.log(&format!("Could not run called me command: {}", e));
+        }
+    }
+    if !app_state.tui {
+        let entity = needs.and_then(|needs| needs.entity.as_ref()).map(|entity| entity.name.as_str()).unwrap_or("");
+        let label = needs.map(|needs| needs.label()).unwrap_or_default();
+        let line = format!(" CALLED BY {} {} dB {} {} ", caller, decode.snr, entity, label);
+        println!("{}", line.reversed().bold());
+    }
+}
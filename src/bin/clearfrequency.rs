@@ -0,0 +1,282 @@
+use std::collections::{HashMap, VecDeque};
+use chrono::{NaiveTime, Timelike, Utc};
+use serde_derive::Deserialize;
+use super::*;
+
+// offsets are tried in steps this size
+const STEP_HZ: u32 = 10;
+
+// "clear_frequency" in config.json, where in the passband to look for a quiet TX offset
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct ClearFrequencyConfig {
+    // send the quietest offset to WSJT-X as rx_df with Configure after each period, while we aren't transmitting
+    pub auto_set: bool,
+    pub min_hz: u32,
+    pub max_hz: u32,
+    // space kept clear either side of our signal
+    pub guard_hz: u32,
+    // periods of our TX parity remembered, older ones count for less
+    pub periods: usize,
+}
+
+impl Default for ClearFrequencyConfig {
+    fn default() -> Self {
+        Self { auto_set: false, min_hz: 300, max_hz: 2700, guard_hz: 10, periods: 4 }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct PeriodSignals {
+    period: u32,
+    // audio offset and width of each signal decoded in the period
+    signals: Vec<(u32, u32)>,
+}
+
+#[derive(Debug, Default)]
+struct Occupancy {
+    period_ms: u32,
+    // 0 for the even periods, 1 for the odd, worked out when we start transmitting
+    tx_parity: Option<u32>,
+    was_transmitting: bool,
+    // the last decoded period we pushed an offset after
+    last_set: Option<u32>,
+    periods: VecDeque<PeriodSignals>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ClearSlot {
+    pub offset_hz: u32,
+    // weighted overlaps with recent signals, 0 is completely clear
+    pub busy: u32,
+}
+
+// Status has the T/R period in whole seconds, FT4's 7.5 comes through as 7
+fn period_ms(mode: &str, tr_period: u32) -> u32 {
+    match mode {
+        "FT4" => 7500,
+        _ => tr_period.max(1) * 1000,
+    }
+}
+
+// periods counted from midnight, so their parity is the even/odd WSJT-X TX setting
+fn period_number(time: NaiveTime, period_ms: u32) -> u32 {
+    (time.num_seconds_from_midnight() * 1000 + time.nanosecond() / 1_000_000) / period_ms.max(1)
+}
+
+impl Occupancy {
+    fn add(&mut self, period: u32, offset_hz: u32, width_hz: u32, keep: usize) {
+        if self.periods.back().map(|last| last.period) != Some(period) {
+            self.periods.push_back(PeriodSignals { period, signals: Vec::new() });
+            // both parities are kept
+            while self.periods.len() > keep * 2 {
+                self.periods.pop_front();
+            }
+        }
+        if let Some(last) = self.periods.back_mut() {
+            last.signals.push((offset_hz, width_hz));
+        }
+    }
+
+    // the periods our transmissions share, newest first, or every period while our parity isn't known
+    fn tx_periods(&self) -> Vec<&PeriodSignals> {
+        self.periods.iter().rev().filter(|signals| self.tx_parity.map(|parity| signals.period % 2 == parity).unwrap_or(true)).collect()
+    }
+
+    fn busy(&self, offset_hz: u32, width_hz: u32, config: &ClearFrequencyConfig) -> u32 {
+        let low = offset_hz.saturating_sub(config.guard_hz);
+        let high = offset_hz + width_hz + config.guard_hz;
+        let periods = self.tx_periods();
+        let weights = periods.len() as u32;
+        periods.iter().enumerate().map(|(age, signals)| {
+            let overlapping = signals.signals.iter().filter(|(signal_offset, signal_width)| *signal_offset < high && low < signal_offset + signal_width).count() as u32;
+            overlapping * (weights - age as u32)
+        }).sum()
+    }
+
+    // the clearest offset, nearest the current one when several are equally clear
+    fn clearest(&self, width_hz: u32, current_hz: u32, config: &ClearFrequencyConfig) -> Option<ClearSlot> {
+        let last = config.max_hz.checked_sub(width_hz)?;
+        (config.min_hz..=last)
+            .step_by(STEP_HZ as usize)
+            .map(|offset_hz| ClearSlot { offset_hz, busy: self.busy(offset_hz, width_hz, config) })
+            .min_by_key(|slot| (slot.busy, slot.offset_hz.abs_diff(current_hz)))
+    }
+
+    // one line of the passband, a character per 100 Hz, dot for clear, digits for how many signals
+    fn map(&self, config: &ClearFrequencyConfig) -> String {
+        let periods = self.tx_periods();
+        (config.min_hz / 100..config.max_hz.div_ceil(100)).map(|hundred| {
+            let (low, high) = (hundred * 100, hundred * 100 + 100);
+            let count = periods.iter().flat_map(|signals| &signals.signals)
+                .filter(|(offset, width)| *offset < high && low < offset + width)
+                .count();
+            match count {
+                0 => '.',
+                1..=9 => char::from_digit(count as u32, 10).unwrap_or('#'),
+                _ => '#',
+            }
+        }).collect()
+    }
+}
+
+// the part of the passband the Fox/Hound rules leave us
+fn allowed_passband(status: &Status, config: &ClearFrequencyConfig) -> ClearFrequencyConfig {
+    let mut config = config.clone();
+    match status.special_operation_mode {
+        HOUND_MODE => config.min_hz = config.min_hz.max(FOX_HOUND_SPLIT_HZ),
+        FOX_MODE => config.max_hz = config.max_hz.min(FOX_HOUND_SPLIT_HZ),
+        _ => {}
+    }
+    config
+}
+
+// passband occupancy for each WSJT-X instance
+#[derive(Debug, Default)]
+pub struct ClearFrequency {
+    instances: HashMap<String, Occupancy>,
+}
+
+impl ClearFrequency {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn heard(&mut self, decode: &Decode, keep: usize) {
+        let occupancy = self.instances.entry(decode.id.clone()).or_default();
+        let mode = decode.mode_name().unwrap_or("FT8");
+        if occupancy.period_ms == 0 {
+            occupancy.period_ms = period_ms(mode, 15);
+        }
+        let width = signal_width_hz(mode) as u32;
+        let period = period_number(decode.time, occupancy.period_ms);
+        occupancy.add(period, decode.delta_frequency_hz, width, keep);
+    }
+
+    fn status(&mut self, status: &Status) -> &mut Occupancy {
+        let occupancy = self.instances.entry(status.id.clone()).or_default();
+        let status_period_ms = period_ms(&status.mode, status.tr_period);
+        if status.tr_period > 0 && status.tr_period != u32::MAX && status_period_ms != occupancy.period_ms {
+            // periods of another length don't line up with what we have
+            occupancy.period_ms = status_period_ms;
+            occupancy.periods.clear();
+            occupancy.tx_parity = None;
+        }
+        if status.transmitting && !occupancy.was_transmitting {
+            occupancy.tx_parity = Some(period_number(Utc::now().time(), occupancy.period_ms) % 2);
+        }
+        occupancy.was_transmitting = status.transmitting;
+        occupancy
+    }
+
+    // the clearest slot for the instance's TX mode, nearest current_hz when there's a choice
+    pub fn suggest(&self, status: &Status, current_hz: u32, config: &ClearFrequencyConfig) -> Option<ClearSlot> {
+        let occupancy = self.instances.get(&status.id)?;
+        let mode = non_empty(&status.tx_mode).unwrap_or(&status.mode);
+        occupancy.clearest(signal_width_hz(mode) as u32, current_hz, &allowed_passband(status, config))
+    }
+
+    pub fn report(&self, status: &Status, config: &ClearFrequencyConfig) -> String {
+        let occupancy = match self.instances.get(&status.id) {
+            Some(occupancy) => occupancy,
+            None => return "Nothing decoded yet".to_string(),
+        };
+        let parity = match occupancy.tx_parity {
+            Some(0) => "even",
+            Some(_) => "odd",
+            None => "all, TX period not known yet",
+        };
+        let mode = non_empty(&status.tx_mode).unwrap_or(&status.mode);
+        let width = signal_width_hz(mode) as u32;
+        let mut lines = vec![
+            format!("{}-{} Hz, periods: {}", config.min_hz, config.max_hz, parity),
+            format!("|{}|", occupancy.map(config)),
+            format!("TX {} Hz busy {}", status.tx_df, occupancy.busy(status.tx_df, width, config)),
+        ];
+        if let Some(slot) = occupancy.clearest(width, status.tx_df, &allowed_passband(status, config)) {
+            lines.push(format!("Clearest {} Hz busy {}", slot.offset_hz, slot.busy));
+        }
+        lines.join("\n")
+    }
+}
+
+pub fn send_rx_df(id: &str, rx_df: u32, app_state: &AppState) -> std::io::Result<()> {
+    let configure = Configure {
+        message_type: 15,
+        id: id.to_string(),
+        mode: String::new(),
+        frequency_tolerance: u32::MAX,
+        submode: String::new(),
+        fast_mode: false,
+        tr_period: -1,
+        rx_df: rx_df as i32,
+        dx_call: String::new(),
+        dx_grid: String::new(),
+        generate_messages: false,
+    };
+    send_to_instance(app_state, id, encode_configure(&configure))
+}
+
+pub fn occupancy_decode(decode: &Decode, app_state: &AppState) {
+    if decode.off_air {
+        return;
+    }
+    let keep = app_state.config().clear_frequency.periods;
+    app_state.clear_frequency.lock().unwrap().heard(decode, keep);
+}
+
+// after each decoded period, moves our offset somewhere clearer if it is busy and we are idle
+pub fn update_clear_frequency(status: &Status, app_state: &AppState) {
+    let config = app_state.config();
+    let mut clear_frequency = app_state.clear_frequency.lock().unwrap();
+    let occupancy = clear_frequency.status(status);
+    let config = &config.clear_frequency;
+    if !config.auto_set || status.decoding || status.transmitting || status.tx_enabled {
+        return;
+    }
+    let period = match occupancy.periods.back() {
+        Some(last) if occupancy.last_set != Some(last.period) => last.period,
+        _ => return,
+    };
+    occupancy.last_set = Some(period);
+    let slot = match clear_frequency.suggest(status, status.rx_df, config) {
+        Some(slot) => slot,
+        None => return,
+    };
+    let mode = non_empty(&status.tx_mode).unwrap_or(&status.mode);
+    let current_busy = clear_frequency.instances.get(&status.id)
+        .map(|occupancy| occupancy.busy(status.rx_df, signal_width_hz(mode) as u32, config))
+        .unwrap_or(0);
+    drop(clear_frequency);
+    if slot.offset_hz == status.rx_df || current_busy <= slot.busy || app_state.qso_tracker.lock().unwrap().own_qso_in_progress(&status.id, Utc::now()) {
+        return;
+    }
+    match send_rx_df(&status.id, slot.offset_hz, app_state) {
+        Ok(()) => app_state.log(&format!("Moved {} to a clear {} Hz, {} Hz was busy", status.id, slot.offset_hz, status.rx_df)),
+        Err(e) => app_state.log(&format!("Could not set rx_df: {}", e)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn period_parity() {
+        let time = |hour, minute, second, milli| NaiveTime::from_hms_milli_opt(hour, minute, second, milli).unwrap();
+        let cases = [
+            ("FT8", 15, time(0, 0, 14, 900), 0),
+            ("FT8", 15, time(0, 0, 15, 0), 1),
+            ("FT8", 15, time(12, 0, 30, 500), 0),
+            // FT4 is reported as 7 s, its periods start at 0, 7.5, 15, 22.5 s
+            ("FT4", 7, time(0, 0, 7, 400), 0),
+            ("FT4", 7, time(0, 0, 7, 600), 1),
+            ("FT4", 7, time(0, 0, 22, 400), 0),
+            ("FT4", 7, time(12, 0, 52, 600), 1),
+            ("FST4", 60, time(0, 1, 30, 0), 1),
+        ];
+        for (mode, tr_period, time, parity) in cases {
+            assert_eq!(period_number(time, period_ms(mode, tr_period)) % 2, parity, "{} {}", mode, time);
+        }
+    }
+}
@@ -0,0 +1,314 @@
+use std::collections::{HashMap, VecDeque};
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::mpsc::{self, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use chrono::{DateTime, Duration, Utc};
+use serde_derive::Deserialize;
+use super::*;
+
+// the longest comment a spot line has room for
+const COMMENT_WIDTH: usize = 30;
+const SHOW_DX_DEFAULT: usize = 10;
+
+// "cluster" in config.json, a DX-cluster telnet server on address for logging programs that only take spots
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct ClusterConfig {
+    // e.g. "127.0.0.1:7300", no server when unset
+    pub address: Option<String>,
+    // which decodes become spots
+    pub cq: bool,
+    pub needed: bool,
+    // a station isn't spotted again on the same band for this long
+    pub holdoff_minutes: i64,
+    // spots kept for sh/dx
+    pub history: usize,
+}
+
+impl Default for ClusterConfig {
+    fn default() -> Self {
+        Self { address: None, cq: true, needed: true, holdoff_minutes: 10, history: 200 }
+    }
+}
+
+// a spot and what a client's set/filter needs to judge it
+#[derive(Debug, Clone)]
+pub struct ClusterSpot {
+    pub line: String,
+    decode: Decode,
+    needs: Option<Needs>,
+    own_call: Option<String>,
+    own_grid: Option<String>,
+}
+
+impl ClusterSpot {
+    fn matches(&self, filter: Option<&Filter>) -> bool {
+        let filter = match filter {
+            Some(filter) => filter,
+            None => return true,
+        };
+        let context = FilterContext {
+            decode: &self.decode,
+            message: parse_ft8_message(&self.decode.message),
+            own_call: self.own_call.clone(),
+            own_grid: self.own_grid.clone(),
+            needs: self.needs.clone(),
+        };
+        filter.matches(&context)
+    }
+}
+
+// "DX de KE8TKS:    14074.0  JA1XYZ       FT8 -12dB PM95                 1234Z"
+pub fn spot_line(spotter: &str, frequency_hz: u64, call: &str, comment: &str, time: DateTime<Utc>) -> String {
+    let comment: String = comment.chars().take(COMMENT_WIDTH).collect();
+    format!("DX de {:<10}{:>8.1}  {:<13}{:<30} {}Z", format!("{}:", spotter), frequency_hz as f64 / 1000.0, call, comment, time.format("%H%M"))
+}
+
+// what a client's writer thread sends, Close ends the connection once everything before it is written
+enum ClusterOutput {
+    Spot(Arc<ClusterSpot>),
+    Text(String),
+    Close,
+}
+
+// connected clients, recent spots and when each station was last spotted
+#[derive(Debug, Default)]
+pub struct Cluster {
+    senders: Vec<Sender<ClusterOutput>>,
+    history: VecDeque<Arc<ClusterSpot>>,
+    spotted: HashMap<(String, Option<&'static str>), DateTime<Utc>>,
+}
+
+impl Cluster {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // an empty text writes nothing, it only finds the connections that have closed
+    pub fn clients(&mut self) -> usize {
+        self.senders.retain(|sender| sender.send(ClusterOutput::Text(String::new())).is_ok());
+        self.senders.len()
+    }
+
+    fn spot(&mut self, spot: ClusterSpot, keep: usize) {
+        let spot = Arc::new(spot);
+        self.history.push_back(Arc::clone(&spot));
+        while self.history.len() > keep {
+            self.history.pop_front();
+        }
+        // connections that have gone away are dropped here
+        self.senders.retain(|sender| sender.send(ClusterOutput::Spot(Arc::clone(&spot))).is_ok());
+    }
+
+    // newest first, as clusters list them
+    fn show_dx(&self, count: usize, filter: Option<&Filter>) -> Vec<Arc<ClusterSpot>> {
+        self.history.iter().rev().filter(|spot| spot.matches(filter)).take(count).cloned().collect()
+    }
+}
+
+// a CQ or a needed station becomes a spot, once per band per holdoff
+pub fn cluster_decode(context: &FilterContext, app_state: &AppState) {
+    let config = app_state.config();
+    let config = &config.cluster;
+    let decode = context.decode;
+    if config.address.is_none() || !decode.new || decode.off_air || decode.low_confidence {
+        return;
+    }
+    let (call, frequency_hz) = match (context.message.from_call(), decode.rf_frequency_hz) {
+        (Some(call), Some(frequency_hz)) if valid_callsign(call) => (call, frequency_hz),
+        _ => return,
+    };
+    let cq = matches!(context.message, Ft8Message::Cq { .. });
+    let needed = context.needs.as_ref().map(|needs| needs.headline().is_some()).unwrap_or(false);
+    let wanted = (config.cq && cq) || (config.needed && needed);
+    if !wanted {
+        return;
+    }
+    let now = Utc::now();
+    let mut cluster = app_state.cluster.lock().unwrap();
+    let key = (call.to_string(), decode.band);
+    if cluster.spotted.get(&key).map(|spotted| now - *spotted < Duration::minutes(config.holdoff_minutes)).unwrap_or(false) {
+        return;
+    }
+    cluster.spotted.insert(key, now);
+    cluster.spotted.retain(|_, spotted| now - *spotted < Duration::minutes(config.holdoff_minutes));
+    let mut comment = format!("{} {}dB", decode.mode_name().unwrap_or(""), decode.snr);
+    if let Some(grid) = context.message.grid().filter(|grid| !grid.is_empty()) {
+        comment = format!("{} {}", comment, grid);
+    }
+    match context.needs.as_ref().filter(|_| needed) {
+        Some(needs) => comment = format!("{} {}", comment, needs.label()),
+        None if cq => comment = format!("{} CQ", comment),
+        None => {}
+    }
+    let spotter = context.own_call.as_deref().unwrap_or("WSJTX");
+    let spot = ClusterSpot {
+        line: spot_line(spotter, frequency_hz, call, &comment, decode_timestamp(decode.time, now)),
+        decode: decode.clone(),
+        needs: context.needs.clone(),
+        own_call: context.own_call.clone(),
+        own_grid: context.own_grid.clone(),
+    };
+    cluster.spot(spot, config.history);
+}
+
+// telnet option negotiation and other control bytes are dropped, what is left is the command
+fn read_command(reader: &mut impl BufRead) -> io::Result<Option<String>> {
+    let mut line = Vec::new();
+    if reader.read_until(b'\n', &mut line)? == 0 {
+        return Ok(None);
+    }
+    Ok(Some(line.iter().filter(|byte| (0x20..0x7F).contains(*byte)).map(|byte| *byte as char).collect::<String>().trim().to_string()))
+}
+
+fn node_call(app_state: &AppState) -> String {
+    app_state.instances.lock().unwrap().latest()
+        .and_then(|instance| instance.status.as_ref())
+        .and_then(|status| non_empty(&status.de_call).map(strip_call))
+        .unwrap_or_else(|| "WSJTX".to_string())
+}
+
+// the reply to one command, None when the client is leaving
+fn cluster_command(line: &str, filter: &Mutex<Option<Filter>>, app_state: &AppState) -> Option<String> {
+    let (command, argument) = match line.split_once(' ') {
+        Some((command, argument)) => (command.to_lowercase(), argument.trim()),
+        None => (line.to_lowercase(), ""),
+    };
+    let reply = match command.as_str() {
+        "" => String::new(),
+        "sh/dx" | "show/dx" => {
+            let count = argument.parse::<usize>().unwrap_or(SHOW_DX_DEFAULT);
+            let filter = filter.lock().unwrap().clone();
+            let spots = app_state.cluster.lock().unwrap().show_dx(count, filter.as_ref());
+            spots.iter().map(|spot| format!("{}\r\n", spot.line)).collect()
+        }
+        "set/filter" => match argument {
+            "" => match filter.lock().unwrap().as_ref() {
+                Some(filter) => format!("Filter: {}\r\n", filter),
+                None => "No filter, sending every spot\r\n".to_string(),
+            },
+            "off" => {
+                *filter.lock().unwrap() = None;
+                "Filter cleared\r\n".to_string()
+            }
+            text => match Filter::parse(text) {
+                Ok(parsed) => {
+                    let reply = format!("Filter: {}\r\n", parsed);
+                    *filter.lock().unwrap() = Some(parsed);
+                    reply
+                }
+                Err(e) => format!("Bad filter: {}\r\n", e),
+            },
+        },
+        "unset/filter" => {
+            *filter.lock().unwrap() = None;
+            "Filter cleared\r\n".to_string()
+        }
+        "bye" | "quit" | "exit" | "q" => return None,
+        "help" | "?" => "sh/dx [n]              the last n spots, newest first\r\n\
+            set/filter <expression> e.g. set/filter band = 20m and snr >= -15, the same terms as the console filter\r\n\
+            set/filter off          send every spot\r\n\
+            bye                     disconnect\r\n".to_string(),
+        _ => format!("Unknown command '{}', try help\r\n", command),
+    };
+    Some(reply)
+}
+
+fn cluster_session(stream: TcpStream, app_state: Arc<AppState>) -> io::Result<()> {
+    let peer = stream.peer_addr()?;
+    let mut writer = stream.try_clone()?;
+    let mut reader = BufReader::new(stream);
+    write!(writer, "login: ")?;
+    let call = loop {
+        match read_command(&mut reader)? {
+            Some(call) if !call.is_empty() => break call.to_uppercase(),
+            Some(_) => write!(writer, "login: ")?,
+            None => return Ok(()),
+        }
+    };
+    let node = node_call(&app_state);
+    let prompt = format!("{} de {} >\r\n", call, node);
+    write!(writer, "Hello {}, this is {} running {} {}\r\n{}", call, node, env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"), prompt)?;
+    app_state.log(&format!("DX cluster: {} connected from {}", call, peer));
+
+    // one writer thread, so spots and command replies don't interleave mid-line
+    let filter = Arc::new(Mutex::new(None::<Filter>));
+    let (output, outgoing) = mpsc::channel::<ClusterOutput>();
+    app_state.cluster.lock().unwrap().senders.push(output.clone());
+    let writer_filter = Arc::clone(&filter);
+    thread::spawn(move || {
+        for message in outgoing {
+            let text = match message {
+                ClusterOutput::Spot(spot) if spot.matches(writer_filter.lock().unwrap().as_ref()) => format!("{}\r\n", spot.line),
+                ClusterOutput::Spot(_) => continue,
+                ClusterOutput::Text(text) => text,
+                ClusterOutput::Close => break,
+            };
+            if writer.write_all(text.as_bytes()).is_err() {
+                break;
+            }
+        }
+        let _ = writer.shutdown(std::net::Shutdown::Both);
+    });
+    // a read error is the client going away, the same as it hanging up
+    while let Ok(Some(line)) = read_command(&mut reader) {
+        match cluster_command(&line, &filter, &app_state) {
+            Some(reply) => {
+                if output.send(ClusterOutput::Text(format!("{}{}", reply, prompt))).is_err() {
+                    break;
+                }
+            }
+            None => {
+                let _ = output.send(ClusterOutput::Text(format!("73 {}\r\n", call)));
+                break;
+            }
+        }
+    }
+    // the writer stops, and the next spot finds its sender gone
+    let _ = output.send(ClusterOutput::Close);
+    app_state.log(&format!("DX cluster: {} disconnected", call));
+    Ok(())
+}
+
+// binds before returning so a bad address is reported at startup, each client gets its own thread
+pub fn start_cluster_server(app_state: Arc<AppState>, address: &str) -> io::Result<()> {
+    let listener = TcpListener::bind(address)?;
+    thread::spawn(move || {
+        for stream in listener.incoming().map_while(Result::ok) {
+            let app_state = Arc::clone(&app_state);
+            thread::spawn(move || {
+                if let Err(e) = cluster_session(stream, Arc::clone(&app_state)) {
+                    app_state.log(&format!("DX cluster session ended: {}", e));
+                }
+            });
+        }
+    });
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn spot_line_columns() {
+        let time = Utc.with_ymd_and_hms(2024, 1, 1, 12, 34, 56).unwrap();
+        assert_eq!(spot_line("KE8TKS", 14_074_600, "JA1XYZ", "FT8 -12dB PM95 CQ", time),
+            "DX de KE8TKS:    14074.6  JA1XYZ       FT8 -12dB PM95 CQ              1234Z");
+        // loggers read the fields by column, so short and empty values keep them in place
+        let short = spot_line("K1A", 1_840_000, "W1AW", "", time);
+        assert_eq!(short, "DX de K1A:        1840.0  W1AW                                        1234Z");
+        assert_eq!(short.len(), 75);
+        assert_eq!(short.find("W1AW"), Some(26));
+    }
+
+    #[test]
+    fn spot_line_cuts_long_comments() {
+        let time = Utc.with_ymd_and_hms(2024, 1, 1, 0, 5, 0).unwrap();
+        let line = spot_line("VE3/KE8TKS", 7_074_000, "3D2/R1ABCD/P", "FT8 -5dB RB32 new DXCC zone grid continent", time);
+        assert_eq!(line, "DX de VE3/KE8TKS:  7074.0  3D2/R1ABCD/P FT8 -5dB RB32 new DXCC zone gr 0005Z");
+    }
+}
@@ -0,0 +1,117 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use serde_derive::Deserialize;
+use super::*;
+
+// optional settings read from config.json, or the file given with --config, every field has a default
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    pub iaru_region: u8,
+    // callsigns to watch for, one per line, relative paths are taken from the config file's directory
+    pub watch_list: String,
+    // AD1C cty.dat for callsign to DXCC lookup, a coarse built-in table is used when unset
+    pub cty_file: Option<String>,
+    // ADIF log imported at startup to seed the worked-before data
+    pub adif_history: Option<String>,
+    // ADIF file every QSO logged in WSJT-X is appended to, relative paths are taken from the config file's directory
+    pub adif_log: Option<String>,
+    // filter expression applied to decodes before they are printed, see filter.rs
+    pub display_filter: Option<String>,
+    // address for the web dashboard, e.g. "0.0.0.0:8080" to reach it from other machines, off when unset
+    pub web_address: Option<String>,
+    // needed as "Authorization: Bearer <token>" on control POSTs, without it only this machine can send them
+    pub web_token: Option<String>,
+    // rules checked against every decode, see alerts.rs
+    pub alerts: Vec<AlertRule>,
+    // HighlightCallsignIn colours pushed to WSJT-X for watched calls, new DXCC and needed grids
+    pub wsjtx_highlights: HighlightConfig,
+    // what happens when a station we aren't working yet calls us
+    pub called_me: CalledMeConfig,
+    // SQLite database of decodes, statuses and QSOs, and how long they are kept
+    pub store: StoreConfig,
+    // Cabrillo export and scoring while WSJT-X is in a contest mode
+    pub contest: ContestConfig,
+    // answering CQs from needed stations without the operator, see autoanswer.rs
+    pub auto_answer: AutoAnswerConfig,
+    // wanted stations called as soon as they are free, see hunt.rs
+    pub hunt: HuntConfig,
+    // HaltTx when transmitting goes wrong, see supervisor.rs
+    pub tx_supervisor: SupervisorConfig,
+    // scheduled band and mode changes, see rotation.rs
+    pub rotation: RotationConfig,
+    // finding a quiet TX offset, see clearfrequency.rs
+    pub clear_frequency: ClearFrequencyConfig,
+    // uploading our decodes as spots, see pskreporter.rs
+    pub psk_reporter: PskReporterConfig,
+    // saving and uploading WSPR spots, see wspr.rs
+    pub wspr: WsprConfig,
+    // the DX-cluster telnet server, see cluster.rs
+    pub cluster: ClusterConfig,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            iaru_region: 2,
+            watch_list: "callsigns.txt".to_string(),
+            cty_file: None,
+            adif_history: None,
+            adif_log: None,
+            display_filter: None,
+            web_address: None,
+            web_token: None,
+            alerts: Vec::new(),
+            wsjtx_highlights: HighlightConfig::default(),
+            called_me: CalledMeConfig::default(),
+            store: StoreConfig::default(),
+            contest: ContestConfig::default(),
+            auto_answer: AutoAnswerConfig::default(),
+            hunt: HuntConfig::default(),
+            tx_supervisor: SupervisorConfig::default(),
+            rotation: RotationConfig::default(),
+            clear_frequency: ClearFrequencyConfig::default(),
+            psk_reporter: PskReporterConfig::default(),
+            wspr: WsprConfig::default(),
+            cluster: ClusterConfig::default(),
+        }
+    }
+}
+
+pub fn relative_to_config(config_path: &Path, path: &str) -> PathBuf {
+    match config_path.parent() {
+        Some(directory) => directory.join(path),
+        None => PathBuf::from(path),
+    }
+}
+
+impl Config {
+    pub fn load(path: &Path) -> io::Result<Self> {
+        if !path.exists() {
+            return Ok(Config::default());
+        }
+        let contents = fs::read_to_string(path)?;
+        serde_json::from_str(&contents).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    pub fn watch_list_path(&self, config_path: &Path) -> PathBuf {
+        relative_to_config(config_path, &self.watch_list)
+    }
+
+    pub fn database_path(&self, config_path: &Path) -> Option<PathBuf> {
+        self.store.database.as_ref().map(|database| relative_to_config(config_path, database))
+    }
+
+    pub fn cabrillo_path(&self, config_path: &Path) -> Option<PathBuf> {
+        self.contest.cabrillo.as_ref().map(|cabrillo| relative_to_config(config_path, cabrillo))
+    }
+
+    pub fn adif_log_path(&self, config_path: &Path) -> Option<PathBuf> {
+        self.adif_log.as_ref().map(|adif_log| relative_to_config(config_path, adif_log))
+    }
+
+    pub fn wspr_spots_path(&self, config_path: &Path) -> Option<PathBuf> {
+        self.wspr.spots_file.as_ref().map(|spots_file| relative_to_config(config_path, spots_file))
+    }
+}
@@ -0,0 +1,192 @@
+use std::io::{self, BufRead};
+use std::path::Path;
+use std::sync::Arc;
+use chrono::Utc;
+use super::*;
+
+// commands typed into the server's terminal while it is running
+pub fn run_console(app_state: Arc<AppState>) {
+    let stdin = io::stdin();
+    for line in stdin.lock().lines().map_while(Result::ok) {
+        app_state.control_activity();
+        let line = line.trim();
+        let (command, argument) = match line.split_once(' ') {
+            Some((command, argument)) => (command, argument.trim()),
+            None => (line, ""),
+        };
+        match command {
+            "" => {}
+            "filter" => filter_command(argument, &app_state),
+            "reload" => reload_and_log(&app_state, "reload command"),
+            "heard" => heard_command(argument, &app_state),
+            "awards" => println!("{}", awards_report(&app_state.worked_before.lock().unwrap(), non_empty(&argument.to_uppercase()))),
+            "contest" => contest_command(argument, &app_state),
+            "callers" => print!("{}", app_state.callers.lock().unwrap()),
+            "auto" => auto_command(argument, &app_state),
+            "hunt" => hunt_command(argument, &app_state),
+            "rotation" => rotation_command(argument, &app_state),
+            "clearfreq" => clear_frequency_command(argument, &app_state),
+            "hound" => print!("{}", app_state.hound.lock().unwrap()),
+            "psk" => print!("{}", app_state.psk_reporter.lock().unwrap()),
+            "wspr" => print!("{}", app_state.wspr_uploads.lock().unwrap()),
+            "cluster" => println!("DX cluster clients: {}", app_state.cluster.lock().unwrap().clients()),
+            "help" => print_help(),
+            _ => println!("Unknown command '{}', type help for a list of commands", command),
+        }
+    }
+}
+
+fn filter_command(argument: &str, app_state: &AppState) {
+    let mut display_filter = app_state.display_filter.lock().unwrap();
+    match argument {
+        "" => match display_filter.as_ref() {
+            Some(filter) => println!("Filter: {}", filter),
+            None => println!("No filter, showing every decode"),
+        },
+        "off" | "none" => {
+            *display_filter = None;
+            println!("Filter cleared");
+        }
+        text => match Filter::parse(text) {
+            Ok(filter) => {
+                println!("Filter: {}", filter);
+                *display_filter = Some(filter);
+            }
+            Err(e) => println!("{}: {}", "Bad filter".red(), e),
+        },
+    }
+}
+
+// heard <call> [band], the last time a station was decoded and its latest decodes
+fn heard_command(argument: &str, app_state: &AppState) {
+    let mut words = argument.split_whitespace();
+    let call = match words.next() {
+        Some(call) => call,
+        None => return println!("Usage: heard <call> [band]"),
+    };
+    let query = DecodeQuery { call: Some(call.to_string()), band: words.next().map(|band| band.to_string()), limit: Some(10), ..DecodeQuery::default() };
+    match app_state.with_store(|store| store.decodes(&query)) {
+        None if app_state.store.lock().unwrap().is_none() => println!("No database, set store.database in the config"),
+        None => {}
+        Some(decodes) if decodes.is_empty() => println!("{} not heard", call.to_uppercase()),
+        Some(decodes) => {
+            println!("{} last heard {}", call.to_uppercase(), decodes[0].time.format("%Y-%m-%d %H:%M:%S UTC"));
+            for decode in decodes {
+                println!("    {}", decode);
+            }
+        }
+    }
+}
+
+fn contest_command(argument: &str, app_state: &AppState) {
+    let config = app_state.config();
+    let contest = app_state.contest.lock().unwrap();
+    let contest = match contest.as_ref() {
+        Some(contest) => contest,
+        None => return println!("WSJT-X isn't in a contest mode"),
+    };
+    match argument.split_once(' ').unwrap_or((argument, "")) {
+        ("", _) => println!("{}", contest.summary(&config.contest, Utc::now())),
+        ("export", path) if !path.is_empty() => match contest.write_cabrillo(Path::new(path.trim()), &config.contest) {
+            Ok(()) => println!("Wrote {} QSOs to {}", contest.qsos.len(), path.trim()),
+            Err(e) => println!("Could not write {}: {}", path.trim(), e),
+        },
+        _ => println!("Usage: contest [export <file>]"),
+    }
+}
+
+fn auto_command(argument: &str, app_state: &AppState) {
+    let config = app_state.config();
+    let mut auto_answer = app_state.auto_answer.lock().unwrap();
+    match argument {
+        "" => {}
+        "on" => auto_answer.enabled_override = Some(true),
+        "off" => auto_answer.enabled_override = Some(false),
+        _ => return println!("Usage: auto [on|off]"),
+    }
+    let enabled = auto_answer.enabled(&config.auto_answer);
+    println!("Auto-answer {}, needs: {}, SNR >= {}", if enabled { "on" } else { "off" }, config.auto_answer.needed.join(" "), config.auto_answer.min_snr);
+    print!("{}", auto_answer);
+}
+
+fn hunt_command(argument: &str, app_state: &AppState) {
+    let mut hunt = app_state.hunt.lock().unwrap();
+    let (action, calls) = argument.split_once(' ').unwrap_or((argument, ""));
+    let calls: Vec<String> = calls.split_whitespace().map(|call| call.to_uppercase()).collect();
+    match action {
+        "" => {}
+        "add" if !calls.is_empty() => hunt.add(&calls),
+        "remove" if !calls.is_empty() => {
+            for call in &calls {
+                hunt.remove(call);
+            }
+        }
+        "clear" => hunt.targets.clear(),
+        _ => return println!("Usage: hunt [add|remove <call>...|clear]"),
+    }
+    print!("{}", hunt);
+}
+
+// rotation lists the entries, rotation <n> switches to one now
+fn rotation_command(argument: &str, app_state: &AppState) {
+    let config = app_state.config();
+    let mut rotation = app_state.rotation.lock().unwrap();
+    if !argument.is_empty() {
+        match argument.parse::<usize>() {
+            Ok(number) if (1..=config.rotation.entries.len()).contains(&number) => rotation.switch_to(number - 1),
+            _ => return println!("Usage: rotation [1-{}]", config.rotation.entries.len()),
+        }
+    }
+    println!("Rotation {}", if config.rotation.enabled { "on" } else { "off" });
+    for (index, entry) in config.rotation.entries.iter().enumerate() {
+        let marker = if rotation.current == Some(index) { "*" } else { " " };
+        println!("{}{:>2}. {}", marker, index + 1, entry);
+    }
+    print!("{}", rotation);
+}
+
+// clearfreq shows the passband on our TX period, clearfreq set moves rx_df to the clearest offset
+fn clear_frequency_command(argument: &str, app_state: &AppState) {
+    let config = app_state.config();
+    let status = match app_state.instances.lock().unwrap().latest().and_then(|instance| instance.status.clone()) {
+        Some(status) => status,
+        None => return println!("No WSJT-X instance yet"),
+    };
+    let clear_frequency = app_state.clear_frequency.lock().unwrap();
+    match argument {
+        "" => println!("{}", clear_frequency.report(&status, &config.clear_frequency)),
+        "set" => {
+            let slot = clear_frequency.suggest(&status, status.rx_df, &config.clear_frequency);
+            drop(clear_frequency);
+            match slot.map(|slot| (slot, send_rx_df(&status.id, slot.offset_hz, app_state))) {
+                Some((slot, Ok(()))) => println!("rx_df set to {} Hz", slot.offset_hz),
+                Some((_, Err(e))) => println!("Could not set rx_df: {}", e),
+                None => println!("Nothing decoded yet"),
+            }
+        }
+        _ => println!("Usage: clearfreq [set]"),
+    }
+}
+
+fn print_help() {
+    println!("filter                show the current display filter");
+    println!("filter <expression>   e.g. filter cq and snr >= -15 and not continent = NA");
+    println!("                      terms: cq, tome, qso, needed, lowconf, offair, snr, distance,");
+    println!("                      continent, dxcc, band, new = call|grid|dxcc|state|zone|continent");
+    println!("filter off            show every decode");
+    println!("heard <call> [band]   when a station was last decoded, from the database");
+    println!("awards [mode]         DXCC, WAS, WAZ, WAC and grid/VUCC progress per band, e.g. awards FT8");
+    println!("contest               score, multipliers and rate while WSJT-X is in a contest mode");
+    println!("contest export <file> write the contest log as Cabrillo");
+    println!("callers               stations calling us that haven't been answered, best first");
+    println!("auto [on|off]         answer the best CQ from a needed station each period when we are idle");
+    println!("hunt                  wanted stations, called when they CQ or finish a QSO");
+    println!("hunt add|remove <call> change the targets, hunt clear empties the list");
+    println!("rotation [n]          the band/mode rotation, or switch to entry n now");
+    println!("clearfreq [set]       passband occupancy on our TX period, set moves rx_df to the clearest offset");
+    println!("hound                 in Hound mode, whether the fox has worked us, the queue and fox messages with our call");
+    println!("psk                   PSK Reporter spots waiting and sent");
+    println!("wspr                  WSPRnet uploads queued, sent and dropped");
+    println!("cluster               how many DX cluster clients are connected");
+    println!("reload                read the config file and watch list again (also on change or SIGHUP)");
+}
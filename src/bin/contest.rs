@@ -0,0 +1,412 @@
+use std::collections::HashSet;
+use std::fs;
+use std::io;
+use std::path::Path;
+use chrono::{DateTime, Datelike, Duration, Utc};
+use maidenhead::grid_distance;
+use serde_derive::Deserialize;
+use super::*;
+
+// "contest" in config.json
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct ContestConfig {
+    // Cabrillo file rewritten after every contest QSO, relative paths are taken from the config file's directory
+    pub cabrillo: Option<String>,
+    // CATEGORY-POWER, HIGH, LOW or QRP
+    pub power: String,
+    pub operator: String,
+    // Field Day's power multiplier, 2 for 100 W or less on batteries or otherwise
+    pub power_multiplier: u32,
+    // QSOs already in the database from this far back count for dupes when a contest starts
+    pub period_hours: i64,
+}
+
+impl Default for ContestConfig {
+    fn default() -> Self {
+        Self {
+            cabrillo: None,
+            power: "LOW".to_string(),
+            operator: "SINGLE-OP".to_string(),
+            power_multiplier: 2,
+            period_hours: 48,
+        }
+    }
+}
+
+// the special operating modes of Status that are contests, Fox and Hound aren't
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ContestKind {
+    NaVhf,
+    EuVhf,
+    FieldDay,
+    RttyRoundup,
+    WwDigi,
+    ArrlDigi,
+}
+
+impl ContestKind {
+    pub fn from_special_operation_mode(mode: u8) -> Option<Self> {
+        match mode {
+            1 => Some(ContestKind::NaVhf),
+            2 => Some(ContestKind::EuVhf),
+            3 => Some(ContestKind::FieldDay),
+            4 => Some(ContestKind::RttyRoundup),
+            5 => Some(ContestKind::WwDigi),
+            8 => Some(ContestKind::ArrlDigi),
+            _ => None,
+        }
+    }
+
+    // the CONTEST: tag, the ARRL VHF contests are named for their month
+    fn cabrillo_name(&self, start: DateTime<Utc>) -> &'static str {
+        match self {
+            ContestKind::NaVhf => match start.month() {
+                1 | 2 => "ARRL-VHF-JAN",
+                8..=10 => "ARRL-VHF-SEP",
+                _ => "ARRL-VHF-JUN",
+            },
+            ContestKind::EuVhf => "EU-VHF",
+            ContestKind::FieldDay => "ARRL-FD",
+            ContestKind::RttyRoundup => "ARRL-RTTY",
+            ContestKind::WwDigi => "WW-DIGI",
+            ContestKind::ArrlDigi => "ARRL-DIGI",
+        }
+    }
+
+    // column width the sent and received exchanges are padded to on QSO: lines
+    fn exchange_width(&self) -> usize {
+        match self {
+            ContestKind::NaVhf | ContestKind::WwDigi | ContestKind::ArrlDigi => 4,
+            ContestKind::FieldDay | ContestKind::RttyRoundup => 7,
+            ContestKind::EuVhf => 13,
+        }
+    }
+}
+
+impl std::fmt::Display for ContestKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let name = match self {
+            ContestKind::NaVhf => "NA VHF",
+            ContestKind::EuVhf => "EU VHF",
+            ContestKind::FieldDay => "Field Day",
+            ContestKind::RttyRoundup => "RTTY Roundup",
+            ContestKind::WwDigi => "WW Digi",
+            ContestKind::ArrlDigi => "ARRL Digi",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ContestQso {
+    pub qso: StoredQso,
+    pub band: String,
+    pub points: u32,
+    pub dupe: bool,
+    // the multipliers this QSO was the first for
+    pub new_multipliers: Vec<String>,
+}
+
+// the QSOs of the contest WSJT-X is set up for, with dupes, multipliers and score
+#[derive(Debug)]
+pub struct Contest {
+    pub kind: ContestKind,
+    pub started: DateTime<Utc>,
+    pub qsos: Vec<ContestQso>,
+    worked: HashSet<(String, String)>,
+    multipliers: HashSet<String>,
+}
+
+// the Canadian provinces and territories RTTY Roundup counts alongside the US states
+const RAC_PROVINCES: &[&str] = &["NB", "NL", "NS", "PE", "QC", "ON", "MB", "SK", "AB", "BC", "NT", "NU", "YT"];
+
+fn grid_token(exchange: &str) -> Option<&str> {
+    exchange.split_whitespace().find(|token| token.len() >= 4 && valid_grid(token))
+}
+
+fn distance_km(my_grid: Option<&str>, grid: Option<&str>) -> Option<f64> {
+    grid_distance(my_grid?, grid?).ok()
+}
+
+// the VHF band designators Cabrillo uses instead of a frequency
+fn cabrillo_frequency(frequency_hz: u64) -> String {
+    match frequency_hz {
+        0..=29_999_999 => (frequency_hz / 1000).to_string(),
+        30_000_000..=59_999_999 => "50".to_string(),
+        60_000_000..=99_999_999 => "70".to_string(),
+        100_000_000..=199_999_999 => "144".to_string(),
+        200_000_000..=299_999_999 => "222".to_string(),
+        300_000_000..=599_999_999 => "432".to_string(),
+        600_000_000..=999_999_999 => "902".to_string(),
+        _ => "1.2G".to_string(),
+    }
+}
+
+impl Contest {
+    pub fn new(kind: ContestKind, started: DateTime<Utc>) -> Self {
+        Self { kind, started, qsos: Vec::new(), worked: HashSet::new(), multipliers: HashSet::new() }
+    }
+
+    pub fn is_dupe(&self, call: &str, band: &str) -> bool {
+        self.worked.contains(&(call.to_uppercase(), band.to_string()))
+    }
+
+    // points for a QSO that isn't a dupe, and the multipliers it counts towards
+    fn score_qso(&self, qso: &StoredQso, band: &str, dxcc: &DxccTable) -> (u32, Vec<String>) {
+        let received = qso.exchange_received.as_deref().unwrap_or("");
+        let grid = grid_token(received);
+        let my_grid = qso.exchange_sent.as_deref().and_then(grid_token).or(qso.my_grid.as_deref());
+        let distance = distance_km(my_grid, grid);
+        match self.kind {
+            ContestKind::NaVhf => {
+                // 2.3 GHz and up is 4 points, HF or a frequency outside the bands counts for nothing
+                let points = match band {
+                    "6m" | "4m" | "2m" => 1,
+                    "1.25m" | "70cm" => 2,
+                    "33cm" | "23cm" => 3,
+                    _ if band_wavelength_m(band) > 0.0 && band_wavelength_m(band) <= 0.13 => 4,
+                    _ => return (0, Vec::new()),
+                };
+                (points, grid.and_then(|grid| grid.get(..4)).map(|square| format!("{} {}", band, square.to_uppercase())).into_iter().collect())
+            }
+            ContestKind::EuVhf => (distance.map(|km| km.round() as u32).unwrap_or(0).max(1), Vec::new()),
+            ContestKind::FieldDay => {
+                // class then ARRL/RAC section, "1D EMA"
+                let section = received.split_whitespace().nth(1).map(|section| format!("section {}", section.to_uppercase()));
+                (2, section.into_iter().collect())
+            }
+            ContestKind::RttyRoundup => {
+                // W/VE send a state or province, everyone else a serial number or "DX" and counts as their DXCC entity
+                let location = received.split_whitespace().last().unwrap_or("").to_uppercase();
+                let multiplier = if is_us_state_code(&location) || RAC_PROVINCES.contains(&location.as_str()) {
+                    Some(format!("state {}", location))
+                } else {
+                    dxcc.lookup(&qso.call)
+                        .filter(|entity| !["K", "VE", "KL", "KH6"].contains(&entity.prefix.as_str()))
+                        .map(|entity| format!("dxcc {}", entity.name))
+                };
+                (1, multiplier.into_iter().collect())
+            }
+            ContestKind::WwDigi | ContestKind::ArrlDigi => {
+                // WW Digi counts 2 character fields, ARRL Digi 4 character grid squares
+                let (step, length) = if self.kind == ContestKind::WwDigi { (3000.0, 2) } else { (500.0, 4) };
+                let points = 1 + distance.map(|km| (km / step) as u32).unwrap_or(0);
+                (points, grid.and_then(|grid| grid.get(..length)).map(|locator| format!("{} {}", band, locator.to_uppercase())).into_iter().collect())
+            }
+        }
+    }
+
+    pub fn log(&mut self, qso: StoredQso, iaru_region: u8, dxcc: &DxccTable) -> &ContestQso {
+        let band = band_for_frequency(qso.frequency_hz, iaru_region).unwrap_or("").to_string();
+        let dupe = self.is_dupe(&qso.call, &band);
+        let (points, multipliers) = if dupe { (0, Vec::new()) } else { self.score_qso(&qso, &band, dxcc) };
+        let new_multipliers = multipliers.into_iter().filter(|multiplier| self.multipliers.insert(multiplier.clone())).collect();
+        self.worked.insert((qso.call.to_uppercase(), band.clone()));
+        self.qsos.push(ContestQso { qso, band, points, dupe, new_multipliers });
+        self.qsos.last().unwrap()
+    }
+
+    pub fn points(&self) -> u32 {
+        self.qsos.iter().map(|qso| qso.points).sum()
+    }
+
+    pub fn score(&self, config: &ContestConfig) -> u32 {
+        match self.kind {
+            ContestKind::EuVhf => self.points(),
+            // sections are tracked for the operator, Field Day scores on power instead
+            ContestKind::FieldDay => self.points() * config.power_multiplier,
+            _ => self.points() * self.multipliers.len() as u32,
+        }
+    }
+
+    // QSOs in the last hour, and the last ten minutes as an hourly rate
+    pub fn rate(&self, now: DateTime<Utc>) -> (usize, usize) {
+        let since = |minutes| self.qsos.iter().filter(|qso| !qso.dupe && now - qso.qso.time_on <= Duration::minutes(minutes)).count();
+        (since(60), since(10) * 6)
+    }
+
+    pub fn summary(&self, config: &ContestConfig, now: DateTime<Utc>) -> String {
+        let (last_hour, rate) = self.rate(now);
+        let dupes = self.qsos.iter().filter(|qso| qso.dupe).count();
+        format!("{}: {} QSOs ({} dupes), {} points, {} multipliers, score {}, {} in the last hour, rate {}/h",
+            self.kind, self.qsos.len() - dupes, dupes, self.points(), self.multipliers.len(), self.score(config), last_hour, rate)
+    }
+
+    fn qso_line(&self, contest_qso: &ContestQso) -> String {
+        let qso = &contest_qso.qso;
+        let width = self.kind.exchange_width();
+        format!("QSO: {:>5} DG {} {:<13} {:<width$} {:<13} {}",
+            cabrillo_frequency(qso.frequency_hz),
+            qso.time_on.format("%Y-%m-%d %H%M"),
+            qso.my_call.as_deref().unwrap_or("").to_uppercase(),
+            qso.exchange_sent.as_deref().unwrap_or("").to_uppercase(),
+            qso.call.to_uppercase(),
+            qso.exchange_received.as_deref().unwrap_or("").to_uppercase(),
+            width = width)
+            .trim_end()
+            .to_string()
+    }
+
+    // a Cabrillo 3.0 log, dupes are left in for the log checker to zero
+    pub fn cabrillo(&self, config: &ContestConfig) -> String {
+        let first = self.qsos.first().map(|qso| &qso.qso);
+        let mut lines = vec![
+            "START-OF-LOG: 3.0".to_string(),
+            format!("CONTEST: {}", self.kind.cabrillo_name(self.started)),
+            format!("CALLSIGN: {}", first.and_then(|qso| qso.my_call.as_deref()).unwrap_or("").to_uppercase()),
+            format!("CATEGORY-OPERATOR: {}", config.operator),
+            "CATEGORY-BAND: ALL".to_string(),
+            "CATEGORY-MODE: DIGI".to_string(),
+            format!("CATEGORY-POWER: {}", config.power),
+            format!("CLAIMED-SCORE: {}", self.score(config)),
+            format!("CREATED-BY: wsjtxrust {}", env!("CARGO_PKG_VERSION")),
+        ];
+        if let Some(grid) = first.and_then(|qso| qso.my_grid.as_deref()) {
+            lines.insert(3, format!("GRID-LOCATOR: {}", grid.to_uppercase()));
+        }
+        lines.extend(self.qsos.iter().map(|qso| self.qso_line(qso)));
+        lines.push("END-OF-LOG:".to_string());
+        lines.join("\n") + "\n"
+    }
+
+    pub fn write_cabrillo(&self, path: &Path, config: &ContestConfig) -> io::Result<()> {
+        fs::write(path, self.cabrillo(config))
+    }
+}
+
+// starts a contest when WSJT-X switches to a contest mode, seeded from the database so a restart keeps the dupes,
+// and ends it when WSJT-X leaves contest mode
+pub fn update_contest(status: &Status, app_state: &AppState) {
+    let kind = ContestKind::from_special_operation_mode(status.special_operation_mode);
+    let mut contest = app_state.contest.lock().unwrap();
+    if contest.as_ref().map(|contest| contest.kind) == kind {
+        return;
+    }
+    let config = app_state.config();
+    let now = Utc::now();
+    let kind = match kind {
+        Some(kind) => kind,
+        None => {
+            if let Some(ended) = contest.take() {
+                drop(contest);
+                app_state.log(&format!("{} ended, {}", ended.kind, ended.summary(&config.contest, now)));
+            }
+            return;
+        }
+    };
+    let mut started = Contest::new(kind, now);
+    let since = now - Duration::hours(config.contest.period_hours);
+    // QSOs from another event or outside contest mode are neither dupes nor multipliers
+    let earlier = app_state.with_store(|store| store.contest_qsos_since(since, status.special_operation_mode)).unwrap_or_default();
+    for qso in earlier.into_iter().filter(|qso| qso.exchange_received.is_some()) {
+        started.log(qso, config.iaru_region, &app_state.dxcc);
+    }
+    app_state.log(&format!("{} mode, {}", kind, started.summary(&config.contest, now)));
+    *contest = Some(started);
+}
+
+// a QSO of WSJT-X's LoggedADIF record, added to the dupe sheet and scored while a contest is on
+pub fn log_contest_qso(adif_qso: &AdifQso, app_state: &AppState) {
+    let config = app_state.config();
+    let mut running = app_state.contest.lock().unwrap();
+    let contest = match running.as_mut() {
+        Some(contest) => contest,
+        None => return,
+    };
+    let qso = match StoredQso::from_adif(adif_qso) {
+        Some(qso) => qso,
+        None => {
+            drop(running);
+            app_state.log(&format!("{} left out of the contest, its record has no date, time or frequency", adif_qso.call));
+            return;
+        }
+    };
+    let logged = contest.log(qso, config.iaru_region, &app_state.dxcc);
+    let note = if logged.dupe {
+        format!("DUPE {} on {}", logged.qso.call, logged.band)
+    } else if logged.new_multipliers.is_empty() {
+        format!("{} for {} points", logged.qso.call, logged.points)
+    } else {
+        format!("{} for {} points, new multiplier {}", logged.qso.call, logged.points, logged.new_multipliers.join(", "))
+    };
+    let summary = contest.summary(&config.contest, Utc::now());
+    if let Some(path) = config.cabrillo_path(&app_state.config_path) {
+        if let Err(e) = contest.write_cabrillo(&path, &config.contest) {
+            app_state.log(&format!("Could not write {}: {}", path.display(), e));
+        }
+    }
+    app_state.log(&format!("{}. {}", note, summary));
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+    use super::*;
+
+    fn qso(call: &str, frequency_hz: u64, sent: &str, received: &str) -> StoredQso {
+        StoredQso {
+            time_on: Utc.with_ymd_and_hms(2024, 6, 8, 18, 30, 0).unwrap(),
+            call: call.to_string(),
+            frequency_hz,
+            mode: "FT8".to_string(),
+            my_call: Some("K1ABC".to_string()),
+            my_grid: Some("FN42".to_string()),
+            exchange_sent: Some(sent.to_string()),
+            exchange_received: Some(received.to_string()),
+        }
+    }
+
+    #[test]
+    fn scoring() {
+        let dxcc = DxccTable::builtin();
+        for (kind, call, frequency_hz, sent, received, points, multipliers) in [
+            (ContestKind::NaVhf, "W1AW", 50_313_000, "FN42", "FN31", 1, vec!["6m FN31"]),
+            (ContestKind::NaVhf, "W1AW", 432_065_000, "FN42", "FN31", 2, vec!["70cm FN31"]),
+            (ContestKind::NaVhf, "W1AW", 14_074_000, "FN42", "FN31", 0, vec![]),
+            (ContestKind::NaVhf, "W1AW", 0, "FN42", "FN31", 0, vec![]),
+            (ContestKind::EuVhf, "G4XYZ", 144_174_000, "599001 JO01", "599002 JO01", 1, vec![]),
+            (ContestKind::FieldDay, "W1AW", 14_074_000, "2A EMA", "1D ctx", 2, vec!["section CTX"]),
+            (ContestKind::RttyRoundup, "W1AW", 14_080_000, "599 MA", "599 ct", 1, vec!["state CT"]),
+            (ContestKind::RttyRoundup, "VE3XYZ", 14_080_000, "599 MA", "599 ON", 1, vec!["state ON"]),
+            (ContestKind::RttyRoundup, "G4XYZ", 14_080_000, "599 MA", "599 0012", 1, vec!["dxcc England"]),
+            (ContestKind::RttyRoundup, "G4XYZ", 14_080_000, "599 MA", "599 DX", 1, vec!["dxcc England"]),
+            (ContestKind::RttyRoundup, "W1AW", 14_080_000, "599 MA", "599 DX", 1, vec![]),
+            (ContestKind::WwDigi, "G4XYZ", 14_074_000, "FN42", "IO91", 2, vec!["20m IO"]),
+            (ContestKind::ArrlDigi, "W1AW", 14_074_000, "FN42", "FN31", 1, vec!["20m FN31"]),
+        ] {
+            let mut contest = Contest::new(kind, Utc::now());
+            let logged = contest.log(qso(call, frequency_hz, sent, received), 2, &dxcc);
+            assert_eq!((logged.points, &logged.new_multipliers), (points, &multipliers.iter().map(|m| m.to_string()).collect()),
+                "{} {} {} {}", kind, call, frequency_hz, received);
+        }
+    }
+
+    #[test]
+    fn dupes_are_per_call_and_band() {
+        let dxcc = DxccTable::builtin();
+        let mut contest = Contest::new(ContestKind::ArrlDigi, Utc::now());
+        assert!(!contest.log(qso("W1AW", 14_074_000, "FN42", "FN31"), 2, &dxcc).dupe);
+        let again = contest.log(qso("w1aw", 14_076_000, "FN42", "FN31"), 2, &dxcc);
+        assert!(again.dupe);
+        assert_eq!((again.points, again.new_multipliers.len()), (0, 0));
+        assert!(!contest.log(qso("W1AW", 7_074_000, "FN42", "FN31"), 2, &dxcc).dupe);
+        assert!(!contest.log(qso("W2XYZ", 14_074_000, "FN42", "FN31"), 2, &dxcc).dupe);
+        assert!(contest.is_dupe("W1AW", "40m"));
+        assert!(!contest.is_dupe("W1AW", "15m"));
+        // two 20m FN31 QSOs and one 40m, each 1 point, FN31 a multiplier on each band
+        assert_eq!((contest.points(), contest.score(&ContestConfig::default())), (3, 6));
+    }
+
+    #[test]
+    fn cabrillo_qso_line() {
+        let mut contest = Contest::new(ContestKind::FieldDay, Utc.with_ymd_and_hms(2024, 6, 22, 18, 0, 0).unwrap());
+        contest.log(qso("W1AW", 14_074_000, "2A EMA", "1D CT"), 2, &DxccTable::builtin());
+        let log = contest.cabrillo(&ContestConfig::default());
+        let lines: Vec<&str> = log.lines().collect();
+        assert_eq!(lines[1], "CONTEST: ARRL-FD");
+        assert!(lines.contains(&"CLAIMED-SCORE: 4"));
+        assert_eq!(lines[lines.len() - 2], "QSO: 14074 DG 2024-06-08 1830 K1ABC         2A EMA  W1AW          1D CT");
+        assert_eq!(lines[lines.len() - 1], "END-OF-LOG:");
+    }
+}
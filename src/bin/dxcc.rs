@@ -0,0 +1,297 @@
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct DxccEntity {
+    pub name: String,
+    pub prefix: String,
+    pub continent: String,
+    pub cq_zone: u8,
+}
+
+impl std::fmt::Display for DxccEntity {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{} ({}, {}, CQ {})", self.name, self.prefix, self.continent, self.cq_zone)
+    }
+}
+
+// fallback when no cty.dat is configured: (name, continent, cq zone, primary prefix, prefixes).
+// zones are the entity defaults, point cty_file at the AD1C cty.dat for per-prefix accuracy.
+const BUILTIN_ENTITIES: &[(&str, &str, u8, &str, &str)] = &[
+    ("United States", "NA", 5, "K", "AA,AB,AC,AD,AE,AF,AG,AI,AJ,AK,K,N,W"),
+    ("Canada", "NA", 5, "VE", "CF,CG,CJ,CK,CY,CZ,VA,VB,VC,VD,VE,VG,VO,VX,VY,XJ,XK,XL,XM,XN,XO"),
+    ("Alaska", "NA", 1, "KL", "AL,KL,NL,WL"),
+    ("Hawaii", "OC", 31, "KH6", "AH6,AH7,KH6,KH7,NH6,NH7,WH6,WH7"),
+    ("Puerto Rico", "NA", 8, "KP4", "KP3,KP4,NP3,NP4,WP3,WP4"),
+    ("US Virgin Islands", "NA", 8, "KP2", "KP2,NP2,WP2"),
+    ("Guam", "OC", 27, "KH2", "AH2,KH2,NH2,WH2"),
+    ("Mexico", "NA", 6, "XE", "4A,4B,4C,6D,6E,6F,6G,6H,6I,6J,XA,XB,XC,XD,XE,XF,XG,XH,XI"),
+    ("Cuba", "NA", 8, "CM", "CL,CM,CO,T4"),
+    ("Bahamas", "NA", 8, "C6", "C6"),
+    ("Jamaica", "NA", 8, "6Y", "6Y"),
+    ("Dominican Republic", "NA", 8, "HI", "HI"),
+    ("Barbados", "NA", 8, "8P", "8P"),
+    ("Trinidad & Tobago", "SA", 9, "9Y", "9Y,9Z"),
+    ("Aruba", "SA", 9, "P4", "P4"),
+    ("Curacao", "SA", 9, "PJ2", "PJ2"),
+    ("Guatemala", "NA", 7, "TG", "TD,TG"),
+    ("Costa Rica", "NA", 7, "TI", "TE,TI"),
+    ("Panama", "NA", 7, "HP", "3E,3F,H3,H8,H9,HO,HP"),
+    ("Greenland", "NA", 40, "OX", "OX,XP"),
+    ("Brazil", "SA", 11, "PY", "PP,PQ,PR,PS,PT,PU,PV,PW,PX,PY,ZV,ZW,ZX,ZY,ZZ"),
+    ("Argentina", "SA", 13, "LU", "AY,AZ,L2,L3,L4,L5,L6,L7,L8,L9,LO,LP,LQ,LR,LS,LT,LU,LV,LW"),
+    ("Chile", "SA", 12, "CE", "3G,CA,CB,CC,CD,CE,XQ,XR"),
+    ("Colombia", "SA", 9, "HK", "5J,5K,HJ,HK"),
+    ("Venezuela", "SA", 9, "YV", "4M,YV,YW,YX,YY"),
+    ("Peru", "SA", 10, "OA", "4T,OA,OB,OC"),
+    ("Ecuador", "SA", 10, "HC", "HC,HD"),
+    ("Bolivia", "SA", 10, "CP", "CP"),
+    ("Paraguay", "SA", 11, "ZP", "ZP"),
+    ("Uruguay", "SA", 13, "CX", "CV,CW,CX"),
+    ("England", "EU", 14, "G", "2E,G,M"),
+    ("Scotland", "EU", 14, "GM", "2M,GM,GS,MA,MM,MS"),
+    ("Wales", "EU", 14, "GW", "2W,GC,GW,MC,MW"),
+    ("Northern Ireland", "EU", 14, "GI", "2I,GI,GN,MI,MN"),
+    ("Isle of Man", "EU", 14, "GD", "2D,GD,GT,MD,MT"),
+    ("Jersey", "EU", 14, "GJ", "2J,GH,GJ,MH,MJ"),
+    ("Guernsey", "EU", 14, "GU", "2U,GP,GU,MP,MU"),
+    ("Ireland", "EU", 14, "EI", "EI,EJ"),
+    ("France", "EU", 14, "F", "F,HW,HX,HY,TH,TM"),
+    ("Corsica", "EU", 15, "TK", "TK"),
+    ("Monaco", "EU", 14, "3A", "3A"),
+    ("Andorra", "EU", 14, "C3", "C3"),
+    ("Germany", "EU", 14, "DL", "DA,DB,DC,DD,DE,DF,DG,DH,DI,DJ,DK,DL,DM,DN,DO,DP,DQ,DR"),
+    ("Netherlands", "EU", 14, "PA", "PA,PB,PC,PD,PE,PF,PG,PH,PI"),
+    ("Belgium", "EU", 14, "ON", "ON,OO,OP,OQ,OR,OS,OT"),
+    ("Luxembourg", "EU", 14, "LX", "LX"),
+    ("Switzerland", "EU", 14, "HB", "HB,HE"),
+    ("Liechtenstein", "EU", 14, "HB0", "HB0,HE0"),
+    ("Austria", "EU", 15, "OE", "OE"),
+    ("Italy", "EU", 15, "I", "I"),
+    ("Sardinia", "EU", 15, "IS", "IM0,IS0"),
+    ("San Marino", "EU", 15, "T7", "T7"),
+    ("Vatican", "EU", 15, "HV", "HV"),
+    ("Malta", "EU", 15, "9H", "9H"),
+    ("Spain", "EU", 14, "EA", "AM,AN,AO,EA,EB,EC,ED,EE,EF,EG,EH"),
+    ("Balearic Islands", "EU", 14, "EA6", "AM6,AN6,AO6,EA6,EB6,EC6,ED6,EE6,EF6,EG6,EH6"),
+    ("Canary Islands", "AF", 33, "EA8", "AM8,AN8,AO8,EA8,EB8,EC8,ED8,EE8,EF8,EG8,EH8"),
+    ("Gibraltar", "EU", 14, "ZB", "ZB,ZG"),
+    ("Portugal", "EU", 14, "CT", "CQ,CR,CS,CT"),
+    ("Madeira Islands", "AF", 33, "CT3", "CQ3,CQ9,CR3,CR9,CS3,CS9,CT3,CT9"),
+    ("Azores", "EU", 14, "CU", "CQ1,CQ8,CR1,CR2,CR8,CS4,CS8,CT8,CU"),
+    ("Denmark", "EU", 14, "OZ", "5P,5Q,OU,OV,OZ"),
+    ("Faroe Islands", "EU", 14, "OY", "OY"),
+    ("Norway", "EU", 14, "LA", "LA,LB,LC,LD,LE,LF,LG,LH,LI,LJ,LK,LL,LM,LN"),
+    ("Sweden", "EU", 14, "SM", "7S,8S,SA,SB,SC,SD,SE,SF,SG,SH,SI,SJ,SK,SL,SM"),
+    ("Finland", "EU", 15, "OH", "OF,OG,OH,OI,OJ"),
+    ("Aland Islands", "EU", 15, "OH0", "OF0,OG0,OH0"),
+    ("Iceland", "EU", 40, "TF", "TF"),
+    ("Poland", "EU", 15, "SP", "3Z,HF,SN,SO,SP,SQ,SR"),
+    ("Czech Republic", "EU", 15, "OK", "OK,OL"),
+    ("Slovak Republic", "EU", 15, "OM", "OM"),
+    ("Hungary", "EU", 15, "HA", "HA,HG"),
+    ("Slovenia", "EU", 15, "S5", "S5"),
+    ("Croatia", "EU", 15, "9A", "9A"),
+    ("Bosnia-Herzegovina", "EU", 15, "E7", "E7"),
+    ("Serbia", "EU", 15, "YU", "YT,YU"),
+    ("Montenegro", "EU", 15, "4O", "4O"),
+    ("North Macedonia", "EU", 15, "Z3", "Z3"),
+    ("Albania", "EU", 15, "ZA", "ZA"),
+    ("Lithuania", "EU", 15, "LY", "LY"),
+    ("Latvia", "EU", 15, "YL", "YL"),
+    ("Estonia", "EU", 15, "ES", "ES"),
+    ("Kaliningrad", "EU", 15, "UA2", "R2F,R2K,RA2,UA2"),
+    ("Romania", "EU", 20, "YO", "YO,YP,YQ,YR"),
+    ("Bulgaria", "EU", 20, "LZ", "LZ"),
+    ("Greece", "EU", 20, "SV", "J4,SV,SW,SX,SY,SZ"),
+    ("Crete", "EU", 20, "SV9", "J49,SV9,SW9,SX9,SY9,SZ9"),
+    ("Dodecanese", "EU", 20, "SV5", "J45,SV5,SW5,SX5,SY5,SZ5"),
+    ("Ukraine", "EU", 16, "UR", "EM,EN,EO,U5,UR,US,UT,UU,UV,UW,UX,UY,UZ"),
+    ("Belarus", "EU", 16, "EW", "EU,EV,EW"),
+    ("Moldova", "EU", 16, "ER", "ER"),
+    ("European Russia", "EU", 16, "UA", "R,U"),
+    ("Asiatic Russia", "AS", 17, "UA9", "R0,R8,R9,RA0,RA8,RA9,RK0,RK8,RK9,RU0,RU9,RV0,RV9,RW0,RW9,RX0,RX9,RZ0,RZ9,U0,U8,U9,UA0,UA8,UA9"),
+    ("Turkey", "AS", 20, "TA", "TA,TB,TC,YM"),
+    ("Cyprus", "AS", 20, "5B", "5B,C4,H2,P3"),
+    ("Israel", "AS", 20, "4X", "4X,4Z"),
+    ("Georgia", "AS", 21, "4L", "4L"),
+    ("Armenia", "AS", 21, "EK", "EK"),
+    ("Azerbaijan", "AS", 21, "4J", "4J,4K"),
+    ("Saudi Arabia", "AS", 21, "HZ", "7Z,8Z,HZ"),
+    ("United Arab Emirates", "AS", 21, "A6", "A6"),
+    ("Qatar", "AS", 21, "A7", "A7"),
+    ("Bahrain", "AS", 21, "A9", "A9"),
+    ("Kuwait", "AS", 21, "9K", "9K"),
+    ("Oman", "AS", 21, "A4", "A4"),
+    ("Iran", "AS", 21, "EP", "9B,9C,9D,EP,EQ"),
+    ("Pakistan", "AS", 21, "AP", "6P,6Q,6R,6S,AP,AQ,AR,AS"),
+    ("Kazakhstan", "AS", 17, "UN", "UN,UO,UP,UQ"),
+    ("India", "AS", 22, "VU", "8T,8U,8V,8W,8X,8Y,AT,AU,AV,AW,VT,VU,VV,VW"),
+    ("Sri Lanka", "AS", 22, "4S", "4P,4Q,4R,4S"),
+    ("Nepal", "AS", 22, "9N", "9N"),
+    ("Bangladesh", "AS", 22, "S2", "S2,S3"),
+    ("Mongolia", "AS", 23, "JT", "JT,JU,JV"),
+    ("China", "AS", 24, "BY", "3H,3I,3J,3K,3L,3M,3N,3O,3P,3Q,3R,3S,3T,3U,B,XS"),
+    ("Taiwan", "AS", 24, "BV", "BM,BN,BO,BP,BQ,BU,BV,BW,BX"),
+    ("Hong Kong", "AS", 24, "VR", "VR"),
+    ("Japan", "AS", 25, "JA", "7J,7K,7L,7M,7N,8J,8K,8L,8M,8N,JA,JB,JC,JD,JE,JF,JG,JH,JI,JJ,JK,JL,JM,JN,JO,JP,JQ,JR,JS"),
+    ("Republic of Korea", "AS", 25, "HL", "6K,6L,6M,6N,D7,D8,D9,DS,DT,HL"),
+    ("Thailand", "AS", 26, "HS", "E2,HS"),
+    ("Vietnam", "AS", 26, "3W", "3W,XV"),
+    ("Philippines", "OC", 27, "DU", "4D,4E,4F,4G,4H,4I,DU,DV,DW,DX,DY,DZ"),
+    ("Singapore", "AS", 28, "9V", "9V,S6"),
+    ("West Malaysia", "AS", 28, "9M2", "9M2,9M4,9W2,9W4"),
+    ("East Malaysia", "OC", 28, "9M6", "9M6,9M8,9W6,9W8"),
+    ("Indonesia", "OC", 28, "YB", "7A,7B,7C,7D,7E,7F,7G,7H,7I,8A,8B,8C,8D,8E,8F,8G,8H,8I,JZ,PK,PL,PM,PN,PO,YB,YC,YD,YE,YF,YG,YH"),
+    ("Australia", "OC", 30, "VK", "AX,VH,VI,VJ,VK,VL,VM,VN,VZ"),
+    ("Christmas Island", "OC", 29, "VK9X", "VK9X"),
+    ("New Zealand", "OC", 32, "ZL", "ZL,ZM"),
+    ("Fiji", "OC", 32, "3D2", "3D2"),
+    ("French Polynesia", "OC", 32, "FO", "FO"),
+    ("Morocco", "AF", 33, "CN", "5C,5D,5E,5F,5G,CN"),
+    ("Egypt", "AF", 34, "SU", "6A,6B,SS,SU"),
+    ("Nigeria", "AF", 35, "5N", "5N,5O"),
+    ("Kenya", "AF", 37, "5Z", "5Y,5Z"),
+    ("South Africa", "AF", 38, "ZS", "H5,S4,S8,V9,ZR,ZS,ZT,ZU"),
+];
+
+// callsign suffixes that don't change the entity
+const IGNORED_SUFFIXES: &[&str] = &["P", "M", "MM", "AM", "QRP", "A", "B", "LH", "R"];
+
+#[derive(Debug, Clone, Default)]
+pub struct DxccTable {
+    entities: Vec<DxccEntity>,
+    prefixes: HashMap<String, usize>,
+    // full callsigns listed with '=' in cty.dat
+    exact_calls: HashMap<String, usize>,
+}
+
+impl DxccTable {
+    pub fn builtin() -> Self {
+        let mut table = DxccTable::default();
+        for (name, continent, cq_zone, prefix, prefixes) in BUILTIN_ENTITIES {
+            let index = table.entities.len();
+            table.entities.push(DxccEntity {
+                name: name.to_string(),
+                prefix: prefix.to_string(),
+                continent: continent.to_string(),
+                cq_zone: *cq_zone,
+            });
+            for alias in prefixes.split(',') {
+                table.prefixes.insert(alias.to_string(), index);
+            }
+        }
+        table
+    }
+
+    // loads the AD1C cty.dat format, per-prefix zone and continent overrides are folded into extra entities
+    pub fn load_cty_dat(path: &Path) -> io::Result<Self> {
+        let contents = fs::read_to_string(path)?;
+        let mut table = DxccTable::default();
+        let mut current: Option<usize> = None;
+        for line in contents.lines() {
+            if line.trim().is_empty() {
+                continue;
+            }
+            if !line.starts_with(' ') && !line.starts_with('\t') {
+                let fields: Vec<&str> = line.split(':').map(|field| field.trim()).collect();
+                if fields.len() < 8 {
+                    return Err(io::Error::new(io::ErrorKind::InvalidData, format!("Bad cty.dat entity line: {}", line)));
+                }
+                table.entities.push(DxccEntity {
+                    name: fields[0].to_string(),
+                    prefix: fields[7].trim_start_matches('*').to_string(),
+                    continent: fields[3].to_string(),
+                    cq_zone: fields[1].parse().unwrap_or(0),
+                });
+                current = Some(table.entities.len() - 1);
+                continue;
+            }
+            let index = match current {
+                Some(index) => index,
+                None => continue,
+            };
+            for alias in line.trim().trim_end_matches(';').split(',') {
+                let alias = alias.trim();
+                if alias.is_empty() {
+                    continue;
+                }
+                table.add_alias(alias, index);
+            }
+        }
+        Ok(table)
+    }
+
+    fn add_alias(&mut self, alias: &str, index: usize) {
+        let (exact, alias) = match alias.strip_prefix('=') {
+            Some(call) => (true, call),
+            None => (false, alias),
+        };
+        let end = alias.find(['(', '[', '<', '{', '~']).unwrap_or(alias.len());
+        let (name, overrides) = alias.split_at(end);
+        let mut entry = index;
+        let cq_zone = between(overrides, '(', ')').and_then(|zone| zone.parse::<u8>().ok());
+        let continent = between(overrides, '{', '}');
+        if cq_zone.is_some() || continent.is_some() {
+            let mut entity = self.entities[index].clone();
+            if let Some(cq_zone) = cq_zone {
+                entity.cq_zone = cq_zone;
+            }
+            if let Some(continent) = continent {
+                entity.continent = continent.to_string();
+            }
+            self.entities.push(entity);
+            entry = self.entities.len() - 1;
+        }
+        if exact {
+            self.exact_calls.insert(name.to_string(), entry);
+        } else {
+            self.prefixes.insert(name.to_string(), entry);
+        }
+    }
+
+    pub fn load(cty_file: Option<&str>) -> io::Result<Self> {
+        match cty_file {
+            Some(path) => DxccTable::load_cty_dat(Path::new(path)),
+            None => Ok(DxccTable::builtin()),
+        }
+    }
+
+    pub fn lookup(&self, call: &str) -> Option<&DxccEntity> {
+        let call = call.trim_start_matches('<').trim_end_matches('>').to_uppercase();
+        if let Some(index) = self.exact_calls.get(&call) {
+            return Some(&self.entities[*index]);
+        }
+        let base = prefix_part(&call);
+        (1..=base.len()).rev()
+            .filter_map(|len| base.get(..len))
+            .find_map(|prefix| self.prefixes.get(prefix))
+            .map(|index| &self.entities[*index])
+    }
+}
+
+fn between(text: &str, open: char, close: char) -> Option<&str> {
+    let start = text.find(open)? + 1;
+    let end = start + text[start..].find(close)?;
+    Some(&text[start..end])
+}
+
+// a trailing /4 moves the station to another call area of the same entity
+fn is_call_area(part: &str) -> bool {
+    part.len() == 1 && part.chars().all(|c| c.is_ascii_digit())
+}
+
+// picks the part of a compound call (KH6/K1ABC, K1ABC/P) that decides the entity
+fn prefix_part(call: &str) -> &str {
+    let parts: Vec<&str> = call
+        .split('/')
+        .filter(|part| !(part.is_empty() || IGNORED_SUFFIXES.contains(part) || is_call_area(part)))
+        .collect();
+    match parts.as_slice() {
+        [] => call,
+        [single] => single,
+        [first, second, ..] => if first.len() <= second.len() { first } else { second },
+    }
+}
@@ -0,0 +1,382 @@
+use maidenhead::grid_distance;
+use super::*;
+
+// display filter expressions, e.g. "cq and snr >= -15 and not (continent = NA or new = call)"
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Comparison {
+    Less,
+    LessEqual,
+    Equal,
+    GreaterEqual,
+    Greater,
+}
+
+impl Comparison {
+    fn parse(op: &str) -> Option<Self> {
+        match op {
+            "<" => Some(Comparison::Less),
+            "<=" => Some(Comparison::LessEqual),
+            "=" | "==" => Some(Comparison::Equal),
+            ">=" => Some(Comparison::GreaterEqual),
+            ">" => Some(Comparison::Greater),
+            _ => None,
+        }
+    }
+
+    fn compare(&self, left: f64, right: f64) -> bool {
+        match self {
+            Comparison::Less => left < right,
+            Comparison::LessEqual => left <= right,
+            Comparison::Equal => left == right,
+            Comparison::GreaterEqual => left >= right,
+            Comparison::Greater => left > right,
+        }
+    }
+}
+
+impl std::fmt::Display for Comparison {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let op = match self {
+            Comparison::Less => "<",
+            Comparison::LessEqual => "<=",
+            Comparison::Equal => "=",
+            Comparison::GreaterEqual => ">=",
+            Comparison::Greater => ">",
+        };
+        write!(f, "{}", op)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Filter {
+    And(Box<Filter>, Box<Filter>),
+    Or(Box<Filter>, Box<Filter>),
+    Not(Box<Filter>),
+    Cq,
+    ToMe,
+    QsoTraffic,
+    Snr(Comparison, i32),
+    Distance(Comparison, f64),
+    Continent(String),
+    Dxcc(String),
+    Band(String),
+    Needed,
+    New(WorkedCategory),
+    LowConfidence,
+    OffAir,
+}
+
+impl std::fmt::Display for Filter {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Filter::And(left, right) => write!(f, "({} and {})", left, right),
+            Filter::Or(left, right) => write!(f, "({} or {})", left, right),
+            Filter::Not(inner) => write!(f, "not {}", inner),
+            Filter::Cq => write!(f, "cq"),
+            Filter::ToMe => write!(f, "tome"),
+            Filter::QsoTraffic => write!(f, "qso"),
+            Filter::Snr(op, value) => write!(f, "snr {} {}", op, value),
+            Filter::Distance(op, value) => write!(f, "distance {} {}", op, value),
+            Filter::Continent(continent) => write!(f, "continent = {}", continent),
+            Filter::Dxcc(dxcc) => write!(f, "dxcc = {}", dxcc),
+            Filter::Band(band) => write!(f, "band = {}", band),
+            Filter::Needed => write!(f, "needed"),
+            Filter::New(category) => write!(f, "new = {}", category.to_string().to_lowercase()),
+            Filter::LowConfidence => write!(f, "lowconf"),
+            Filter::OffAir => write!(f, "offair"),
+        }
+    }
+}
+
+// everything a filter may look at for one decode
+pub struct FilterContext<'a> {
+    pub decode: &'a Decode,
+    pub message: Ft8Message,
+    pub own_call: Option<String>,
+    pub own_grid: Option<String>,
+    pub needs: Option<Needs>,
+}
+
+impl<'a> FilterContext<'a> {
+    pub fn new(decode: &'a Decode, app_state: &AppState) -> Self {
+        let message = parse_ft8_message(&decode.message);
+        let (own_call, own_grid) = match app_state.instances.lock().unwrap().status(&decode.id) {
+            Some(status) => (
+                non_empty(&status.de_call).map(strip_call),
+                non_empty(&status.de_grid).map(|grid| grid.to_string()),
+            ),
+            None => (None, None),
+        };
+        let needs = message.from_call().map(|call| {
+            let state = message.grid().and_then(|grid| grid_state(grid, app_state));
+            decode.needs(call, message.grid(), state, app_state)
+        });
+        Self { decode, message, own_call, own_grid, needs }
+    }
+
+    pub fn is_to_me(&self) -> bool {
+        match &self.own_call {
+            Some(own_call) => self.message.to_calls().contains(&own_call.as_str()),
+            None => false,
+        }
+    }
+
+    pub fn distance_km(&self) -> Option<f64> {
+        let own_grid = self.own_grid.as_ref()?;
+        grid_distance(own_grid, self.message.grid()?).ok()
+    }
+}
+
+// US state code for a grid, via the same reverse geocode the CQ line uses
+pub fn grid_state(grid: &str, app_state: &AppState) -> Option<&'static str> {
+    let (lon, lat) = maidenhead::grid_to_longlat(grid).ok()?;
+    let search_result = app_state.geocoder.search((lat, lon));
+    if search_result.record.cc == "US" {
+        us_state_code(&search_result.record.admin1)
+    } else {
+        None
+    }
+}
+
+impl Filter {
+    pub fn matches(&self, context: &FilterContext) -> bool {
+        match self {
+            Filter::And(left, right) => left.matches(context) && right.matches(context),
+            Filter::Or(left, right) => left.matches(context) || right.matches(context),
+            Filter::Not(inner) => !inner.matches(context),
+            Filter::Cq => matches!(context.message, Ft8Message::Cq { .. }),
+            Filter::ToMe => context.is_to_me(),
+            Filter::QsoTraffic => context.message.to_call().is_some() && !context.is_to_me(),
+            Filter::Snr(op, value) => op.compare(context.decode.snr as f64, *value as f64),
+            Filter::Distance(op, value) => context.distance_km().map(|distance| op.compare(distance, *value)).unwrap_or(false),
+            Filter::Continent(continent) => context.needs.as_ref()
+                .and_then(|needs| needs.entity.as_ref())
+                .map(|entity| entity.continent.eq_ignore_ascii_case(continent))
+                .unwrap_or(false),
+            Filter::Dxcc(dxcc) => context.needs.as_ref()
+                .and_then(|needs| needs.entity.as_ref())
+                .map(|entity| entity.prefix.eq_ignore_ascii_case(dxcc) || entity.name.eq_ignore_ascii_case(dxcc))
+                .unwrap_or(false),
+            Filter::Band(band) => context.decode.band.map(|decode_band| decode_band.eq_ignore_ascii_case(band)).unwrap_or(false),
+            Filter::Needed => context.needs.as_ref().map(|needs| needs.headline().is_some()).unwrap_or(false),
+            Filter::New(category) => context.needs.as_ref().map(|needs| needs.is_needed(*category)).unwrap_or(false),
+            Filter::LowConfidence => context.decode.low_confidence,
+            Filter::OffAir => context.decode.off_air,
+        }
+    }
+
+    pub fn parse(text: &str) -> Result<Filter, String> {
+        let tokens = tokenize(text)?;
+        let mut parser = Parser { tokens, position: 0 };
+        let filter = parser.expression()?;
+        match parser.peek() {
+            None => Ok(filter),
+            Some(token) => Err(format!("Unexpected '{}' in filter", token)),
+        }
+    }
+}
+
+fn tokenize(text: &str) -> Result<Vec<String>, String> {
+    let mut tokens = Vec::new();
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            c if c.is_whitespace() => {}
+            '(' | ')' => tokens.push(c.to_string()),
+            '<' | '>' | '=' | '!' => {
+                let mut op = c.to_string();
+                if chars.peek() == Some(&'=') {
+                    op.push(chars.next().unwrap());
+                }
+                tokens.push(op);
+            }
+            '"' => {
+                let mut word = String::new();
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some(c) => word.push(c),
+                        None => return Err("Unterminated quote in filter".to_string()),
+                    }
+                }
+                tokens.push(word);
+            }
+            _ => {
+                let mut word = c.to_string();
+                while let Some(next) = chars.peek() {
+                    if next.is_whitespace() || "()<>=!\"".contains(*next) {
+                        break;
+                    }
+                    word.push(chars.next().unwrap());
+                }
+                tokens.push(word);
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<String>,
+    position: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&str> {
+        self.tokens.get(self.position).map(|token| token.as_str())
+    }
+
+    fn next(&mut self) -> Option<String> {
+        let token = self.tokens.get(self.position).cloned();
+        self.position += 1;
+        token
+    }
+
+    fn keyword(&mut self, keyword: &str) -> bool {
+        if self.peek().map(|token| token.eq_ignore_ascii_case(keyword)).unwrap_or(false) {
+            self.position += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expression(&mut self) -> Result<Filter, String> {
+        let mut filter = self.term()?;
+        while self.keyword("or") {
+            filter = Filter::Or(Box::new(filter), Box::new(self.term()?));
+        }
+        Ok(filter)
+    }
+
+    fn term(&mut self) -> Result<Filter, String> {
+        let mut filter = self.factor()?;
+        while self.keyword("and") {
+            filter = Filter::And(Box::new(filter), Box::new(self.factor()?));
+        }
+        Ok(filter)
+    }
+
+    fn factor(&mut self) -> Result<Filter, String> {
+        if self.keyword("not") {
+            return Ok(Filter::Not(Box::new(self.factor()?)));
+        }
+        if self.keyword("(") {
+            let filter = self.expression()?;
+            if !self.keyword(")") {
+                return Err("Missing ')' in filter".to_string());
+            }
+            return Ok(filter);
+        }
+        self.predicate()
+    }
+
+    fn comparison(&mut self, name: &str) -> Result<(Comparison, String), String> {
+        let op = self.next().ok_or(format!("Missing comparison after '{}'", name))?;
+        let value = self.next().ok_or(format!("Missing value after '{} {}'", name, op))?;
+        if op == "!=" {
+            return Err(format!("Use 'not {} = {}' instead of '!='", name, value));
+        }
+        let op = Comparison::parse(&op).ok_or(format!("Unknown comparison '{}'", op))?;
+        Ok((op, value))
+    }
+
+    fn equality(&mut self, name: &str) -> Result<String, String> {
+        match self.comparison(name)? {
+            (Comparison::Equal, value) => Ok(value),
+            (op, _) => Err(format!("'{}' only supports '=', not '{}'", name, op)),
+        }
+    }
+
+    fn predicate(&mut self) -> Result<Filter, String> {
+        let name = self.next().ok_or("Filter ended unexpectedly")?.to_lowercase();
+        match name.as_str() {
+            "cq" => Ok(Filter::Cq),
+            "tome" => Ok(Filter::ToMe),
+            "qso" => Ok(Filter::QsoTraffic),
+            "needed" => Ok(Filter::Needed),
+            "lowconf" => Ok(Filter::LowConfidence),
+            "offair" => Ok(Filter::OffAir),
+            "snr" => {
+                let (op, value) = self.comparison(&name)?;
+                let value = value.parse::<i32>().map_err(|_| format!("Bad SNR '{}'", value))?;
+                Ok(Filter::Snr(op, value))
+            }
+            "distance" => {
+                let (op, value) = self.comparison(&name)?;
+                let value = value.parse::<f64>().map_err(|_| format!("Bad distance '{}'", value))?;
+                Ok(Filter::Distance(op, value))
+            }
+            "continent" => Ok(Filter::Continent(self.equality(&name)?.to_uppercase())),
+            "dxcc" => Ok(Filter::Dxcc(self.equality(&name)?)),
+            "band" => Ok(Filter::Band(self.equality(&name)?.to_lowercase())),
+            "new" => {
+                let value = self.equality(&name)?;
+                let category = WorkedCategory::parse(&value)
+                    .ok_or(format!("Unknown category '{}', use call, grid, dxcc, state, zone or continent", value))?;
+                Ok(Filter::New(category))
+            }
+            _ => Err(format!("Unknown filter term '{}'", name)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // parsed filters are compared through Display, which brackets every and/or
+    fn parsed(text: &str) -> String {
+        Filter::parse(text).map(|filter| filter.to_string()).unwrap_or_else(|e| e)
+    }
+
+    #[test]
+    fn parse_precedence() {
+        // and binds tighter than or, not applies to the next term only
+        assert_eq!(Filter::parse("cq or tome and needed"), Ok(Filter::Or(
+            Box::new(Filter::Cq),
+            Box::new(Filter::And(Box::new(Filter::ToMe), Box::new(Filter::Needed))),
+        )));
+        assert_eq!(Filter::parse("not cq and offair"), Ok(Filter::And(
+            Box::new(Filter::Not(Box::new(Filter::Cq))),
+            Box::new(Filter::OffAir),
+        )));
+        assert_eq!(parsed("(cq or tome) and needed"), "((cq or tome) and needed)");
+        assert_eq!(parsed("CQ AND snr >= -15 and NOT continent = na"), "((cq and snr >= -15) and not continent = NA)");
+    }
+
+    #[test]
+    fn parse_terms() {
+        assert_eq!(parsed("snr>-10"), "snr > -10");
+        assert_eq!(parsed("snr == 0"), "snr = 0");
+        assert_eq!(parsed("distance <= 1500.5"), "distance <= 1500.5");
+        assert_eq!(parsed("band = 20M"), "band = 20m");
+        assert_eq!(parsed("dxcc = \"United States\""), "dxcc = United States");
+        assert_eq!(parsed("new = Zone"), "new = zone");
+        assert_eq!(parsed("qso or lowconf"), "(qso or lowconf)");
+    }
+
+    #[test]
+    fn display_parses_back() {
+        for text in ["cq and not (tome or qso)", "snr < -20 or distance > 10000", "new = call and band = 6m", "needed and continent = OC"] {
+            let filter = Filter::parse(text).unwrap();
+            assert_eq!(Filter::parse(&filter.to_string()), Ok(filter), "{}", text);
+        }
+    }
+
+    #[test]
+    fn parse_errors() {
+        assert_eq!(parsed(""), "Filter ended unexpectedly");
+        assert_eq!(parsed("cq and"), "Filter ended unexpectedly");
+        assert_eq!(parsed("(cq or tome"), "Missing ')' in filter");
+        assert_eq!(parsed("cq tome"), "Unexpected 'tome' in filter");
+        assert_eq!(parsed("snr"), "Missing comparison after 'snr'");
+        assert_eq!(parsed("snr >="), "Missing value after 'snr >='");
+        assert_eq!(parsed("snr != -10"), "Use 'not snr = -10' instead of '!='");
+        assert_eq!(parsed("snr >= loud"), "Bad SNR 'loud'");
+        assert_eq!(parsed("band > 20m"), "'band' only supports '=', not '>'");
+        assert_eq!(parsed("dxcc = \"Japan"), "Unterminated quote in filter");
+        assert_eq!(parsed("weak"), "Unknown filter term 'weak'");
+        assert!(parsed("new = prefix").starts_with("Unknown category 'prefix'"));
+    }
+}
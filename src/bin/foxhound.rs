@@ -0,0 +1,172 @@
+use std::collections::HashMap;
+use chrono::{DateTime, Duration, Utc};
+use super::*;
+
+// special_operation_mode values WSJT-X sends in Status
+pub const FOX_MODE: u8 = 6;
+pub const HOUND_MODE: u8 = 7;
+// the fox and its streams stay below this offset, hounds call above it until the fox answers
+pub const FOX_HOUND_SPLIT_HZ: u32 = 1000;
+// a hound not heard calling for this long has given up
+const QUEUE_MINUTES: i64 = 3;
+// multi-stream messages with our call kept for the view
+const SIGHTINGS_KEPT: usize = 10;
+
+// why an offset breaks the Fox/Hound frequency rules, None when it's fine or the mode has none
+pub fn fox_hound_offset_problem(special_operation_mode: u8, tx_df: u32, calling: bool) -> Option<String> {
+    match special_operation_mode {
+        FOX_MODE if tx_df >= FOX_HOUND_SPLIT_HZ => Some(format!("Fox TX at {} Hz, the fox transmits below {} Hz", tx_df, FOX_HOUND_SPLIT_HZ)),
+        HOUND_MODE if calling && tx_df < FOX_HOUND_SPLIT_HZ => Some(format!("Hound TX at {} Hz, hounds call the fox above {} Hz", tx_df, FOX_HOUND_SPLIT_HZ)),
+        _ => None,
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct FoxSighting {
+    pub time: DateTime<Utc>,
+    pub message: String,
+    pub snr: i32,
+}
+
+// what a hound wants to know about the fox it's calling
+#[derive(Debug, Default)]
+pub struct HoundView {
+    pub active: bool,
+    pub fox_call: Option<String>,
+    pub own_call: Option<String>,
+    // the report the fox sent us, once it has picked us from the queue
+    pub report: Option<i32>,
+    // when the fox sent us RR73
+    pub worked: Option<DateTime<Utc>>,
+    // other hounds heard calling the fox, and when
+    callers: HashMap<String, DateTime<Utc>>,
+    pub sightings: Vec<FoxSighting>,
+    // the offset problem last logged, so each is logged once
+    warned: Option<String>,
+}
+
+impl HoundView {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn queue_depth(&self, now: DateTime<Utc>) -> usize {
+        self.callers.values().filter(|heard| now - **heard <= Duration::minutes(QUEUE_MINUTES)).count()
+    }
+
+    fn set_fox(&mut self, fox_call: Option<String>) {
+        if fox_call.is_some() && fox_call != self.fox_call {
+            let own_call = self.own_call.take();
+            *self = Self { active: true, fox_call, own_call, ..Self::default() };
+        }
+    }
+
+    // one line for the TUI status bar
+    pub fn summary(&self, now: DateTime<Utc>) -> Option<String> {
+        if !self.active {
+            return None;
+        }
+        let fox = self.fox_call.as_deref().unwrap_or("-");
+        let progress = match (self.worked, self.report) {
+            (Some(_), _) => "worked".to_string(),
+            (None, Some(report)) => format!("report {:+}", report),
+            (None, None) => "not yet".to_string(),
+        };
+        Some(format!("Fox {}: {}, queue {}", fox, progress, self.queue_depth(now)))
+    }
+}
+
+impl std::fmt::Display for HoundView {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        if !self.active {
+            return writeln!(f, "Not in Hound mode");
+        }
+        let now = Utc::now();
+        writeln!(f, "Fox: {}", self.fox_call.as_deref().unwrap_or("none, set the DX call to the fox"))?;
+        match (self.worked, self.report) {
+            (Some(worked), _) => writeln!(f, "Worked at {}", worked.format("%H:%M:%S"))?,
+            (None, Some(report)) => writeln!(f, "Fox sent us {:+}, waiting for RR73", report)?,
+            (None, None) => writeln!(f, "Not worked yet")?,
+        }
+        writeln!(f, "Hounds calling: {}", self.queue_depth(now))?;
+        for sighting in &self.sightings {
+            writeln!(f, "{} {:>3} dB  {}", sighting.time.format("%H:%M:%S"), sighting.snr, sighting.message)?;
+        }
+        Ok(())
+    }
+}
+
+// keeps the fox call and our own from Status and logs offsets that break the Fox/Hound rules
+pub fn fox_hound_status(status: &Status, app_state: &AppState) {
+    let mut hound = app_state.hound.lock().unwrap();
+    hound.active = status.special_operation_mode == HOUND_MODE;
+    if hound.active {
+        hound.own_call = non_empty(&status.de_call).map(strip_call);
+        hound.set_fox(non_empty(&status.dx_call).map(strip_call));
+    }
+    // once the fox has answered, WSJT-X moves the hound to the fox's offset
+    let calling = status.tx_enabled && hound.report.is_none() && hound.worked.is_none();
+    let problem = fox_hound_offset_problem(status.special_operation_mode, status.tx_df, calling);
+    if problem == hound.warned {
+        return;
+    }
+    hound.warned = problem.clone();
+    drop(hound);
+    if let Some(problem) = problem {
+        app_state.log(&problem);
+    }
+}
+
+// follows the fox's streams and the hounds calling it
+pub fn hound_decode(context: &FilterContext, app_state: &AppState) {
+    if context.decode.off_air {
+        return;
+    }
+    let mut hound = app_state.hound.lock().unwrap();
+    let fox_call = match (hound.active, hound.fox_call.clone()) {
+        (true, Some(fox_call)) => fox_call,
+        _ => return,
+    };
+    let from = match context.message.from_call() {
+        Some(from) => from,
+        None => return,
+    };
+    let now = Utc::now();
+    if from != fox_call {
+        if context.message.to_call() == Some(fox_call.as_str()) && context.own_call.as_deref() != Some(from) {
+            hound.callers.insert(from.to_string(), now);
+        }
+        return;
+    }
+    let own_call = context.own_call.clone();
+    if matches!(context.message, Ft8Message::FoxMulti { .. }) && context.is_to_me() {
+        hound.sightings.push(FoxSighting { time: now, message: context.decode.message.clone(), snr: context.decode.snr });
+        if hound.sightings.len() > SIGHTINGS_KEPT {
+            hound.sightings.remove(0);
+        }
+    }
+    let mut worked = false;
+    for stream in context.message.streams() {
+        // a hound the fox has picked has left the queue
+        let to = match stream.to_call() {
+            Some(to) => to.to_string(),
+            None => continue,
+        };
+        hound.callers.remove(&to);
+        if own_call.as_deref() != Some(to.as_str()) {
+            continue;
+        }
+        match stream {
+            Ft8Message::Report { report, .. } => hound.report = Some(report),
+            Ft8Message::RogerRoger { .. } | Ft8Message::SeventyThree { .. } if hound.worked.is_none() => {
+                hound.worked = Some(now);
+                worked = true;
+            }
+            _ => {}
+        }
+    }
+    drop(hound);
+    if worked {
+        app_state.log(&format!("Hound: {} has worked us", fox_call));
+    }
+}
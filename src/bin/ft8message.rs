@@ -0,0 +1,206 @@
+// parsing of the free-form text carried in Decode::message / Status::tx_message
+// into the standard FT8/FT4 QSO sequence.
+use super::*;
+#[derive(Debug, Clone, PartialEq)]
+pub enum Ft8Message {
+    Cq { modifier: Option<String>, call: String, grid: Option<String> },
+    Grid { to: String, from: String, grid: String },
+    Report { to: String, from: String, report: i32 },
+    RogerReport { to: String, from: String, report: i32 },
+    RogerRoger { to: String, from: String, rr73: bool },
+    SeventyThree { to: String, from: String },
+    // a fox in Fox/Hound mode finishing with one hound and sending the next their report in the same transmission
+    FoxMulti { rr73_to: String, report_to: String, from: String, report: i32 },
+    Other(String),
+}
+
+impl std::fmt::Display for Ft8Message {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Ft8Message::Cq { modifier: Some(modifier), call, .. } => write!(f, "CQ {} de {}", modifier, call),
+            Ft8Message::Cq { call, .. } => write!(f, "CQ de {}", call),
+            Ft8Message::Grid { to, from, grid } => write!(f, "{} -> {}: grid {}", from, to, grid),
+            Ft8Message::Report { to, from, report } => write!(f, "{} -> {}: report {:+}", from, to, report),
+            Ft8Message::RogerReport { to, from, report } => write!(f, "{} -> {}: R{:+}", from, to, report),
+            Ft8Message::RogerRoger { to, from, rr73 } => write!(f, "{} -> {}: {}", from, to, if *rr73 { "RR73" } else { "RRR" }),
+            Ft8Message::SeventyThree { to, from } => write!(f, "{} -> {}: 73", from, to),
+            Ft8Message::FoxMulti { rr73_to, report_to, from, report } => write!(f, "{} -> {}: RR73, {}: report {:+}", from, rr73_to, report_to, report),
+            Ft8Message::Other(text) => write!(f, "{}", text),
+        }
+    }
+}
+
+impl Ft8Message {
+    // the station that sent the message, if the message follows the standard sequence
+    pub fn from_call(&self) -> Option<&str> {
+        match self {
+            Ft8Message::Cq { call, .. } => Some(call),
+            Ft8Message::Grid { from, .. }
+            | Ft8Message::Report { from, .. }
+            | Ft8Message::RogerReport { from, .. }
+            | Ft8Message::RogerRoger { from, .. }
+            | Ft8Message::SeventyThree { from, .. }
+            | Ft8Message::FoxMulti { from, .. } => Some(from),
+            Ft8Message::Other(_) => None,
+        }
+    }
+
+    // the station the message is addressed to, None for CQs and free text,
+    // for a fox message it's the hound being sent a report, to_calls has both
+    pub fn to_call(&self) -> Option<&str> {
+        match self {
+            Ft8Message::Grid { to, .. }
+            | Ft8Message::Report { to, .. }
+            | Ft8Message::RogerReport { to, .. }
+            | Ft8Message::RogerRoger { to, .. }
+            | Ft8Message::SeventyThree { to, .. } => Some(to),
+            Ft8Message::FoxMulti { report_to, .. } => Some(report_to),
+            _ => None,
+        }
+    }
+
+    pub fn to_calls(&self) -> Vec<&str> {
+        match self {
+            Ft8Message::FoxMulti { rr73_to, report_to, .. } => vec![rr73_to, report_to],
+            _ => self.to_call().into_iter().collect(),
+        }
+    }
+
+    // a fox message split into the standard message each hound would have received on its own
+    pub fn streams(&self) -> Vec<Ft8Message> {
+        match self {
+            Ft8Message::FoxMulti { rr73_to, report_to, from, report } => vec![
+                Ft8Message::RogerRoger { to: rr73_to.clone(), from: from.clone(), rr73: true },
+                Ft8Message::Report { to: report_to.clone(), from: from.clone(), report: *report },
+            ],
+            _ => vec![self.clone()],
+        }
+    }
+
+    pub fn grid(&self) -> Option<&str> {
+        match self {
+            Ft8Message::Cq { grid, .. } => grid.as_deref(),
+            Ft8Message::Grid { grid, .. } => Some(grid),
+            _ => None,
+        }
+    }
+}
+
+// hashed calls are sent as <K1ABC>, strip the brackets so they match the plain call
+pub fn strip_call(call: &str) -> String {
+    call.trim_start_matches('<').trim_end_matches('>').to_string()
+}
+
+// FT8 messages carry 4 character grids, and RR73 looks like one
+fn is_grid(text: &str) -> bool {
+    text.len() == 4 && text != "RR73" && valid_grid(text)
+}
+
+fn parse_report(text: &str) -> Option<i32> {
+    if !(text.starts_with('+') || text.starts_with('-')) || text.len() < 2 {
+        return None;
+    }
+    text.parse::<i32>().ok()
+}
+
+fn parse_exchange(to: &str, from: &str, exchange: Option<&str>) -> Ft8Message {
+    let to = strip_call(to);
+    let from = strip_call(from);
+    match exchange {
+        None => Ft8Message::Grid { to, from, grid: String::new() },
+        Some("RR73") => Ft8Message::RogerRoger { to, from, rr73: true },
+        Some("RRR") => Ft8Message::RogerRoger { to, from, rr73: false },
+        Some("73") => Ft8Message::SeventyThree { to, from },
+        Some(grid) if is_grid(grid) => Ft8Message::Grid { to, from, grid: grid.to_string() },
+        Some(text) => {
+            if let Some(report) = parse_report(text) {
+                Ft8Message::Report { to, from, report }
+            } else if let Some(report) = text.strip_prefix('R').and_then(parse_report) {
+                Ft8Message::RogerReport { to, from, report }
+            } else {
+                Ft8Message::Other(format!("{} {} {}", to, from, text))
+            }
+        }
+    }
+}
+
+pub fn parse_ft8_message(message: &str) -> Ft8Message {
+    let parts: Vec<&str> = message.split_whitespace().collect();
+    if parts.first() == Some(&"CQ") {
+        return match parts.len() {
+            2 => Ft8Message::Cq { modifier: None, call: strip_call(parts[1]), grid: None },
+            3 if is_grid(parts[2]) => Ft8Message::Cq { modifier: None, call: strip_call(parts[1]), grid: Some(parts[2].to_string()) },
+            3 => Ft8Message::Cq { modifier: Some(parts[1].to_string()), call: strip_call(parts[2]), grid: None },
+            4 => Ft8Message::Cq { modifier: Some(parts[1].to_string()), call: strip_call(parts[2]), grid: Some(parts[3].to_string()) },
+            _ => Ft8Message::Other(message.to_string()),
+        };
+    }
+    match parts.len() {
+        2 => parse_exchange(parts[0], parts[1], None),
+        3 => parse_exchange(parts[0], parts[1], Some(parts[2])),
+        // "K1ABC RR73; W9XYZ <KH1/KH7Z> -08", the fox's own call is always hashed
+        5 if parts[1] == "RR73;" => match parse_report(parts[4]) {
+            Some(report) => Ft8Message::FoxMulti {
+                rr73_to: strip_call(parts[0]),
+                report_to: strip_call(parts[2]),
+                from: strip_call(parts[3]),
+                report,
+            },
+            None => Ft8Message::Other(message.to_string()),
+        },
+        _ => Ft8Message::Other(message.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn s(text: &str) -> String {
+        text.to_string()
+    }
+
+    #[test]
+    fn parse_messages() {
+        let cases = [
+            ("CQ K1ABC", Ft8Message::Cq { modifier: None, call: s("K1ABC"), grid: None }),
+            ("CQ K1ABC FN42", Ft8Message::Cq { modifier: None, call: s("K1ABC"), grid: Some(s("FN42")) }),
+            ("CQ DX K1ABC", Ft8Message::Cq { modifier: Some(s("DX")), call: s("K1ABC"), grid: None }),
+            ("CQ POTA <K1ABC/P> FN42", Ft8Message::Cq { modifier: Some(s("POTA")), call: s("K1ABC/P"), grid: Some(s("FN42")) }),
+            ("KE8TKS K1ABC", Ft8Message::Grid { to: s("KE8TKS"), from: s("K1ABC"), grid: String::new() }),
+            ("KE8TKS K1ABC FN42", Ft8Message::Grid { to: s("KE8TKS"), from: s("K1ABC"), grid: s("FN42") }),
+            ("KE8TKS K1ABC -12", Ft8Message::Report { to: s("KE8TKS"), from: s("K1ABC"), report: -12 }),
+            ("KE8TKS K1ABC +05", Ft8Message::Report { to: s("KE8TKS"), from: s("K1ABC"), report: 5 }),
+            ("KE8TKS K1ABC R-07", Ft8Message::RogerReport { to: s("KE8TKS"), from: s("K1ABC"), report: -7 }),
+            ("KE8TKS K1ABC RRR", Ft8Message::RogerRoger { to: s("KE8TKS"), from: s("K1ABC"), rr73: false }),
+            // RR73 is also a valid grid square, it is never read as one
+            ("KE8TKS K1ABC RR73", Ft8Message::RogerRoger { to: s("KE8TKS"), from: s("K1ABC"), rr73: true }),
+            ("<KE8TKS> K1ABC 73", Ft8Message::SeventyThree { to: s("KE8TKS"), from: s("K1ABC") }),
+            ("K1ABC RR73; KE8TKS <KH1/KH7Z> -08", Ft8Message::FoxMulti { rr73_to: s("K1ABC"), report_to: s("KE8TKS"), from: s("KH1/KH7Z"), report: -8 }),
+            ("K1ABC RR73; KE8TKS <KH1/KH7Z> +02", Ft8Message::FoxMulti { rr73_to: s("K1ABC"), report_to: s("KE8TKS"), from: s("KH1/KH7Z"), report: 2 }),
+            ("K1ABC RR73; KE8TKS <KH1/KH7Z> FN42", Ft8Message::Other(s("K1ABC RR73; KE8TKS <KH1/KH7Z> FN42"))),
+            ("KE8TKS K1ABC TNX", Ft8Message::Other(s("KE8TKS K1ABC TNX"))),
+            ("TNX BOB 73 GL", Ft8Message::Other(s("TNX BOB 73 GL"))),
+            ("CQ", Ft8Message::Other(s("CQ"))),
+            ("", Ft8Message::Other(String::new())),
+        ];
+        for (text, expected) in cases {
+            assert_eq!(parse_ft8_message(text), expected, "{}", text);
+        }
+    }
+
+    #[test]
+    fn fox_streams() {
+        let message = parse_ft8_message("K1ABC RR73; KE8TKS <KH1/KH7Z> -08");
+        assert_eq!(message.to_call(), Some("KE8TKS"));
+        assert_eq!(message.to_calls(), vec!["K1ABC", "KE8TKS"]);
+        assert_eq!(message.streams(), vec![
+            Ft8Message::RogerRoger { to: s("K1ABC"), from: s("KH1/KH7Z"), rr73: true },
+            Ft8Message::Report { to: s("KE8TKS"), from: s("KH1/KH7Z"), report: -8 },
+        ]);
+        // a standard message is its own only stream
+        let report = parse_ft8_message("KE8TKS K1ABC -12");
+        assert_eq!(report.to_calls(), vec!["KE8TKS"]);
+        assert_eq!(report.streams(), vec![report.clone()]);
+    }
+}
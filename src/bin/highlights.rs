@@ -0,0 +1,219 @@
+use std::collections::HashMap;
+use chrono::{DateTime, Duration, Utc};
+use serde_derive::Deserialize;
+use super::*;
+
+// a call not decoded for this long has its highlight cleared and is forgotten
+const HIGHLIGHT_MINUTES: i64 = 60;
+
+// colours are "#rrggbb", an empty string leaves that part of the decode as WSJT-X draws it
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct HighlightStyle {
+    pub background: String,
+    pub foreground: String,
+}
+
+impl HighlightStyle {
+    fn new(background: &str, foreground: &str) -> Self {
+        Self { background: background.to_string(), foreground: foreground.to_string() }
+    }
+}
+
+impl Default for HighlightStyle {
+    fn default() -> Self {
+        Self::new("", "")
+    }
+}
+
+// "wsjtx_highlights" in config.json, the colours WSJT-X shows each category of call in
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct HighlightConfig {
+    pub enabled: bool,
+    pub watched: HighlightStyle,
+    pub new_dxcc: HighlightStyle,
+    pub needed_grid: HighlightStyle,
+}
+
+impl Default for HighlightConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            watched: HighlightStyle::new("#ffffff", "#000000"),
+            new_dxcc: HighlightStyle::new("#ff0000", "#ffffff"),
+            needed_grid: HighlightStyle::new("#ffff00", "#000000"),
+        }
+    }
+}
+
+impl HighlightConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        for (name, style) in [("watched", &self.watched), ("new_dxcc", &self.new_dxcc), ("needed_grid", &self.needed_grid)] {
+            for colour in [&style.background, &style.foreground] {
+                if !colour.is_empty() && parse_colour(colour).is_none() {
+                    return Err(format!("wsjtx_highlights {}: '{}' is not a \"#rrggbb\" colour", name, colour));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn style(&self, category: HighlightCategory) -> &HighlightStyle {
+        match category {
+            HighlightCategory::Watched => &self.watched,
+            HighlightCategory::NewDxcc => &self.new_dxcc,
+            HighlightCategory::NeededGrid => &self.needed_grid,
+        }
+    }
+}
+
+// in priority order, a call gets the first category that applies
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum HighlightCategory {
+    Watched,
+    NewDxcc,
+    NeededGrid,
+}
+
+fn category(watched: bool, needs: Option<&Needs>) -> Option<HighlightCategory> {
+    if watched {
+        Some(HighlightCategory::Watched)
+    } else if needs.map(|needs| needs.is_needed(WorkedCategory::Dxcc)).unwrap_or(false) {
+        Some(HighlightCategory::NewDxcc)
+    } else if needs.map(|needs| needs.is_needed(WorkedCategory::Grid)).unwrap_or(false) {
+        Some(HighlightCategory::NeededGrid)
+    } else {
+        None
+    }
+}
+
+// what we last asked an instance to show for a call, and enough of the decode to work out its needs again
+#[derive(Debug, Clone)]
+struct SentHighlight {
+    category: HighlightCategory,
+    grid: Option<String>,
+    band: Option<&'static str>,
+    mode: Option<&'static str>,
+    heard: DateTime<Utc>,
+}
+
+// the HighlightCallsignIn state of each WSJT-X instance, keyed by (instance id, call)
+#[derive(Debug, Default)]
+pub struct WsjtxHighlights {
+    sent: HashMap<(String, String), SentHighlight>,
+}
+
+fn send_highlight(app_state: &AppState, id: &str, call: &str, style: &HighlightStyle) {
+    let highlight = HighlightCallsignIn {
+        message_type: 13,
+        id: id.to_string(),
+        callsign: call.to_string(),
+        background_color: style.background.clone(),
+        foreground_color: style.foreground.clone(),
+        highlight_last: false,
+    };
+    if let Err(e) = send_to_instance(app_state, id, encode_highlight_callsign_in(&highlight)) {
+        app_state.log(&format!("Could not highlight {} in {}: {}", call, id, e));
+    }
+}
+
+impl WsjtxHighlights {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // called for every decode, only changes of category go out to WSJT-X
+    pub fn update(&mut self, decode: &Decode, call: &str, grid: Option<&str>, needs: Option<&Needs>, app_state: &AppState) {
+        let config = app_state.config();
+        if !config.wsjtx_highlights.enabled {
+            return;
+        }
+        let now = Utc::now();
+        self.expire(now, app_state);
+        let key = (decode.id.clone(), call.to_string());
+        let wanted = category(app_state.alerts.lock().unwrap().watched(call), needs);
+        let previous = self.sent.get_mut(&key).map(|sent| {
+            sent.heard = now;
+            sent.category
+        });
+        if wanted == previous {
+            return;
+        }
+        match wanted {
+            Some(category) => {
+                send_highlight(app_state, &decode.id, call, config.wsjtx_highlights.style(category));
+                self.sent.insert(key, SentHighlight {
+                    category,
+                    grid: grid.map(|grid| grid.to_string()),
+                    band: decode.band,
+                    mode: decode.mode_name(),
+                    heard: now,
+                });
+            }
+            None => {
+                send_highlight(app_state, &decode.id, call, &HighlightStyle::default());
+                self.sent.remove(&key);
+            }
+        }
+    }
+
+    // calls that have gone quiet are cleared in WSJT-X too, so it doesn't keep a highlight we no longer track
+    fn expire(&mut self, now: DateTime<Utc>, app_state: &AppState) {
+        let expired: Vec<(String, String)> = self.sent.iter()
+            .filter(|(_, sent)| now - sent.heard > Duration::minutes(HIGHLIGHT_MINUTES))
+            .map(|(key, _)| key.clone())
+            .collect();
+        for key in expired {
+            send_highlight(app_state, &key.0, &key.1, &HighlightStyle::default());
+            self.sent.remove(&key);
+        }
+    }
+
+    // after a QSO is logged or the watch list changes, highlights that no longer apply are changed or cleared
+    pub fn refresh(&mut self, app_state: &AppState) {
+        let config = app_state.config();
+        let mut changed = Vec::new();
+        {
+            let alerts = app_state.alerts.lock().unwrap();
+            let worked_before = app_state.worked_before.lock().unwrap();
+            for ((id, call), sent) in &self.sent {
+                let needs = worked_before.needs(call, sent.grid.as_deref(), None, sent.band, sent.mode, &app_state.dxcc);
+                let wanted = if config.wsjtx_highlights.enabled { category(alerts.watched(call), Some(&needs)) } else { None };
+                if wanted != Some(sent.category) {
+                    changed.push((id.clone(), call.clone(), wanted));
+                }
+            }
+        }
+        for (id, call, wanted) in changed {
+            let key = (id, call);
+            match wanted {
+                Some(category) => {
+                    send_highlight(app_state, &key.0, &key.1, config.wsjtx_highlights.style(category));
+                    if let Some(sent) = self.sent.get_mut(&key) {
+                        sent.category = category;
+                    }
+                }
+                None => {
+                    send_highlight(app_state, &key.0, &key.1, &HighlightStyle::default());
+                    self.sent.remove(&key);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn watch_list_wildcards() {
+        let alerts = Alerts::new(&[], &["VK9*".to_string(), "k1abc".to_string()]).unwrap();
+        for (call, watched) in [("VK9XY", true), ("vk9dx", true), ("VK2ABC", false), ("K1ABC", true), ("K1ABCD", false)] {
+            assert_eq!(alerts.watched(call), watched, "{}", call);
+        }
+        assert_eq!(category(alerts.watched("VK9XY"), None), Some(HighlightCategory::Watched));
+        assert_eq!(category(alerts.watched("VK2ABC"), None), None);
+    }
+}
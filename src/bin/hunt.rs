@@ -0,0 +1,309 @@
+use chrono::{DateTime, Duration, Utc};
+use serde_derive::Deserialize;
+use super::*;
+
+// "hunt" in config.json, the wanted stations in priority order and how long to keep calling each
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct HuntConfig {
+    pub targets: Vec<String>,
+    // calls without an answer before giving up, the last one is still sent
+    pub retry_cycles: u32,
+}
+
+impl Default for HuntConfig {
+    fn default() -> Self {
+        Self { targets: Vec::new(), retry_cycles: 4 }
+    }
+}
+
+// the target we have sent a Reply for, followed through WSJT-X's Status until it ends one way or another
+#[derive(Debug, Clone)]
+pub struct HuntAttempt {
+    pub call: String,
+    pub id: String,
+    pub started: DateTime<Utc>,
+    pub tx_periods: u32,
+    // they came back to us, from here on it's an ordinary QSO
+    pub answered: bool,
+    was_transmitting: bool,
+    // WSJT-X has picked up the Reply and set them as the DX call
+    dx_call_set: bool,
+}
+
+impl std::fmt::Display for HuntAttempt {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let progress = if self.answered { "answered".to_string() } else { format!("{} calls", self.tx_periods) };
+        write!(f, "Calling {} since {}, {}", self.call, self.started.format("%H:%M:%S"), progress)
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Hunt {
+    // best first, a target that didn't answer goes to the back
+    pub targets: Vec<String>,
+    pub attempt: Option<HuntAttempt>,
+}
+
+impl Hunt {
+    pub fn new(config: &HuntConfig) -> Self {
+        let mut hunt = Self::default();
+        hunt.add(&config.targets);
+        hunt
+    }
+
+    pub fn add(&mut self, calls: &[String]) {
+        for call in calls {
+            let call = call.trim().to_uppercase();
+            if !call.is_empty() && !self.targets.contains(&call) {
+                self.targets.push(call);
+            }
+        }
+    }
+
+    pub fn remove(&mut self, call: &str) -> bool {
+        let before = self.targets.len();
+        self.targets.retain(|target| target != call);
+        before != self.targets.len()
+    }
+
+    fn is_target(&self, call: &str) -> bool {
+        self.targets.iter().any(|target| target == call)
+    }
+
+    fn give_up(&mut self) -> Option<HuntAttempt> {
+        let attempt = self.attempt.take()?;
+        if self.remove(&attempt.call) {
+            self.targets.push(attempt.call.clone());
+        }
+        Some(attempt)
+    }
+}
+
+impl std::fmt::Display for Hunt {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        if self.targets.is_empty() {
+            return writeln!(f, "No targets");
+        }
+        for (index, target) in self.targets.iter().enumerate() {
+            writeln!(f, "{:>2}. {}", index + 1, target)?;
+        }
+        if let Some(attempt) = &self.attempt {
+            writeln!(f, "{}", attempt)?;
+        }
+        Ok(())
+    }
+}
+
+fn send_hunt_halt(id: &str, auto_tx_only: bool, app_state: &AppState) {
+    let halt_tx = HaltTx { message_type: 8, id: id.to_string(), auto_tx_only };
+    if let Err(e) = send_to_instance(app_state, id, encode_halt_tx(&halt_tx)) {
+        app_state.log(&format!("Hunt halt TX failed: {}", e));
+    }
+}
+
+// a target calling CQ or signing off with someone else can be called
+fn target_free(context: &FilterContext) -> bool {
+    match &context.message {
+        Ft8Message::Cq { .. } => true,
+        Ft8Message::RogerRoger { rr73: true, .. } | Ft8Message::SeventyThree { .. } => !context.is_to_me(),
+        _ => false,
+    }
+}
+
+// a free target is called, a target working someone else means our call was missed
+pub fn hunt_decode(context: &FilterContext, app_state: &AppState) {
+    let decode = context.decode;
+    if !decode.new || decode.off_air {
+        return;
+    }
+    let call = match context.message.from_call() {
+        Some(call) => call,
+        None => return,
+    };
+    let mut hunt = app_state.hunt.lock().unwrap();
+    if !hunt.is_target(call) {
+        return;
+    }
+    let own_call = context.own_call.as_deref();
+    let free = target_free(context);
+    match hunt.attempt.as_mut() {
+        Some(attempt) if attempt.call == call => {
+            if context.is_to_me() {
+                attempt.answered = true;
+            } else if !attempt.answered && context.message.to_call().is_some() && context.message.to_call() != own_call && !free {
+                let attempt = hunt.give_up().unwrap();
+                drop(hunt);
+                send_hunt_halt(&attempt.id, false, app_state);
+                app_state.log(&format!("Hunt: {} answered {}, giving up", call, context.message.to_call().unwrap_or("")));
+            }
+        }
+        Some(_) => {}
+        None if free => {
+            if app_state.qso_tracker.lock().unwrap().own_qso_in_progress(&context.decode.id, Utc::now()) {
+                return;
+            }
+            let reply = Reply::from_decode(decode, 0);
+            match send_to_instance(app_state, &decode.id, encode_reply(&reply)) {
+                Ok(()) => {
+                    hunt.attempt = Some(HuntAttempt {
+                        call: call.to_string(),
+                        id: decode.id.clone(),
+                        started: Utc::now(),
+                        tx_periods: 0,
+                        answered: false,
+                        was_transmitting: false,
+                        dx_call_set: false,
+                    });
+                    drop(hunt);
+                    app_state.log(&format!("Hunt: calling {} after {}", call, decode.message));
+                }
+                Err(e) => {
+                    drop(hunt);
+                    app_state.log(&format!("Hunt reply failed: {}", e));
+                }
+            }
+        }
+        None => {}
+    }
+}
+
+// follows what WSJT-X does with the call, counting transmit periods and ending the attempt when it's over
+pub fn update_hunt(status: &Status, app_state: &AppState) {
+    let retry_cycles = app_state.config().hunt.retry_cycles;
+    let mut hunt = app_state.hunt.lock().unwrap();
+    let attempt = match hunt.attempt.as_mut() {
+        Some(attempt) if attempt.id == status.id => attempt,
+        _ => return,
+    };
+    let dx_call = non_empty(&status.dx_call).map(strip_call);
+    let on_target = dx_call.as_deref() == Some(attempt.call.as_str());
+    if on_target {
+        attempt.dx_call_set = true;
+    }
+    let started_transmitting = status.transmitting && !attempt.was_transmitting;
+    attempt.was_transmitting = status.transmitting;
+    let tr_period = if status.tr_period > 0 && status.tr_period != u32::MAX { status.tr_period } else { 15 };
+    let expiry = Duration::seconds(tr_period as i64) * (2 * retry_cycles as i32 + 4);
+    let ended = if attempt.dx_call_set && !on_target {
+        Some("the DX call was changed in WSJT-X")
+    } else if attempt.answered && !status.tx_enabled && !status.transmitting
+        && !app_state.qso_tracker.lock().unwrap().own_qso_in_progress(&status.id, Utc::now()) {
+        Some("the QSO is over")
+    } else if attempt.answered {
+        None
+    } else if !attempt.dx_call_set && Utc::now() - attempt.started > expiry {
+        Some("WSJT-X didn't take the call")
+    } else if attempt.tx_periods > 0 && !status.tx_enabled && !status.transmitting {
+        Some("TX was disabled")
+    } else {
+        None
+    };
+    if let Some(reason) = ended {
+        let attempt = hunt.attempt.take().unwrap();
+        drop(hunt);
+        app_state.log(&format!("Hunt: stopped calling {}, {}", attempt.call, reason));
+        return;
+    }
+    if started_transmitting && on_target && !attempt.answered {
+        attempt.tx_periods += 1;
+        if attempt.tx_periods >= retry_cycles {
+            let attempt = hunt.give_up().unwrap();
+            drop(hunt);
+            // auto TX off lets the call that has just started finish
+            send_hunt_halt(&status.id, true, app_state);
+            app_state.log(&format!("Hunt: no answer from {} after {} calls, moving on", attempt.call, retry_cycles));
+        }
+    }
+}
+
+// a logged target has been found, the attempt is over
+pub fn hunt_logged(call: &str, app_state: &AppState) {
+    let mut hunt = app_state.hunt.lock().unwrap();
+    if hunt.attempt.as_ref().map(|attempt| attempt.call == call).unwrap_or(false) {
+        hunt.attempt = None;
+    }
+    if hunt.remove(call) {
+        drop(hunt);
+        app_state.log(&format!("Hunt: worked {}", call));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn free_targets() {
+        let decode = Decode::default();
+        for (message, free) in [
+            ("CQ K9XYZ EN52", true),
+            ("CQ DX K9XYZ EN52", true),
+            ("W1AW K9XYZ RR73", true),
+            ("W1AW K9XYZ 73", true),
+            ("K1ABC K9XYZ RR73", false),
+            ("K1ABC K9XYZ 73", false),
+            ("W1AW K9XYZ RRR", false),
+            ("W1AW K9XYZ -05", false),
+            ("W1AW K9XYZ R-05", false),
+            ("W1AW K9XYZ EN52", false),
+        ] {
+            let context = FilterContext {
+                decode: &decode,
+                message: parse_ft8_message(message),
+                own_call: Some("K1ABC".to_string()),
+                own_grid: None,
+                needs: None,
+            };
+            assert_eq!(target_free(&context), free, "{}", message);
+        }
+    }
+
+    fn transmitting(dx_call: &str, transmitting: bool) -> Status {
+        Status { id: "WSJT-X".to_string(), dx_call: dx_call.to_string(), tx_enabled: true, transmitting, ..Default::default() }
+    }
+
+    #[test]
+    fn retry_cutoff() {
+        let app_state = AppState::for_test(r#"{"hunt": {"targets": ["K9XYZ", "W1AW"], "retry_cycles": 2}}"#);
+        app_state.hunt.lock().unwrap().attempt = Some(HuntAttempt {
+            call: "K9XYZ".to_string(),
+            id: "WSJT-X".to_string(),
+            started: Utc::now(),
+            tx_periods: 0,
+            answered: false,
+            was_transmitting: false,
+            dx_call_set: false,
+        });
+        // each call is counted once, when it starts
+        for (status, tx_periods) in [
+            (transmitting("K9XYZ", true), 1),
+            (transmitting("K9XYZ", true), 1),
+            (transmitting("K9XYZ", false), 1),
+        ] {
+            update_hunt(&status, &app_state);
+            assert_eq!(app_state.hunt.lock().unwrap().attempt.as_ref().map(|attempt| attempt.tx_periods), Some(tx_periods));
+        }
+        // the second call reaches retry_cycles, the target goes to the back of the list
+        update_hunt(&transmitting("K9XYZ", true), &app_state);
+        let hunt = app_state.hunt.lock().unwrap();
+        assert!(hunt.attempt.is_none());
+        assert_eq!(hunt.targets, ["W1AW", "K9XYZ"]);
+    }
+
+    #[test]
+    fn answered_calls_are_not_counted() {
+        let app_state = AppState::for_test(r#"{"hunt": {"targets": ["K9XYZ"], "retry_cycles": 1}}"#);
+        app_state.hunt.lock().unwrap().attempt = Some(HuntAttempt {
+            call: "K9XYZ".to_string(),
+            id: "WSJT-X".to_string(),
+            started: Utc::now(),
+            tx_periods: 0,
+            answered: true,
+            was_transmitting: false,
+            dx_call_set: true,
+        });
+        update_hunt(&transmitting("K9XYZ", true), &app_state);
+        assert_eq!(app_state.hunt.lock().unwrap().attempt.as_ref().map(|attempt| attempt.tx_periods), Some(0));
+    }
+}
@@ -0,0 +1,68 @@
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use chrono::{DateTime, Utc};
+use serde_derive::Serialize;
+use super::*;
+
+// a WSJT-X client we have heard from, keyed by the id it puts in every message
+#[derive(Debug, Clone, Serialize)]
+pub struct Instance {
+    pub id: String,
+    pub address: SocketAddr,
+    pub last_heard: DateTime<Utc>,
+    pub version: Option<String>,
+    pub status: Option<Status>,
+    // when we last sent it a command, so changes it makes on our say-so aren't taken for the operator's
+    pub last_command: Option<DateTime<Utc>>,
+}
+
+impl std::fmt::Display for Instance {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "Id: {}, Address: {}, Last Heard: {}, Version: {}", self.id, self.address,
+            self.last_heard.format("%H:%M:%S"), self.version.as_deref().unwrap_or("unknown"))
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Instances {
+    pub instances: HashMap<String, Instance>,
+}
+
+impl Instances {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn heard(&mut self, id: &str, address: SocketAddr) -> &mut Instance {
+        let instance = self.instances.entry(id.to_string()).or_insert_with(|| Instance {
+            id: id.to_string(),
+            address,
+            last_heard: Utc::now(),
+            version: None,
+            status: None,
+            last_command: None,
+        });
+        instance.address = address;
+        instance.last_heard = Utc::now();
+        instance
+    }
+
+    pub fn get(&self, id: &str) -> Option<&Instance> {
+        self.instances.get(id)
+    }
+
+    pub fn status(&self, id: &str) -> Option<&Status> {
+        self.instances.get(id).and_then(|instance| instance.status.as_ref())
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Instance> {
+        self.instances.values()
+    }
+
+    // the instance that most recently sent a Status, used where only one rig can be shown
+    pub fn latest(&self) -> Option<&Instance> {
+        self.instances.values()
+            .filter(|instance| instance.status.is_some())
+            .max_by_key(|instance| instance.last_heard)
+    }
+}
@@ -1,13 +1,75 @@
 pub mod wsjtxmessages;
+pub mod activity;
+pub mod alerts;
+pub mod adif;
 pub mod appstate;
+pub mod autoanswer;
+pub mod awards;
+pub mod band;
+pub mod callers;
+pub mod clearfrequency;
+pub mod cluster;
+pub mod config;
+pub mod contest;
+pub mod console;
+pub mod dxcc;
+pub mod filter;
+pub mod foxhound;
+pub mod highlights;
+pub mod hunt;
+pub mod instances;
+pub mod ft8message;
+pub mod pskreporter;
+pub mod qsotracker;
+pub mod reload;
+pub mod rotation;
+pub mod store;
+pub mod supervisor;
+pub mod tui;
+pub mod web;
+pub mod workedbefore;
+pub mod wspr;
 use std::net::{UdpSocket, SocketAddr};
 use std::io;
+use std::env;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::thread;
 use colored::*;
 // use std::str;
 pub use wsjtxmessages::*;
 pub use wsjtxmessages::receivemessages::*;
 pub use wsjtxmessages::sendmessages::*;
+pub use activity::*;
+pub use alerts::*;
+pub use adif::*;
 pub use appstate::*;
+pub use autoanswer::*;
+pub use awards::*;
+pub use band::*;
+pub use callers::*;
+pub use clearfrequency::*;
+pub use cluster::*;
+pub use config::*;
+pub use contest::*;
+pub use console::*;
+pub use dxcc::*;
+pub use filter::*;
+pub use foxhound::*;
+pub use highlights::*;
+pub use hunt::*;
+pub use instances::*;
+pub use ft8message::*;
+pub use pskreporter::*;
+pub use qsotracker::*;
+pub use reload::*;
+pub use rotation::*;
+pub use store::*;
+pub use supervisor::*;
+pub use tui::*;
+pub use web::*;
+pub use workedbefore::*;
+pub use wspr::*;
 
 
 const DEBUG: bool = false;
@@ -15,12 +77,38 @@ const DEBUG: bool = false;
 
 
 fn main() {
-    let app_state = AppState::new().expect("Could not read callsigns");
-    println!("Designated Callsigns: {:?}", app_state.designated_callsigns);
+    let socket = UdpSocket::bind("127.0.0.1:2237").expect("Could not bind socket");
+    let args: Vec<String> = env::args().collect();
+    let config_path = args.iter().position(|arg| arg == "--config")
+        .and_then(|index| args.get(index + 1))
+        .map(PathBuf::from)
+        .unwrap_or_else(|| PathBuf::from("config.json"));
+    let mut app_state = AppState::new(socket.try_clone().expect("Could not clone socket"), &config_path).expect("Could not read config or callsigns");
+    app_state.tui = args.iter().any(|arg| arg == "--tui");
+    let app_state = Arc::new(app_state);
+    println!("Designated Callsigns: {:?}", app_state.designated_callsigns());
+    watch_for_changes(Arc::clone(&app_state));
+    start_rotation(Arc::clone(&app_state));
+    start_psk_reporter(Arc::clone(&app_state));
+    start_wspr_uploader(Arc::clone(&app_state));
     //uncomment below line for windows 
     //set_virtual_terminal(true).unwrap();
     println!("{}","WSJTX Message Server".green().bold());
-    let socket = UdpSocket::bind("127.0.0.1:2237").expect("Could not bind socket");
+    if let Some(web_address) = &app_state.config().web_address {
+        start_web_server(Arc::clone(&app_state), web_address).expect("Could not start web dashboard");
+        println!("Web dashboard on http://{}/", web_address);
+    }
+    if let Some(cluster_address) = &app_state.config().cluster.address {
+        start_cluster_server(Arc::clone(&app_state), cluster_address).expect("Could not start DX cluster server");
+        println!("DX cluster on telnet {}", cluster_address);
+    }
+    if app_state.tui {
+        let tui_state = Arc::clone(&app_state);
+        thread::spawn(move || run_tui(tui_state));
+    } else {
+        let console_state = Arc::clone(&app_state);
+        thread::spawn(move || run_console(console_state));
+    }
     loop {
         let mut buffer = [0u8; 4096];
         match socket.recv_from(&mut buffer) {
@@ -28,7 +116,7 @@ fn main() {
                 if DEBUG {
                     println!("Received {} bytes from: {}", size, src);
                 }
-                handle_incoming_data(&buffer[..size], &app_state);
+                handle_incoming_data(&buffer[..size], src, &app_state);
 
                 // let close = Close {
                 //     message_type: 6,
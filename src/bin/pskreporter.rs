@@ -0,0 +1,519 @@
+use std::collections::HashMap;
+use std::net::{SocketAddr, ToSocketAddrs, UdpSocket};
+use std::sync::Arc;
+use std::thread;
+use chrono::{DateTime, Duration, Utc};
+use serde_derive::Deserialize;
+use super::*;
+
+const TICK: std::time::Duration = std::time::Duration::from_secs(1);
+const IPFIX_VERSION: u16 = 10;
+const TEMPLATE_SET_ID: u16 = 2;
+const OPTIONS_TEMPLATE_SET_ID: u16 = 3;
+const RECEIVER_TEMPLATE_ID: u16 = 0x9992;
+const SENDER_TEMPLATE_ID: u16 = 0x9993;
+// PSK Reporter's enterprise number, sent with each of its own field ids
+const ENTERPRISE_NUMBER: u32 = 0x768F;
+const VARIABLE_LENGTH: u16 = 0xFFFF;
+// the field ids, with the enterprise bit set
+const SENDER_CALLSIGN: u16 = 0x8001;
+const RECEIVER_CALLSIGN: u16 = 0x8002;
+const SENDER_LOCATOR: u16 = 0x8003;
+const RECEIVER_LOCATOR: u16 = 0x8004;
+const FREQUENCY: u16 = 0x8005;
+const SNR: u16 = 0x8006;
+const DECODER_SOFTWARE: u16 = 0x8008;
+const ANTENNA_INFORMATION: u16 = 0x8009;
+const MODE: u16 = 0x800A;
+const INFORMATION_SOURCE: u16 = 0x800B;
+// the standard IPFIX flowStartSeconds
+const FLOW_START_SECONDS: u16 = 150;
+// informationSource for spots taken automatically from decodes
+const AUTOMATIC: u8 = 1;
+// the templates go in the first few packets and then this often, so the collector can always decode us
+const TEMPLATE_PACKETS: u32 = 3;
+const TEMPLATE_MINUTES: i64 = 60;
+// kept under a typical path MTU
+const MAX_PACKET_BYTES: usize = 1400;
+
+// "psk_reporter" in config.json, spots our decodes to PSK Reporter, off unless enabled
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct PskReporterConfig {
+    pub enabled: bool,
+    // host:port, report.pskreporter.info:14739 is their test collector
+    pub collector: String,
+    pub interval_seconds: u64,
+    pub antenna: Option<String>,
+}
+
+impl Default for PskReporterConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            collector: "report.pskreporter.info:4739".to_string(),
+            interval_seconds: 300,
+            antenna: None,
+        }
+    }
+}
+
+impl PskReporterConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        match self.collector.rsplit_once(':') {
+            Some((host, port)) if !host.is_empty() && port.parse::<u16>().is_ok() => {}
+            _ => return Err(format!("psk_reporter: collector '{}' should be host:port", self.collector)),
+        }
+        if self.interval_seconds == 0 {
+            return Err("psk_reporter: interval_seconds must be positive".to_string());
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Spot {
+    pub call: String,
+    pub frequency_hz: u64,
+    pub snr: i32,
+    pub mode: String,
+    pub grid: Option<String>,
+    pub time: DateTime<Utc>,
+    band: Option<&'static str>,
+}
+
+// who heard the spots in a packet
+#[derive(Debug, Clone)]
+pub struct Receiver {
+    pub call: String,
+    pub grid: String,
+    pub software: String,
+    pub antenna: String,
+}
+
+// PSK Reporter's strings are a length byte and up to 254 bytes of text
+fn push_string(buffer: &mut Vec<u8>, text: &str) {
+    let bytes = &text.as_bytes()[..text.len().min(254)];
+    buffer.push(bytes.len() as u8);
+    buffer.extend_from_slice(bytes);
+}
+
+fn push_field(buffer: &mut Vec<u8>, id: u16, length: u16) {
+    buffer.extend_from_slice(&id.to_be_bytes());
+    buffer.extend_from_slice(&length.to_be_bytes());
+    if id & 0x8000 != 0 {
+        buffer.extend_from_slice(&ENTERPRISE_NUMBER.to_be_bytes());
+    }
+}
+
+// a set is its id, its length and its contents padded to four bytes
+fn set(id: u16, mut contents: Vec<u8>) -> Vec<u8> {
+    while !contents.len().is_multiple_of(4) {
+        contents.push(0);
+    }
+    let mut set = Vec::with_capacity(contents.len() + 4);
+    set.extend_from_slice(&id.to_be_bytes());
+    set.extend_from_slice(&(contents.len() as u16 + 4).to_be_bytes());
+    set.extend(contents);
+    set
+}
+
+fn receiver_template() -> Vec<u8> {
+    let fields = [RECEIVER_CALLSIGN, RECEIVER_LOCATOR, DECODER_SOFTWARE, ANTENNA_INFORMATION];
+    let mut contents = Vec::new();
+    contents.extend_from_slice(&RECEIVER_TEMPLATE_ID.to_be_bytes());
+    contents.extend_from_slice(&(fields.len() as u16).to_be_bytes());
+    // no scope fields
+    contents.extend_from_slice(&0u16.to_be_bytes());
+    for field in fields {
+        push_field(&mut contents, field, VARIABLE_LENGTH);
+    }
+    set(OPTIONS_TEMPLATE_SET_ID, contents)
+}
+
+fn sender_template() -> Vec<u8> {
+    let fields = [
+        (SENDER_CALLSIGN, VARIABLE_LENGTH),
+        (FREQUENCY, 4),
+        (SNR, 1),
+        (MODE, VARIABLE_LENGTH),
+        (SENDER_LOCATOR, VARIABLE_LENGTH),
+        (INFORMATION_SOURCE, 1),
+        (FLOW_START_SECONDS, 4),
+    ];
+    let mut contents = Vec::new();
+    contents.extend_from_slice(&SENDER_TEMPLATE_ID.to_be_bytes());
+    contents.extend_from_slice(&(fields.len() as u16).to_be_bytes());
+    for (field, length) in fields {
+        push_field(&mut contents, field, length);
+    }
+    set(TEMPLATE_SET_ID, contents)
+}
+
+fn receiver_record(receiver: &Receiver) -> Vec<u8> {
+    let mut record = Vec::new();
+    push_string(&mut record, &receiver.call);
+    push_string(&mut record, &receiver.grid);
+    push_string(&mut record, &receiver.software);
+    push_string(&mut record, &receiver.antenna);
+    record
+}
+
+fn sender_record(spot: &Spot) -> Vec<u8> {
+    let mut record = Vec::new();
+    push_string(&mut record, &spot.call);
+    record.extend_from_slice(&(spot.frequency_hz.min(u32::MAX as u64) as u32).to_be_bytes());
+    record.push(spot.snr.clamp(i8::MIN as i32, i8::MAX as i32) as i8 as u8);
+    push_string(&mut record, &spot.mode);
+    push_string(&mut record, spot.grid.as_deref().unwrap_or(""));
+    record.push(AUTOMATIC);
+    record.extend_from_slice(&(spot.time.timestamp() as u32).to_be_bytes());
+    record
+}
+
+// one IPFIX message, the header then the templates if wanted, the receiver and the spots
+pub fn encode_ipfix(receiver: &Receiver, spots: &[Spot], templates: bool, sequence: u32, observation_domain: u32, now: DateTime<Utc>) -> Vec<u8> {
+    let mut body = Vec::new();
+    if templates {
+        body.extend(receiver_template());
+        body.extend(sender_template());
+    }
+    body.extend(set(RECEIVER_TEMPLATE_ID, receiver_record(receiver)));
+    if !spots.is_empty() {
+        body.extend(set(SENDER_TEMPLATE_ID, spots.iter().flat_map(sender_record).collect()));
+    }
+    let mut packet = Vec::with_capacity(body.len() + 16);
+    packet.extend_from_slice(&IPFIX_VERSION.to_be_bytes());
+    packet.extend_from_slice(&(body.len() as u16 + 16).to_be_bytes());
+    packet.extend_from_slice(&(now.timestamp() as u32).to_be_bytes());
+    packet.extend_from_slice(&sequence.to_be_bytes());
+    packet.extend_from_slice(&observation_domain.to_be_bytes());
+    packet.extend(body);
+    packet
+}
+
+// the spots waiting for the next upload, per WSJT-X instance as each is its own receiver
+#[derive(Debug)]
+pub struct PskReporter {
+    pending: HashMap<String, Vec<Spot>>,
+    last_upload: DateTime<Utc>,
+    templates_sent: Option<DateTime<Utc>>,
+    packets: u32,
+    // IPFIX counts the data records sent before each message
+    sequence: u32,
+    // picked once per run, it tells the collector our packets apart from a restart's
+    observation_domain: u32,
+    pub spots_sent: u64,
+    pub last_error: Option<String>,
+}
+
+impl PskReporter {
+    pub fn new() -> Self {
+        let seed = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).map(|elapsed| elapsed.subsec_nanos()).unwrap_or(0);
+        Self {
+            pending: HashMap::new(),
+            last_upload: Utc::now(),
+            templates_sent: None,
+            packets: 0,
+            sequence: 0,
+            observation_domain: seed ^ std::process::id().rotate_left(16),
+            spots_sent: 0,
+            last_error: None,
+        }
+    }
+
+    // one spot per station, band and mode in each upload, the latest wins but keeps any grid we had
+    pub fn add(&mut self, id: &str, mut spot: Spot) {
+        let spots = self.pending.entry(id.to_string()).or_default();
+        if let Some(index) = spots.iter().position(|pending| pending.call == spot.call && pending.band == spot.band && pending.mode == spot.mode) {
+            let earlier = spots.remove(index);
+            spot.grid = spot.grid.or(earlier.grid);
+        }
+        spots.push(spot);
+    }
+
+    pub fn pending(&self) -> usize {
+        self.pending.values().map(|spots| spots.len()).sum()
+    }
+
+    fn templates_due(&self, now: DateTime<Utc>) -> bool {
+        self.packets < TEMPLATE_PACKETS
+            || self.templates_sent.map(|sent| now - sent >= Duration::minutes(TEMPLATE_MINUTES)).unwrap_or(true)
+    }
+
+    // the packets for everything pending with the spots in each, split to stay under MAX_PACKET_BYTES
+    fn packets(&mut self, receivers: &HashMap<String, Receiver>, now: DateTime<Utc>) -> Vec<(String, Vec<u8>, Vec<Spot>)> {
+        let mut packets = Vec::new();
+        for (id, spots) in std::mem::take(&mut self.pending) {
+            // an instance that hasn't told us its call can't be a receiver
+            let receiver = match receivers.get(&id) {
+                Some(receiver) => receiver,
+                None => continue,
+            };
+            let mut remaining = &spots[..];
+            while !remaining.is_empty() {
+                let templates = self.templates_due(now);
+                let mut count = remaining.len();
+                let mut packet = encode_ipfix(receiver, &remaining[..count], templates, self.sequence, self.observation_domain, now);
+                while packet.len() > MAX_PACKET_BYTES && count > 1 {
+                    count = count * 2 / 3;
+                    packet = encode_ipfix(receiver, &remaining[..count], templates, self.sequence, self.observation_domain, now);
+                }
+                if templates {
+                    self.templates_sent = Some(now);
+                }
+                self.packets += 1;
+                // the receiver record counts as well as the spots
+                self.sequence = self.sequence.wrapping_add(count as u32 + 1);
+                packets.push((id.clone(), packet, remaining[..count].to_vec()));
+                remaining = &remaining[count..];
+            }
+        }
+        packets
+    }
+
+    // sends everything pending to the collector, the spots of a packet that can't be sent wait for the next upload
+    fn send(&mut self, socket: &UdpSocket, collector: SocketAddr, receivers: &HashMap<String, Receiver>, now: DateTime<Utc>) -> usize {
+        let mut sent = 0;
+        for (id, packet, spots) in self.packets(receivers, now) {
+            match socket.send_to(&packet, collector) {
+                Ok(_) => sent += spots.len(),
+                Err(e) => {
+                    self.last_error = Some(e.to_string());
+                    spots.into_iter().for_each(|spot| self.add(&id, spot));
+                }
+            }
+        }
+        self.spots_sent += sent as u64;
+        sent
+    }
+}
+
+impl Default for PskReporter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl std::fmt::Display for PskReporter {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        writeln!(f, "Spots waiting: {}, sent: {}, last upload {}", self.pending(), self.spots_sent, self.last_upload.format("%H:%M:%S"))?;
+        if let Some(error) = &self.last_error {
+            writeln!(f, "Last error: {}", error)?;
+        }
+        Ok(())
+    }
+}
+
+// a decode of a station we can place on the band becomes a spot
+pub fn psk_reporter_decode(context: &FilterContext, app_state: &AppState) {
+    let decode = context.decode;
+    if !app_state.config().psk_reporter.enabled || !decode.new || decode.off_air || decode.low_confidence {
+        return;
+    }
+    // hashed calls we never resolved come through as "..."
+    let (call, frequency_hz, mode) = match (context.message.from_call(), decode.rf_frequency_hz, decode.mode_name()) {
+        (Some(call), Some(frequency_hz), Some(mode)) if valid_callsign(call) => (call, frequency_hz, mode),
+        _ => return,
+    };
+    let spot = Spot {
+        call: call.to_string(),
+        frequency_hz,
+        snr: decode.snr,
+        mode: mode.to_string(),
+        grid: context.message.grid().filter(|grid| !grid.is_empty()).map(|grid| grid.to_string()),
+        time: decode_timestamp(decode.time, Utc::now()),
+        band: decode.band,
+    };
+    app_state.psk_reporter.lock().unwrap().add(&decode.id, spot);
+}
+
+fn receivers(app_state: &AppState, antenna: &str) -> HashMap<String, Receiver> {
+    let software = format!("{} {}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"));
+    let instances = app_state.instances.lock().unwrap();
+    instances.iter()
+        .filter_map(|instance| {
+            let status = instance.status.as_ref()?;
+            let call = non_empty(&status.de_call).map(strip_call)?;
+            Some((instance.id.clone(), Receiver {
+                call,
+                grid: non_empty(&status.de_grid).unwrap_or("").to_string(),
+                software: software.clone(),
+                antenna: antenna.to_string(),
+            }))
+        })
+        .collect()
+}
+
+fn upload(socket: &UdpSocket, app_state: &AppState, now: DateTime<Utc>) {
+    let config = app_state.config();
+    let config = &config.psk_reporter;
+    {
+        let mut reporter = app_state.psk_reporter.lock().unwrap();
+        if !config.enabled || now - reporter.last_upload < Duration::seconds(config.interval_seconds as i64) {
+            return;
+        }
+        reporter.last_upload = now;
+        if reporter.pending() == 0 {
+            return;
+        }
+    }
+    // the DNS lookup and the instances lock happen before the reporter is locked again, so decodes aren't held up
+    let collector = match config.collector.to_socket_addrs().map(|mut addresses| addresses.next()) {
+        Ok(Some(collector)) => collector,
+        Ok(None) | Err(_) => {
+            // the spots stay pending for the next interval
+            app_state.psk_reporter.lock().unwrap().last_error = Some(format!("could not resolve {}", config.collector));
+            return app_state.log(&format!("PSK Reporter: could not resolve {}", config.collector));
+        }
+    };
+    let receivers = receivers(app_state, config.antenna.as_deref().unwrap_or(""));
+    let sent = app_state.psk_reporter.lock().unwrap().send(socket, collector, &receivers, now);
+    app_state.log(&format!("PSK Reporter: sent {} spots to {}", sent, config.collector));
+}
+
+// uploads what has been decoded every interval_seconds
+pub fn start_psk_reporter(app_state: Arc<AppState>) {
+    let socket = match UdpSocket::bind("0.0.0.0:0") {
+        Ok(socket) => socket,
+        Err(e) => return app_state.log(&format!("PSK Reporter disabled, could not open a socket: {}", e)),
+    };
+    thread::spawn(move || loop {
+        thread::sleep(TICK);
+        upload(&socket, &app_state, Utc::now());
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration as StdDuration;
+    use chrono::TimeZone;
+    use super::*;
+
+    fn receiver() -> Receiver {
+        Receiver { call: "KE8TKS".to_string(), grid: "EN80".to_string(), software: "wsjtxrust 0.1.0".to_string(), antenna: String::new() }
+    }
+
+    fn spot(call: &str, grid: Option<&str>) -> Spot {
+        Spot {
+            call: call.to_string(),
+            frequency_hz: 14_075_234,
+            snr: -12,
+            mode: "FT8".to_string(),
+            grid: grid.map(|grid| grid.to_string()),
+            time: Utc.with_ymd_and_hms(2026, 10, 19, 12, 0, 15).unwrap(),
+            band: Some("20m"),
+        }
+    }
+
+    fn u16_at(bytes: &[u8], index: usize) -> u16 {
+        u16::from_be_bytes([bytes[index], bytes[index + 1]])
+    }
+
+    fn u32_at(bytes: &[u8], index: usize) -> u32 {
+        u32::from_be_bytes([bytes[index], bytes[index + 1], bytes[index + 2], bytes[index + 3]])
+    }
+
+    // the id and contents of each set after the 16 byte header
+    fn sets(packet: &[u8]) -> Vec<(u16, &[u8])> {
+        let mut sets = Vec::new();
+        let mut index = 16;
+        while index < packet.len() {
+            let length = u16_at(packet, index + 2) as usize;
+            sets.push((u16_at(packet, index), &packet[index + 4..index + length]));
+            index += length;
+        }
+        sets
+    }
+
+    #[test]
+    fn encode_ipfix_sets() {
+        let now = Utc.with_ymd_and_hms(2026, 10, 19, 12, 1, 0).unwrap();
+        let cases = [
+            (true, vec![spot("JA1XYZ", Some("PM95"))], vec![OPTIONS_TEMPLATE_SET_ID, TEMPLATE_SET_ID, RECEIVER_TEMPLATE_ID, SENDER_TEMPLATE_ID]),
+            (false, vec![spot("JA1XYZ", Some("PM95")), spot("VK2ABC", None)], vec![RECEIVER_TEMPLATE_ID, SENDER_TEMPLATE_ID]),
+            (false, vec![], vec![RECEIVER_TEMPLATE_ID]),
+        ];
+        for (templates, spots, set_ids) in cases {
+            let packet = encode_ipfix(&receiver(), &spots, templates, 7, 42, now);
+            assert_eq!(u16_at(&packet, 0), IPFIX_VERSION);
+            assert_eq!(u16_at(&packet, 2) as usize, packet.len());
+            assert_eq!(u32_at(&packet, 4), now.timestamp() as u32);
+            assert_eq!(u32_at(&packet, 8), 7);
+            assert_eq!(u32_at(&packet, 12), 42);
+            let sets = sets(&packet);
+            assert_eq!(sets.iter().map(|(id, _)| *id).collect::<Vec<_>>(), set_ids, "templates {} spots {}", templates, spots.len());
+            assert!(sets.iter().all(|(_, contents)| contents.len().is_multiple_of(4)));
+        }
+    }
+
+    #[test]
+    fn upload_to_collector() {
+        let collector = UdpSocket::bind("127.0.0.1:0").unwrap();
+        collector.set_read_timeout(Some(StdDuration::from_secs(5))).unwrap();
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let receivers = HashMap::from([("WSJT-X".to_string(), receiver())]);
+        let mut reporter = PskReporter::new();
+        reporter.add("WSJT-X", spot("JA1XYZ", Some("PM95")));
+        let now = Utc.with_ymd_and_hms(2026, 10, 19, 12, 1, 0).unwrap();
+        assert_eq!(reporter.send(&socket, collector.local_addr().unwrap(), &receivers, now), 1);
+        assert_eq!(reporter.pending(), 0);
+
+        let mut buffer = [0u8; MAX_PACKET_BYTES];
+        let (length, _) = collector.recv_from(&mut buffer).unwrap();
+        let packet = &buffer[..length];
+        assert_eq!(u16_at(packet, 2) as usize, length);
+        let sets = sets(packet);
+
+        // sender template: id, field count, then each field with the enterprise number on PSK Reporter's own
+        let (_, template) = sets.iter().find(|(id, _)| *id == TEMPLATE_SET_ID).unwrap();
+        assert_eq!(u16_at(template, 0), SENDER_TEMPLATE_ID);
+        assert_eq!(u16_at(template, 2), 7);
+        let mut fields = Vec::new();
+        let mut index = 4;
+        while index + 4 <= template.len() && fields.len() < 7 {
+            let (id, length) = (u16_at(template, index), u16_at(template, index + 2));
+            index += 4;
+            if id & 0x8000 != 0 {
+                assert_eq!(u32_at(template, index), ENTERPRISE_NUMBER);
+                index += 4;
+            }
+            fields.push((id, length));
+        }
+        assert_eq!(fields, vec![
+            (SENDER_CALLSIGN, VARIABLE_LENGTH),
+            (FREQUENCY, 4),
+            (SNR, 1),
+            (MODE, VARIABLE_LENGTH),
+            (SENDER_LOCATOR, VARIABLE_LENGTH),
+            (INFORMATION_SOURCE, 1),
+            (FLOW_START_SECONDS, 4),
+        ]);
+
+        let (_, record) = sets.iter().find(|(id, _)| *id == SENDER_TEMPLATE_ID).unwrap();
+        let mut expected = vec![6];
+        expected.extend_from_slice(b"JA1XYZ");
+        expected.extend_from_slice(&14_075_234u32.to_be_bytes());
+        expected.push(-12i8 as u8);
+        expected.push(3);
+        expected.extend_from_slice(b"FT8");
+        expected.push(4);
+        expected.extend_from_slice(b"PM95");
+        expected.push(AUTOMATIC);
+        expected.extend_from_slice(&(spot("JA1XYZ", None).time.timestamp() as u32).to_be_bytes());
+        assert_eq!(&record[..expected.len()], &expected[..]);
+        assert!(record[expected.len()..].iter().all(|byte| *byte == 0));
+    }
+
+    #[test]
+    fn failed_send_keeps_spots() {
+        // an IPv4 socket can't send to an IPv6 collector
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let receivers = HashMap::from([("WSJT-X".to_string(), receiver())]);
+        let mut reporter = PskReporter::new();
+        reporter.add("WSJT-X", spot("JA1XYZ", Some("PM95")));
+        assert_eq!(reporter.send(&socket, "[::1]:4739".parse().unwrap(), &receivers, Utc::now()), 0);
+        assert_eq!(reporter.pending(), 1);
+        assert!(reporter.last_error.is_some());
+    }
+}
@@ -0,0 +1,285 @@
+use std::collections::HashMap;
+use chrono::{DateTime, Utc, Duration};
+use colored::*;
+use super::*;
+
+// a QSO that hasn't moved on for this many periods is considered stalled
+const STALL_PERIODS: i64 = 4;
+// QSOs and CQs older than this are dropped from the tracker
+const EXPIRY_MINUTES: i64 = 15;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum QsoState {
+    Calling,
+    GridSent,
+    ReportSent,
+    RogerReport,
+    RogerRoger,
+    Complete,
+}
+
+impl std::fmt::Display for QsoState {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let name = match self {
+            QsoState::Calling => "Calling",
+            QsoState::GridSent => "Grid",
+            QsoState::ReportSent => "Report",
+            QsoState::RogerReport => "R+Report",
+            QsoState::RogerRoger => "RR73/RRR",
+            QsoState::Complete => "73",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl QsoState {
+    fn from_message(message: &Ft8Message) -> Option<QsoState> {
+        match message {
+            Ft8Message::Cq { .. } => Some(QsoState::Calling),
+            Ft8Message::Grid { .. } => Some(QsoState::GridSent),
+            Ft8Message::Report { .. } => Some(QsoState::ReportSent),
+            Ft8Message::RogerReport { .. } => Some(QsoState::RogerReport),
+            Ft8Message::RogerRoger { .. } => Some(QsoState::RogerRoger),
+            Ft8Message::SeventyThree { .. } => Some(QsoState::Complete),
+            // tracked a stream at a time
+            Ft8Message::FoxMulti { .. } | Ft8Message::Other(_) => None,
+        }
+    }
+
+    // once RR73/RRR has been sent both sides have everything they need to log
+    pub fn is_finished(&self) -> bool {
+        *self >= QsoState::RogerRoger
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Qso {
+    pub calls: (String, String),
+    pub state: QsoState,
+    pub started: DateTime<Utc>,
+    pub updated: DateTime<Utc>,
+    pub last_message: String,
+    pub last_snr: i32,
+    pub own: bool,
+}
+
+impl std::fmt::Display for Qso {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{} <-> {}: {} ({}, last: {})", self.calls.0, self.calls.1, self.state,
+            self.updated.format("%H:%M:%S"), self.last_message)
+    }
+}
+
+impl Qso {
+    pub fn involves(&self, call: &str) -> bool {
+        self.calls.0 == call || self.calls.1 == call
+    }
+
+    pub fn other_call(&self, call: &str) -> Option<&str> {
+        if self.calls.0 == call {
+            Some(&self.calls.1)
+        } else if self.calls.1 == call {
+            Some(&self.calls.0)
+        } else {
+            None
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct CqCall {
+    pub call: String,
+    pub modifier: Option<String>,
+    pub grid: Option<String>,
+    pub snr: i32,
+    pub heard: DateTime<Utc>,
+}
+
+// our call and the DX call set in one WSJT-X instance, from its Status
+#[derive(Debug, Clone, Default)]
+pub struct OwnStation {
+    pub call: Option<String>,
+    pub dx_call: Option<String>,
+}
+
+#[derive(Debug)]
+pub struct QsoTracker {
+    pub qsos: HashMap<(String, String), Qso>,
+    pub cqs: HashMap<String, CqCall>,
+    // keyed by instance id, each rig can run under its own call
+    pub own: HashMap<String, OwnStation>,
+    pub tr_period: u32,
+}
+
+impl Default for QsoTracker {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn qso_key(a: &str, b: &str) -> (String, String) {
+    if a <= b {
+        (a.to_string(), b.to_string())
+    } else {
+        (b.to_string(), a.to_string())
+    }
+}
+
+impl QsoTracker {
+    pub fn new() -> Self {
+        Self {
+            qsos: HashMap::new(),
+            cqs: HashMap::new(),
+            own: HashMap::new(),
+            tr_period: 15,
+        }
+    }
+
+    fn period(&self) -> Duration {
+        Duration::seconds(self.tr_period as i64)
+    }
+
+    fn is_own_pair(&self, id: &str, a: &str, b: &str) -> bool {
+        match self.own.get(id).and_then(|own| own.call.as_deref()) {
+            Some(own) => own == a || own == b,
+            None => false,
+        }
+    }
+
+    // feeds one message decoded (or transmitted) by instance id into the tracker, returning the QSO if its state changed
+    pub fn track_message(&mut self, id: &str, message: &Ft8Message, snr: i32, now: DateTime<Utc>) -> Option<Qso> {
+        let state = QsoState::from_message(message)?;
+        if let Ft8Message::Cq { modifier, call, grid } = message {
+            // a station calling CQ has finished whatever it was doing before
+            for qso in self.qsos.values_mut().filter(|qso| qso.involves(call) && !qso.state.is_finished()) {
+                qso.state = QsoState::Complete;
+                qso.updated = now;
+            }
+            self.cqs.insert(call.clone(), CqCall {
+                call: call.clone(),
+                modifier: modifier.clone(),
+                grid: grid.clone(),
+                snr,
+                heard: now,
+            });
+            return None;
+        }
+        let (from, to) = (message.from_call()?, message.to_call()?);
+        self.cqs.remove(from);
+        let key = qso_key(from, to);
+        let own = self.is_own_pair(id, from, to);
+        let period = self.period();
+        let qso = self.qsos.entry(key.clone()).or_insert_with(|| Qso {
+            calls: key,
+            state: QsoState::Calling,
+            started: now,
+            updated: now,
+            last_message: String::new(),
+            last_snr: snr,
+            own,
+        });
+        let restarted = state < qso.state
+            && (qso.state == QsoState::Complete || now - qso.updated > period * STALL_PERIODS as i32);
+        qso.last_message = message.to_string();
+        qso.last_snr = snr;
+        qso.own = own;
+        if state > qso.state || restarted {
+            if restarted {
+                qso.started = now;
+            }
+            qso.state = state;
+            qso.updated = now;
+            return Some(qso.clone());
+        }
+        // repeats don't count as progress, so a QSO stuck on the same message goes stale
+        None
+    }
+
+    // a fox message moves two QSOs on at once
+    fn track_streams(&mut self, id: &str, message: &Ft8Message, snr: i32, now: DateTime<Utc>) -> Vec<Qso> {
+        message.streams().iter().filter_map(|stream| self.track_message(id, stream, snr, now)).collect()
+    }
+
+    pub fn track_decode(&mut self, decode: &Decode, now: DateTime<Utc>) -> Vec<Qso> {
+        let message = parse_ft8_message(&decode.message);
+        self.track_streams(&decode.id, &message, decode.snr, now)
+    }
+
+    pub fn track_status(&mut self, status: &Status, now: DateTime<Utc>) -> Vec<Qso> {
+        self.own.insert(status.id.clone(), OwnStation {
+            call: non_empty(&status.de_call).map(strip_call),
+            dx_call: non_empty(&status.dx_call).map(strip_call),
+        });
+        if status.tr_period > 0 && status.tr_period != u32::MAX {
+            self.tr_period = status.tr_period;
+        }
+        match non_empty(&status.tx_message) {
+            Some(tx_message) if status.transmitting => self.track_streams(&status.id, &parse_ft8_message(tx_message), 0, now),
+            _ => Vec::new(),
+        }
+    }
+
+    // the QSO instance id is working
+    pub fn own_qso(&self, id: &str) -> Option<&Qso> {
+        let own = self.own.get(id)?;
+        self.qsos.get(&qso_key(own.call.as_ref()?, own.dx_call.as_ref()?))
+    }
+
+    // the instance's QSO hasn't finished and hasn't stalled
+    pub fn own_qso_in_progress(&self, id: &str, now: DateTime<Utc>) -> bool {
+        self.own_qso(id)
+            .map(|qso| !qso.state.is_finished() && now - qso.updated <= self.period() * STALL_PERIODS as i32)
+            .unwrap_or(false)
+    }
+
+    pub fn active(&self, now: DateTime<Utc>) -> Vec<&Qso> {
+        let mut active: Vec<&Qso> = self.qsos.values()
+            .filter(|qso| qso.state != QsoState::Complete && now - qso.updated <= self.period() * STALL_PERIODS as i32)
+            .collect();
+        active.sort_by_key(|qso| std::cmp::Reverse(qso.updated));
+        active
+    }
+
+    pub fn stalled(&self, now: DateTime<Utc>) -> Vec<&Qso> {
+        self.qsos.values()
+            .filter(|qso| !qso.state.is_finished() && now - qso.updated > self.period() * STALL_PERIODS as i32)
+            .collect()
+    }
+
+    // a station is free when it isn't part of any QSO that is still in progress
+    pub fn is_free(&self, call: &str, now: DateTime<Utc>) -> bool {
+        !self.active(now).iter().any(|qso| qso.involves(call) && !qso.state.is_finished())
+    }
+
+    pub fn prune(&mut self, now: DateTime<Utc>) {
+        let expiry = Duration::minutes(EXPIRY_MINUTES);
+        self.qsos.retain(|_, qso| now - qso.updated <= expiry);
+        self.cqs.retain(|_, cq| now - cq.heard <= expiry);
+    }
+}
+
+impl std::fmt::Display for QsoTracker {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let now = Utc::now();
+        let mut ids: Vec<&String> = self.own.keys().collect();
+        ids.sort();
+        for id in ids {
+            if let Some(qso) = self.own_qso(id) {
+                writeln!(f, "Our QSO on {}: {}", id, qso)?;
+            }
+        }
+        for qso in self.active(now) {
+            writeln!(f, "{}", qso)?;
+        }
+        for qso in self.stalled(now) {
+            writeln!(f, "Stalled: {}", qso)?;
+        }
+        Ok(())
+    }
+}
+
+pub fn print_qso_update(qso: &Qso) {
+    if qso.own {
+        println!("{} {}", "QSO".bold().yellow(), qso.to_string().yellow());
+    }
+}
@@ -0,0 +1,92 @@
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::{Duration, SystemTime};
+use super::*;
+
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|metadata| metadata.modified()).ok()
+}
+
+// reads the config and watch list again and swaps them in only if everything built from them is valid
+pub fn reload(app_state: &AppState) -> Result<String, String> {
+    let config = Config::load(&app_state.config_path)
+        .map_err(|e| format!("{}: {}", app_state.config_path.display(), e))?;
+    let watch_list_path = config.watch_list_path(&app_state.config_path);
+    let designated_callsigns = read_watch_list(&watch_list_path)
+        .map_err(|e| format!("{}: {}", watch_list_path.display(), e))?;
+    let alerts = Alerts::new(&config.alerts, &designated_callsigns)?;
+    config.wsjtx_highlights.validate()?;
+    config.auto_answer.categories()?;
+    config.tx_supervisor.validate()?;
+    config.rotation.validate()?;
+    config.psk_reporter.validate()?;
+    config.wspr.validate()?;
+    let display_filter = match &config.display_filter {
+        Some(text) => Some(Filter::parse(text)?),
+        None => None,
+    };
+    let previous = app_state.config();
+    let mut summary = format!("{} watched callsigns, {} alert rules", designated_callsigns.len(), config.alerts.len());
+    if previous.cty_file != config.cty_file || previous.adif_history != config.adif_history || previous.web_address != config.web_address
+        || previous.store.database != config.store.database {
+        summary.push_str(", cty_file, adif_history, web_address and store.database changes need a restart");
+    }
+    // targets added to the config join the queue, ones already worked or removed at the console stay gone
+    let new_targets: Vec<String> = config.hunt.targets.iter().filter(|target| !previous.hunt.targets.contains(target)).cloned().collect();
+    app_state.hunt.lock().unwrap().add(&new_targets);
+    app_state.replace_config(config, designated_callsigns, alerts, display_filter);
+    Ok(summary)
+}
+
+pub fn reload_and_log(app_state: &AppState, reason: &str) {
+    match reload(app_state) {
+        Ok(summary) => {
+            app_state.log(&format!("Reloaded config after {}: {}", reason, summary));
+            app_state.wsjtx_highlights.lock().unwrap().refresh(app_state);
+        }
+        Err(e) => app_state.log(&format!("Reload after {} failed, keeping the previous config: {}", reason, e)),
+    }
+}
+
+#[cfg(unix)]
+fn register_sighup(flag: &Arc<AtomicBool>) {
+    if let Err(e) = signal_hook::flag::register(signal_hook::consts::SIGHUP, Arc::clone(flag)) {
+        eprintln!("Could not listen for SIGHUP: {}", e);
+    }
+}
+
+#[cfg(not(unix))]
+fn register_sighup(_flag: &Arc<AtomicBool>) {}
+
+// polls the config file and the watch list for changes, and reloads on SIGHUP where there is one
+pub fn watch_for_changes(app_state: Arc<AppState>) {
+    let hangup = Arc::new(AtomicBool::new(false));
+    register_sighup(&hangup);
+    thread::spawn(move || {
+        let mut config_modified = modified(&app_state.config_path);
+        let mut watch_list_modified = modified(&app_state.watch_list_path());
+        loop {
+            thread::sleep(POLL_INTERVAL);
+            let reason = if hangup.swap(false, Ordering::SeqCst) {
+                Some("SIGHUP")
+            } else if modified(&app_state.config_path) != config_modified {
+                Some("config change")
+            } else if modified(&app_state.watch_list_path()) != watch_list_modified {
+                Some("watch list change")
+            } else {
+                None
+            };
+            if let Some(reason) = reason {
+                reload_and_log(&app_state, reason);
+                // the watch list path may have moved with the config
+                config_modified = modified(&app_state.config_path);
+                watch_list_modified = modified(&app_state.watch_list_path());
+            }
+        }
+    });
+}
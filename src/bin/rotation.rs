@@ -0,0 +1,418 @@
+use std::collections::VecDeque;
+use std::io::{self, Write};
+use std::sync::Arc;
+use std::thread;
+use chrono::{DateTime, Datelike, Duration, Timelike, Utc};
+use serde_derive::Deserialize;
+use super::*;
+
+const TICK: std::time::Duration = std::time::Duration::from_secs(1);
+// how long WSJT-X gets to report the new settings in a Status before the switch is sent again
+const CONFIRM_SECONDS: i64 = 15;
+
+// a cron schedule, minute hour day-of-month month day-of-week in UTC, each field *, */n, a-b, a-b/n or a list of them
+#[derive(Debug, Clone, PartialEq)]
+pub struct CronSchedule {
+    fields: [Vec<bool>; 5],
+}
+
+const CRON_FIELDS: [(&str, u32, u32); 5] = [("minute", 0, 59), ("hour", 0, 23), ("day", 1, 31), ("month", 1, 12), ("weekday", 0, 6)];
+
+fn parse_cron_field(text: &str, name: &str, min: u32, max: u32) -> Result<Vec<bool>, String> {
+    let mut allowed = vec![false; max as usize + 1];
+    for part in text.split(',') {
+        let (range, step) = match part.split_once('/') {
+            Some((range, step)) => (range, step.parse::<u32>().ok().filter(|step| *step > 0)
+                .ok_or(format!("bad step '{}' in cron {} field", step, name))?),
+            None => (part, 1),
+        };
+        let number = |text: &str| text.parse::<u32>().ok().filter(|value| (min..=max).contains(value))
+            .ok_or(format!("'{}' is outside {}-{} in cron {} field", text, min, max, name));
+        let (low, high) = match range {
+            "*" => (min, max),
+            _ => match range.split_once('-') {
+                Some((low, high)) => (number(low)?, number(high)?),
+                None if step > 1 => (number(range)?, max),
+                None => (number(range)?, number(range)?),
+            },
+        };
+        if low > high {
+            return Err(format!("'{}' is backwards in cron {} field", range, name));
+        }
+        for value in (low..=high).step_by(step as usize) {
+            allowed[value as usize] = true;
+        }
+    }
+    Ok(allowed)
+}
+
+impl CronSchedule {
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let parts: Vec<&str> = text.split_whitespace().collect();
+        if parts.len() != 5 {
+            return Err(format!("cron '{}' needs 5 fields: minute hour day month weekday", text));
+        }
+        let mut fields: [Vec<bool>; 5] = Default::default();
+        for (index, (name, min, max)) in CRON_FIELDS.iter().enumerate() {
+            fields[index] = parse_cron_field(parts[index], name, *min, *max)?;
+        }
+        Ok(Self { fields })
+    }
+
+    pub fn matches(&self, time: DateTime<Utc>) -> bool {
+        let values = [time.minute(), time.hour(), time.day(), time.month(), time.weekday().num_days_from_sunday()];
+        self.fields.iter().zip(values).all(|(allowed, value)| allowed[value as usize])
+    }
+}
+
+// one stop of the rotation, anything left out isn't changed or checked
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct RotationEntry {
+    // a WSJT-X configuration set up for the band, SwitchConfiguration is how the band changes
+    pub configuration: Option<String>,
+    pub mode: Option<String>,
+    pub submode: Option<String>,
+    pub tr_period: Option<u32>,
+    pub rx_df: Option<u32>,
+    pub frequency_tolerance: Option<u32>,
+    // checked against the dial frequency once switched
+    pub band: Option<String>,
+    // when to switch here, entries without one are only reached when the band before them goes quiet
+    pub cron: Option<String>,
+}
+
+impl std::fmt::Display for RotationEntry {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let parts: Vec<String> = [
+            self.configuration.clone(),
+            self.band.clone(),
+            self.mode.clone(),
+            self.submode.clone(),
+            self.tr_period.map(|period| format!("{} s", period)),
+            self.rx_df.map(|rx_df| format!("rx {} Hz", rx_df)),
+            self.frequency_tolerance.map(|tolerance| format!("tol {} Hz", tolerance)),
+            self.cron.as_ref().map(|cron| format!("at '{}'", cron)),
+        ].into_iter().flatten().collect();
+        write!(f, "{}", parts.join(", "))
+    }
+}
+
+impl RotationEntry {
+    // what in a Status doesn't match yet, empty once WSJT-X has switched
+    fn mismatches(&self, status: &Status, iaru_region: u8) -> Vec<String> {
+        let mut mismatches = Vec::new();
+        let mut check = |name: &str, wanted: Option<String>, actual: String| {
+            if let Some(wanted) = wanted.filter(|wanted| !wanted.eq_ignore_ascii_case(&actual)) {
+                mismatches.push(format!("{} is {} not {}", name, actual, wanted));
+            }
+        };
+        check("configuration", self.configuration.clone(), status.configuration_name.clone());
+        check("mode", self.mode.clone(), status.mode.clone());
+        check("submode", self.submode.clone(), non_empty(&status.sub_mode).unwrap_or("").to_string());
+        check("tr_period", self.tr_period.map(|period| period.to_string()), status.tr_period.to_string());
+        check("rx_df", self.rx_df.map(|rx_df| rx_df.to_string()), status.rx_df.to_string());
+        check("frequency_tolerance", self.frequency_tolerance.map(|tolerance| tolerance.to_string()), status.frequency_tolerance.to_string());
+        check("band", self.band.clone(), band_for_frequency(status.dial_frequency, iaru_region).unwrap_or("out of band").to_string());
+        mismatches
+    }
+}
+
+// "rotation" in config.json, off unless enabled
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct RotationConfig {
+    pub enabled: bool,
+    // the WSJT-X id to drive, the one that last sent a Status when unset
+    pub instance: Option<String>,
+    pub entries: Vec<RotationEntry>,
+    // move on to the next entry when fewer than min_decodes were heard in this many minutes
+    pub quiet_minutes: Option<u32>,
+    pub min_decodes: usize,
+    // times a switch is sent again before giving up and raising the alarm
+    pub retries: u32,
+}
+
+impl Default for RotationConfig {
+    fn default() -> Self {
+        Self { enabled: false, instance: None, entries: Vec::new(), quiet_minutes: None, min_decodes: 5, retries: 2 }
+    }
+}
+
+impl RotationConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        for (index, entry) in self.entries.iter().enumerate() {
+            if let Some(cron) = &entry.cron {
+                CronSchedule::parse(cron).map_err(|e| format!("rotation entry {}: {}", index + 1, e))?;
+            }
+            if entry.tr_period == Some(0) {
+                return Err(format!("rotation entry {}: tr_period must be positive", index + 1));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone)]
+struct PendingSwitch {
+    index: usize,
+    id: String,
+    sent: DateTime<Utc>,
+    attempts: u32,
+    mismatches: Vec<String>,
+}
+
+#[derive(Debug, Default)]
+pub struct Rotation {
+    // the entry WSJT-X was last confirmed on
+    pub current: Option<usize>,
+    switched: Option<DateTime<Utc>>,
+    // an entry whose time came while we were transmitting
+    due: Option<usize>,
+    pending: Option<PendingSwitch>,
+    heard: VecDeque<DateTime<Utc>>,
+    last_minute: Option<DateTime<Utc>>,
+}
+
+impl Rotation {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn heard(&mut self, now: DateTime<Utc>) {
+        self.heard.push_back(now);
+        while self.heard.len() > 10_000 {
+            self.heard.pop_front();
+        }
+    }
+
+    pub fn switch_to(&mut self, index: usize) {
+        self.due = Some(index);
+    }
+
+    fn quiet(&self, config: &RotationConfig, now: DateTime<Utc>) -> bool {
+        let minutes = match config.quiet_minutes {
+            Some(minutes) => Duration::minutes(minutes as i64),
+            None => return false,
+        };
+        let settled = self.switched.map(|switched| now - switched >= minutes).unwrap_or(false);
+        settled && self.heard.iter().filter(|heard| now - **heard < minutes).count() < config.min_decodes
+    }
+}
+
+impl std::fmt::Display for Rotation {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        if let Some(pending) = &self.pending {
+            writeln!(f, "Switching to entry {}, attempt {}", pending.index + 1, pending.attempts)?;
+        }
+        if let Some(due) = self.due {
+            writeln!(f, "Entry {} is due once TX is off", due + 1)?;
+        }
+        Ok(())
+    }
+}
+
+fn rotation_instance(config: &RotationConfig, app_state: &AppState) -> Option<(String, Status)> {
+    let instances = app_state.instances.lock().unwrap();
+    let instance = match &config.instance {
+        Some(id) => instances.get(id)?,
+        None => instances.latest()?,
+    };
+    Some((instance.id.clone(), instance.status.clone()?))
+}
+
+fn send_switch(id: &str, entry: &RotationEntry, app_state: &AppState) -> io::Result<()> {
+    if let Some(configuration_name) = &entry.configuration {
+        let switch = SwitchConfiguration { message_type: 14, id: id.to_string(), configuration_name: configuration_name.clone() };
+        send_to_instance(app_state, id, encode_switch_configuration(&switch))?;
+    }
+    let configure = Configure {
+        message_type: 15,
+        id: id.to_string(),
+        mode: entry.mode.clone().unwrap_or_default(),
+        frequency_tolerance: entry.frequency_tolerance.unwrap_or(u32::MAX),
+        submode: entry.submode.clone().unwrap_or_default(),
+        fast_mode: false,
+        tr_period: entry.tr_period.map(|period| period as i32).unwrap_or(-1),
+        rx_df: entry.rx_df.map(|rx_df| rx_df as i32).unwrap_or(-1),
+        dx_call: String::new(),
+        dx_grid: String::new(),
+        generate_messages: false,
+    };
+    send_to_instance(app_state, id, encode_configure(&configure))
+}
+
+fn rotation_alarm(text: &str, app_state: &AppState) {
+    if !app_state.tui {
+        print!("\x07");
+        let _ = io::stdout().flush();
+    }
+    app_state.log(text);
+    broadcast_json(app_state, &serde_json::json!({ "type": "rotation_failed", "message": text }));
+}
+
+// one second of the scheduler: sends what is due, and resends or gives up on switches that weren't confirmed
+fn rotation_tick(app_state: &AppState, now: DateTime<Utc>) {
+    let config = app_state.config();
+    let rotation_config = &config.rotation;
+    if !rotation_config.enabled || rotation_config.entries.is_empty() {
+        return;
+    }
+    let (id, status) = match rotation_instance(rotation_config, app_state) {
+        Some(instance) => instance,
+        None => return,
+    };
+    let mut rotation = app_state.rotation.lock().unwrap();
+    if let Some(pending) = rotation.pending.clone() {
+        if now - pending.sent < Duration::seconds(CONFIRM_SECONDS) {
+            return;
+        }
+        let entry = match rotation_config.entries.get(pending.index) {
+            Some(entry) => entry,
+            None => {
+                rotation.pending = None;
+                return;
+            }
+        };
+        if pending.attempts > rotation_config.retries {
+            rotation.pending = None;
+            drop(rotation);
+            rotation_alarm(&format!("Rotation: WSJT-X didn't switch to {}: {}", entry, pending.mismatches.join(", ")), app_state);
+            return;
+        }
+        rotation.pending = Some(PendingSwitch { sent: now, attempts: pending.attempts + 1, ..pending });
+        drop(rotation);
+        if let Err(e) = send_switch(&id, entry, app_state) {
+            app_state.log(&format!("Rotation: could not resend the switch: {}", e));
+        }
+        return;
+    }
+    // the quiet clock starts with the server when nothing has been switched yet
+    if rotation.switched.is_none() {
+        rotation.switched = Some(now);
+    }
+    // cron entries are checked once a minute
+    let minute = now.with_second(0).and_then(|now| now.with_nanosecond(0)).unwrap_or(now);
+    if rotation.last_minute != Some(minute) {
+        rotation.last_minute = Some(minute);
+        let scheduled = rotation_config.entries.iter().position(|entry| entry.cron.as_ref()
+            .and_then(|cron| CronSchedule::parse(cron).ok())
+            .map(|schedule| schedule.matches(minute))
+            .unwrap_or(false));
+        if let Some(index) = scheduled.filter(|index| Some(*index) != rotation.current) {
+            rotation.due = Some(index);
+        }
+    }
+    if rotation.due.is_none() && rotation.quiet(rotation_config, now) {
+        let next = rotation.current.map(|current| (current + 1) % rotation_config.entries.len()).unwrap_or(0);
+        app_state.log(&format!("Rotation: fewer than {} decodes, moving on to entry {}", rotation_config.min_decodes, next + 1));
+        rotation.due = Some(next);
+        rotation.switched = Some(now);
+        rotation.heard.clear();
+    }
+    let index = match rotation.due {
+        Some(index) if index < rotation_config.entries.len() => index,
+        Some(_) => {
+            rotation.due = None;
+            return;
+        }
+        None => return,
+    };
+    // never change band under a transmission or a QSO
+    if status.transmitting || status.tx_enabled || app_state.qso_tracker.lock().unwrap().own_qso_in_progress(&id, now) {
+        return;
+    }
+    rotation.due = None;
+    rotation.pending = Some(PendingSwitch { index, id: id.clone(), sent: now, attempts: 1, mismatches: Vec::new() });
+    drop(rotation);
+    let entry = &rotation_config.entries[index];
+    match send_switch(&id, entry, app_state) {
+        Ok(()) => app_state.log(&format!("Rotation: switching {} to {}", id, entry)),
+        Err(e) => app_state.log(&format!("Rotation: could not switch {}: {}", id, e)),
+    }
+}
+
+// a Status that shows the new settings confirms the switch
+pub fn confirm_rotation(status: &Status, app_state: &AppState) {
+    let mut rotation = app_state.rotation.lock().unwrap();
+    let pending = match rotation.pending.as_mut() {
+        Some(pending) if pending.id == status.id => pending,
+        _ => return,
+    };
+    let config = app_state.config();
+    let entry = match config.rotation.entries.get(pending.index) {
+        Some(entry) => entry,
+        None => return,
+    };
+    pending.mismatches = entry.mismatches(status, config.iaru_region);
+    if pending.mismatches.is_empty() {
+        let index = pending.index;
+        rotation.pending = None;
+        rotation.current = Some(index);
+        rotation.switched = Some(Utc::now());
+        rotation.heard.clear();
+        drop(rotation);
+        app_state.log(&format!("Rotation: {} is on entry {}", status.id, index + 1));
+    }
+}
+
+// decodes from the rotated instance tell us whether the band is open
+pub fn rotation_decode(decode: &Decode, app_state: &AppState) {
+    let config = app_state.config();
+    if !config.rotation.enabled || config.rotation.instance.as_ref().map(|id| *id != decode.id).unwrap_or(false) {
+        return;
+    }
+    app_state.rotation.lock().unwrap().heard(Utc::now());
+}
+
+pub fn start_rotation(app_state: Arc<AppState>) {
+    thread::spawn(move || loop {
+        thread::sleep(TICK);
+        rotation_tick(&app_state, Utc::now());
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    // the minutes past 12:00 on Monday 2024-01-01 that a schedule fires in
+    fn minutes(cron: &str) -> Vec<u32> {
+        let schedule = CronSchedule::parse(cron).unwrap();
+        (0..60).filter(|minute| schedule.matches(Utc.with_ymd_and_hms(2024, 1, 1, 12, *minute, 0).unwrap())).collect()
+    }
+
+    #[test]
+    fn cron_minutes() {
+        assert_eq!(minutes("0 12 * * *"), [0]);
+        assert_eq!(minutes("*/15 * * * *"), [0, 15, 30, 45]);
+        assert_eq!(minutes("5/20 * * * *"), [5, 25, 45]);
+        assert_eq!(minutes("10-13,58 * * * *"), [10, 11, 12, 13, 58]);
+        assert_eq!(minutes("0-30/10 * * * *"), [0, 10, 20, 30]);
+        assert!(minutes("0 13 * * *").is_empty());
+    }
+
+    #[test]
+    fn cron_days() {
+        // 2024-01-01 was a Monday, weekdays count from Sunday = 0
+        let day = |day| Utc.with_ymd_and_hms(2024, 1, day, 0, 0, 0).unwrap();
+        let weekdays = CronSchedule::parse("0 0 * * 1-5").unwrap();
+        assert_eq!((1..=7).filter(|d| weekdays.matches(day(*d))).collect::<Vec<_>>(), [1, 2, 3, 4, 5]);
+        let sundays = CronSchedule::parse("0 0 * * 0").unwrap();
+        assert!(sundays.matches(day(7)) && !sundays.matches(day(6)));
+        let new_year = CronSchedule::parse("0 0 1 1 *").unwrap();
+        assert!(new_year.matches(day(1)) && !new_year.matches(day(2)));
+    }
+
+    #[test]
+    fn cron_errors() {
+        let error = |cron| CronSchedule::parse(cron).unwrap_err();
+        assert_eq!(error("0 12 * *"), "cron '0 12 * *' needs 5 fields: minute hour day month weekday");
+        assert_eq!(error("60 * * * *"), "'60' is outside 0-59 in cron minute field");
+        assert_eq!(error("0 0 0 * *"), "'0' is outside 1-31 in cron day field");
+        assert_eq!(error("0 0 * * 7"), "'7' is outside 0-6 in cron weekday field");
+        assert_eq!(error("noon * * * *"), "'noon' is outside 0-59 in cron minute field");
+        assert_eq!(error("*/0 * * * *"), "bad step '0' in cron minute field");
+        assert_eq!(error("0 17-8 * * *"), "'17-8' is backwards in cron hour field");
+    }
+}
@@ -0,0 +1,438 @@
+use std::collections::HashMap;
+use std::path::Path;
+use chrono::{DateTime, Duration, NaiveTime, TimeZone, Utc};
+use rusqlite::{params, params_from_iter, Connection, Row};
+use serde_derive::{Deserialize, Serialize};
+use super::*;
+
+// retention is enforced at most this often, it's a table scan on each table
+const PRUNE_INTERVAL_MINUTES: i64 = 60;
+
+const SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS decodes (
+        id INTEGER PRIMARY KEY,
+        time INTEGER NOT NULL,
+        instance TEXT NOT NULL,
+        band TEXT,
+        frequency_hz INTEGER,
+        mode TEXT NOT NULL,
+        snr INTEGER NOT NULL,
+        delta_time_s REAL NOT NULL,
+        delta_frequency_hz INTEGER NOT NULL,
+        message TEXT NOT NULL,
+        from_call TEXT,
+        to_call TEXT,
+        grid TEXT,
+        low_confidence INTEGER NOT NULL,
+        off_air INTEGER NOT NULL
+    );
+    CREATE INDEX IF NOT EXISTS decodes_time ON decodes (time);
+    CREATE INDEX IF NOT EXISTS decodes_from_call ON decodes (from_call, band, time);
+    CREATE INDEX IF NOT EXISTS decodes_to_call ON decodes (to_call);
+    CREATE INDEX IF NOT EXISTS decodes_grid ON decodes (grid);
+    CREATE INDEX IF NOT EXISTS decodes_band ON decodes (band, time);
+    CREATE INDEX IF NOT EXISTS decodes_snr ON decodes (snr);
+
+    CREATE TABLE IF NOT EXISTS wspr_decodes (
+        id INTEGER PRIMARY KEY,
+        time INTEGER NOT NULL,
+        instance TEXT NOT NULL,
+        band TEXT,
+        frequency_hz INTEGER NOT NULL,
+        snr INTEGER NOT NULL,
+        delta_time_s REAL NOT NULL,
+        drift INTEGER NOT NULL,
+        callsign TEXT NOT NULL,
+        grid TEXT,
+        power_dbm INTEGER NOT NULL
+    );
+    CREATE INDEX IF NOT EXISTS wspr_decodes_time ON wspr_decodes (time);
+    CREATE INDEX IF NOT EXISTS wspr_decodes_callsign ON wspr_decodes (callsign, band, time);
+    CREATE INDEX IF NOT EXISTS wspr_decodes_grid ON wspr_decodes (grid);
+
+    CREATE TABLE IF NOT EXISTS statuses (
+        id INTEGER PRIMARY KEY,
+        time INTEGER NOT NULL,
+        instance TEXT NOT NULL,
+        dial_frequency INTEGER NOT NULL,
+        band TEXT,
+        mode TEXT NOT NULL,
+        dx_call TEXT,
+        dx_grid TEXT,
+        de_call TEXT,
+        de_grid TEXT,
+        tx_enabled INTEGER NOT NULL,
+        transmitting INTEGER NOT NULL,
+        rx_df INTEGER NOT NULL,
+        tx_df INTEGER NOT NULL,
+        tx_message TEXT,
+        special_operation_mode INTEGER NOT NULL
+    );
+    CREATE INDEX IF NOT EXISTS statuses_time ON statuses (time);
+    CREATE INDEX IF NOT EXISTS statuses_dx_call ON statuses (dx_call);
+
+    CREATE TABLE IF NOT EXISTS qsos (
+        id INTEGER PRIMARY KEY,
+        time_on INTEGER NOT NULL,
+        time_off INTEGER NOT NULL,
+        instance TEXT NOT NULL,
+        call TEXT NOT NULL,
+        grid TEXT,
+        band TEXT,
+        frequency_hz INTEGER NOT NULL,
+        mode TEXT NOT NULL,
+        report_sent TEXT,
+        report_received TEXT,
+        tx_power TEXT,
+        name TEXT,
+        comments TEXT,
+        operator_call TEXT,
+        my_call TEXT,
+        my_grid TEXT,
+        exchange_sent TEXT,
+        exchange_received TEXT,
+        propagation_mode TEXT
+    );
+    CREATE INDEX IF NOT EXISTS qsos_call ON qsos (call, band);
+    CREATE INDEX IF NOT EXISTS qsos_grid ON qsos (grid);
+    CREATE INDEX IF NOT EXISTS qsos_time_on ON qsos (time_on);
+";
+
+// "store" in config.json, days each kind of record is kept, QSOs are never pruned
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct StoreConfig {
+    // SQLite database file, relative paths are taken from the config file's directory, off when unset
+    pub database: Option<String>,
+    pub decode_days: Option<u32>,
+    pub wspr_days: Option<u32>,
+    pub status_days: Option<u32>,
+}
+
+impl Default for StoreConfig {
+    fn default() -> Self {
+        Self { database: None, decode_days: Some(90), wspr_days: Some(365), status_days: Some(30) }
+    }
+}
+
+// a search of the stored decodes, every criterion that is set must match
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct DecodeQuery {
+    // the sending station
+    pub call: Option<String>,
+    // a grid prefix, "FN" or "FN31"
+    pub grid: Option<String>,
+    pub band: Option<String>,
+    pub since: Option<DateTime<Utc>>,
+    pub until: Option<DateTime<Utc>>,
+    pub min_snr: Option<i32>,
+    pub limit: Option<u32>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct StoredDecode {
+    pub time: DateTime<Utc>,
+    pub instance: String,
+    pub band: Option<String>,
+    pub frequency_hz: Option<u64>,
+    pub mode: String,
+    pub snr: i32,
+    pub delta_frequency_hz: u32,
+    pub message: String,
+    pub from_call: Option<String>,
+    pub grid: Option<String>,
+}
+
+impl std::fmt::Display for StoredDecode {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{} {:<5} {:>3} dB {}", self.time.format("%Y-%m-%d %H:%M:%S"), self.band.as_deref().unwrap_or("-"), self.snr, self.message)
+    }
+}
+
+// decodes only carry the time of day, they belong to the latest such time that isn't in the future
+pub fn decode_timestamp(time: NaiveTime, now: DateTime<Utc>) -> DateTime<Utc> {
+    let today = Utc.from_utc_datetime(&now.date_naive().and_time(time));
+    if today > now + Duration::minutes(1) { today - Duration::days(1) } else { today }
+}
+
+fn from_timestamp(seconds: i64) -> DateTime<Utc> {
+    Utc.timestamp_opt(seconds, 0).single().unwrap_or_default()
+}
+
+fn stored_decode(row: &Row) -> rusqlite::Result<StoredDecode> {
+    Ok(StoredDecode {
+        time: from_timestamp(row.get(0)?),
+        instance: row.get(1)?,
+        band: row.get(2)?,
+        frequency_hz: row.get::<_, Option<i64>>(3)?.map(|frequency_hz| frequency_hz as u64),
+        mode: row.get(4)?,
+        snr: row.get(5)?,
+        delta_frequency_hz: row.get(6)?,
+        message: row.get(7)?,
+        from_call: row.get(8)?,
+        grid: row.get(9)?,
+    })
+}
+
+// a logged QSO as stored, with what a contest needs to score it again
+#[derive(Debug, Clone)]
+pub struct StoredQso {
+    pub time_on: DateTime<Utc>,
+    pub call: String,
+    pub frequency_hz: u64,
+    pub mode: String,
+    pub my_call: Option<String>,
+    pub my_grid: Option<String>,
+    pub exchange_sent: Option<String>,
+    pub exchange_received: Option<String>,
+}
+
+impl StoredQso {
+    // None without the date, time and frequency a contest QSO needs
+    pub fn from_adif(qso: &AdifQso) -> Option<Self> {
+        qso.time_on?;
+        Some(Self {
+            time_on: Utc.from_utc_datetime(&qso.time_on()?),
+            call: qso.call.clone(),
+            frequency_hz: (qso.freq_mhz? * 1_000_000.0).round() as u64,
+            mode: qso.mode_name().to_string(),
+            my_call: qso.station_callsign.clone().or_else(|| qso.operator.clone()),
+            my_grid: qso.my_gridsquare.clone(),
+            exchange_sent: qso.stx_string.clone(),
+            exchange_received: qso.srx_string.clone(),
+        })
+    }
+}
+
+// the fields of a Status worth a row when they change, the rest change with every period
+#[derive(Debug, Clone, PartialEq)]
+struct StatusKey {
+    dial_frequency: u64,
+    mode: String,
+    dx_call: String,
+    de_call: String,
+    tx_enabled: bool,
+    transmitting: bool,
+    tx_df: u32,
+    tx_message: String,
+    special_operation_mode: u8,
+}
+
+impl StatusKey {
+    fn new(status: &Status) -> Self {
+        Self {
+            dial_frequency: status.dial_frequency,
+            mode: status.mode.clone(),
+            dx_call: status.dx_call.clone(),
+            de_call: status.de_call.clone(),
+            tx_enabled: status.tx_enabled,
+            transmitting: status.transmitting,
+            tx_df: status.tx_df,
+            tx_message: status.tx_message.clone(),
+            special_operation_mode: status.special_operation_mode,
+        }
+    }
+}
+
+// everything the server hears, kept in SQLite
+pub struct Store {
+    connection: Connection,
+    last_status: HashMap<String, StatusKey>,
+    last_pruned: Option<DateTime<Utc>>,
+}
+
+impl Store {
+    pub fn open(path: &Path) -> rusqlite::Result<Self> {
+        let connection = Connection::open(path)?;
+        // a decode is written every few milliseconds during a period, WAL keeps that cheap
+        connection.pragma_update(None, "journal_mode", "WAL")?;
+        connection.pragma_update(None, "synchronous", "NORMAL")?;
+        connection.execute_batch(SCHEMA)?;
+        Ok(Self { connection, last_status: HashMap::new(), last_pruned: None })
+    }
+
+    pub fn add_decode(&self, decode: &Decode, message: &Ft8Message, now: DateTime<Utc>) -> rusqlite::Result<()> {
+        self.connection.prepare_cached(
+            "INSERT INTO decodes (time, instance, band, frequency_hz, mode, snr, delta_time_s, delta_frequency_hz, message,
+                from_call, to_call, grid, low_confidence, off_air) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14)",
+        )?.execute(params![
+            decode_timestamp(decode.time, now).timestamp(),
+            decode.id,
+            decode.band,
+            decode.rf_frequency_hz.map(|frequency_hz| frequency_hz as i64),
+            decode.mode_name().unwrap_or(&decode.mode),
+            decode.snr,
+            decode.delta_time_s,
+            decode.delta_frequency_hz,
+            decode.message,
+            message.from_call(),
+            message.to_call(),
+            message.grid(),
+            decode.low_confidence,
+            decode.off_air,
+        ])?;
+        Ok(())
+    }
+
+    pub fn add_wspr_decode(&self, wspr_decode: &WSPRDecode, iaru_region: u8, now: DateTime<Utc>) -> rusqlite::Result<()> {
+        self.connection.prepare_cached(
+            "INSERT INTO wspr_decodes (time, instance, band, frequency_hz, snr, delta_time_s, drift, callsign, grid, power_dbm)
+                VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)",
+        )?.execute(params![
+            decode_timestamp(wspr_decode.time, now).timestamp(),
+            wspr_decode.id,
+            band_for_frequency(wspr_decode.frequency_hz, iaru_region),
+            wspr_decode.frequency_hz as i64,
+            wspr_decode.snr,
+            wspr_decode.delta_time_s,
+            wspr_decode.drift,
+            wspr_decode.callsign,
+            non_empty(&wspr_decode.grid),
+            wspr_decode.power_dbm,
+        ])?;
+        Ok(())
+    }
+
+    // a row only when something that matters has changed since the instance's last Status
+    pub fn add_status(&mut self, status: &Status, iaru_region: u8, now: DateTime<Utc>) -> rusqlite::Result<()> {
+        let key = StatusKey::new(status);
+        if self.last_status.get(&status.id) == Some(&key) {
+            return Ok(());
+        }
+        self.connection.prepare_cached(
+            "INSERT INTO statuses (time, instance, dial_frequency, band, mode, dx_call, dx_grid, de_call, de_grid, tx_enabled,
+                transmitting, rx_df, tx_df, tx_message, special_operation_mode) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15)",
+        )?.execute(params![
+            now.timestamp(),
+            status.id,
+            status.dial_frequency as i64,
+            band_for_frequency(status.dial_frequency, iaru_region),
+            status.mode,
+            non_empty(&status.dx_call),
+            non_empty(&status.dx_grid),
+            non_empty(&status.de_call),
+            non_empty(&status.de_grid),
+            status.tx_enabled,
+            status.transmitting,
+            status.rx_df,
+            status.tx_df,
+            non_empty(&status.tx_message),
+            status.special_operation_mode,
+        ])?;
+        self.last_status.insert(status.id.clone(), key);
+        Ok(())
+    }
+
+    pub fn add_qso(&self, logdata: &LogData, iaru_region: u8) -> rusqlite::Result<()> {
+        self.connection.prepare_cached(
+            "INSERT INTO qsos (time_on, time_off, instance, call, grid, band, frequency_hz, mode, report_sent, report_received,
+                tx_power, name, comments, operator_call, my_call, my_grid, exchange_sent, exchange_received, propagation_mode)
+                VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17, ?18, ?19)",
+        )?.execute(params![
+            logdata.date_time_on.timestamp(),
+            logdata.date_time_off.timestamp(),
+            logdata.id,
+            logdata.dx_call.to_uppercase(),
+            non_empty(&logdata.dx_grid),
+            band_for_frequency(logdata.tx_frequency_hz, iaru_region),
+            logdata.tx_frequency_hz as i64,
+            logdata.mode,
+            non_empty(&logdata.report_sent),
+            non_empty(&logdata.report_received),
+            non_empty(&logdata.tx_power),
+            non_empty(&logdata.name),
+            non_empty(&logdata.comments),
+            non_empty(&logdata.operator_call),
+            non_empty(&logdata.my_call),
+            non_empty(&logdata.my_grid),
+            non_empty(&logdata.exchange_sent),
+            non_empty(&logdata.exchange_received),
+            non_empty(&logdata.adif_propagation_mode),
+        ])?;
+        Ok(())
+    }
+
+    // newest first
+    pub fn decodes(&self, query: &DecodeQuery) -> rusqlite::Result<Vec<StoredDecode>> {
+        let mut sql = "SELECT time, instance, band, frequency_hz, mode, snr, delta_frequency_hz, message, from_call, grid
+            FROM decodes WHERE 1 = 1".to_string();
+        let mut values: Vec<rusqlite::types::Value> = Vec::new();
+        if let Some(call) = &query.call {
+            sql.push_str(" AND from_call = ?");
+            values.push(call.to_uppercase().into());
+        }
+        if let Some(grid) = &query.grid {
+            // a range rather than LIKE so the index on grid is used
+            sql.push_str(" AND grid >= ? AND grid < ?");
+            let grid = grid.to_uppercase();
+            values.push(grid.clone().into());
+            values.push(format!("{}\u{10ffff}", grid).into());
+        }
+        if let Some(band) = &query.band {
+            sql.push_str(" AND band = ?");
+            values.push(band.to_lowercase().into());
+        }
+        if let Some(since) = query.since {
+            sql.push_str(" AND time >= ?");
+            values.push(since.timestamp().into());
+        }
+        if let Some(until) = query.until {
+            sql.push_str(" AND time <= ?");
+            values.push(until.timestamp().into());
+        }
+        if let Some(min_snr) = query.min_snr {
+            sql.push_str(" AND snr >= ?");
+            values.push(i64::from(min_snr).into());
+        }
+        sql.push_str(" ORDER BY time DESC LIMIT ?");
+        values.push(i64::from(query.limit.unwrap_or(100)).into());
+        let mut statement = self.connection.prepare_cached(&sql)?;
+        let rows = statement.query_map(params_from_iter(values), stored_decode)?;
+        rows.collect()
+    }
+
+    // "when did we last hear VK9X on 20m?"
+    pub fn last_heard(&self, call: &str, band: Option<&str>) -> rusqlite::Result<Option<StoredDecode>> {
+        let query = DecodeQuery { call: Some(call.to_string()), band: band.map(|band| band.to_string()), limit: Some(1), ..DecodeQuery::default() };
+        Ok(self.decodes(&query)?.into_iter().next())
+    }
+
+    // oldest first, only QSOs logged while the instance's last Status was in this special operating mode
+    pub fn contest_qsos_since(&self, since: DateTime<Utc>, special_operation_mode: u8) -> rusqlite::Result<Vec<StoredQso>> {
+        let mut statement = self.connection.prepare_cached(
+            "SELECT time_on, call, frequency_hz, mode, my_call, my_grid, exchange_sent, exchange_received
+                FROM qsos WHERE time_on >= ?1 AND (SELECT special_operation_mode FROM statuses
+                    WHERE statuses.instance = qsos.instance AND statuses.time <= qsos.time_off
+                    ORDER BY statuses.time DESC LIMIT 1) = ?2
+                ORDER BY time_on",
+        )?;
+        let rows = statement.query_map(params![since.timestamp(), special_operation_mode], |row| Ok(StoredQso {
+            time_on: from_timestamp(row.get(0)?),
+            call: row.get(1)?,
+            frequency_hz: row.get::<_, i64>(2)? as u64,
+            mode: row.get(3)?,
+            my_call: row.get(4)?,
+            my_grid: row.get(5)?,
+            exchange_sent: row.get(6)?,
+            exchange_received: row.get(7)?,
+        }))?;
+        rows.collect()
+    }
+
+    // deletes what is older than the retention settings, once an hour
+    pub fn prune(&mut self, config: &StoreConfig, now: DateTime<Utc>) -> rusqlite::Result<usize> {
+        if self.last_pruned.map(|pruned| now - pruned < Duration::minutes(PRUNE_INTERVAL_MINUTES)).unwrap_or(false) {
+            return Ok(0);
+        }
+        self.last_pruned = Some(now);
+        let mut deleted = 0;
+        for (table, days) in [("decodes", config.decode_days), ("wspr_decodes", config.wspr_days), ("statuses", config.status_days)] {
+            if let Some(days) = days {
+                let cutoff = (now - Duration::days(i64::from(days))).timestamp();
+                deleted += self.connection.execute(&format!("DELETE FROM {} WHERE time < ?1", table), params![cutoff])?;
+            }
+        }
+        Ok(deleted)
+    }
+}
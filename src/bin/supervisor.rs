@@ -0,0 +1,291 @@
+use std::collections::HashMap;
+use chrono::{DateTime, Duration, Utc};
+use serde_derive::Deserialize;
+use super::*;
+
+// where each US licence class may send data modes, in Hz, 60m is the five USB channels
+const US_EXTRA: &[(u64, u64)] = &[
+    (135_700, 137_800), (472_000, 479_000), (1_800_000, 2_000_000), (3_500_000, 3_600_000),
+    (5_330_500, 5_333_300), (5_346_500, 5_349_300), (5_357_000, 5_359_800), (5_371_500, 5_374_300), (5_403_500, 5_406_300),
+    (7_000_000, 7_125_000), (10_100_000, 10_150_000), (14_000_000, 14_150_000), (18_068_000, 18_110_000),
+    (21_000_000, 21_200_000), (24_890_000, 24_930_000), (28_000_000, 28_300_000), (50_100_000, 54_000_000),
+    (144_100_000, 148_000_000), (222_000_000, 225_000_000), (420_000_000, 450_000_000), (902_000_000, 928_000_000),
+    (1_240_000_000, 1_300_000_000),
+];
+const US_GENERAL: &[(u64, u64)] = &[
+    (135_700, 137_800), (472_000, 479_000), (1_800_000, 2_000_000), (3_525_000, 3_600_000),
+    (5_330_500, 5_333_300), (5_346_500, 5_349_300), (5_357_000, 5_359_800), (5_371_500, 5_374_300), (5_403_500, 5_406_300),
+    (7_025_000, 7_125_000), (10_100_000, 10_150_000), (14_025_000, 14_150_000), (18_068_000, 18_110_000),
+    (21_025_000, 21_200_000), (24_890_000, 24_930_000), (28_000_000, 28_300_000), (50_100_000, 54_000_000),
+    (144_100_000, 148_000_000), (222_000_000, 225_000_000), (420_000_000, 450_000_000), (902_000_000, 928_000_000),
+    (1_240_000_000, 1_300_000_000),
+];
+// technicians have CW only on 80m, 40m and 15m
+const US_TECHNICIAN: &[(u64, u64)] = &[
+    (28_000_000, 28_300_000), (50_100_000, 54_000_000), (144_100_000, 148_000_000), (222_000_000, 225_000_000),
+    (420_000_000, 450_000_000), (902_000_000, 928_000_000), (1_240_000_000, 1_300_000_000),
+];
+
+fn licence_segments(class: &str) -> Option<&'static [(u64, u64)]> {
+    match class.to_lowercase().as_str() {
+        "us_extra" => Some(US_EXTRA),
+        "us_advanced" | "us_general" => Some(US_GENERAL),
+        "us_technician" => Some(US_TECHNICIAN),
+        _ => None,
+    }
+}
+
+// the whole signal has to be inside one segment
+fn fits(mut segments: impl Iterator<Item = (u64, u64)>, low_hz: u64, high_hz: u64) -> bool {
+    segments.any(|(low, high)| low <= low_hz && high_hz <= high)
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct TxSegment {
+    pub low_hz: u64,
+    pub high_hz: u64,
+}
+
+// "tx_supervisor" in config.json, guardrails for running unattended, each check is off when unset
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct SupervisorConfig {
+    // transmit periods in a row with nothing decoded that was sent to us
+    pub max_unanswered_periods: Option<u32>,
+    pub halt_on_watchdog: bool,
+    // when not empty, transmitting anywhere else is halted
+    pub segments: Vec<TxSegment>,
+    // us_extra, us_advanced, us_general or us_technician
+    pub licence_class: Option<String>,
+    // halt once nobody has used the console, the TUI or the control API, or picked a call or enabled TX
+    // in WSJT-X, for this long. An open dashboard doesn't count until a command is sent from it
+    pub control_timeout_seconds: Option<u64>,
+}
+
+impl Default for SupervisorConfig {
+    fn default() -> Self {
+        Self {
+            max_unanswered_periods: None,
+            halt_on_watchdog: true,
+            segments: Vec::new(),
+            licence_class: None,
+            control_timeout_seconds: None,
+        }
+    }
+}
+
+impl SupervisorConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        if let Some(class) = &self.licence_class {
+            licence_segments(class)
+                .ok_or(format!("tx_supervisor: unknown licence_class '{}', use us_extra, us_advanced, us_general or us_technician", class))?;
+        }
+        if let Some(segment) = self.segments.iter().find(|segment| segment.low_hz >= segment.high_hz) {
+            return Err(format!("tx_supervisor: segment {}-{} Hz is empty", segment.low_hz, segment.high_hz));
+        }
+        Ok(())
+    }
+
+    // why a signal from low_hz to high_hz may not be sent, None when it may
+    fn frequency_problem(&self, low_hz: u64, high_hz: u64) -> Option<String> {
+        let range = format!("{}-{}", format_frequency_mhz(low_hz), format_frequency_mhz(high_hz));
+        if !self.segments.is_empty() && !fits(self.segments.iter().map(|segment| (segment.low_hz, segment.high_hz)), low_hz, high_hz) {
+            return Some(format!("{} is outside the configured segments", range));
+        }
+        let class = self.licence_class.as_deref()?;
+        let segments = licence_segments(class)?;
+        if !fits(segments.iter().copied(), low_hz, high_hz) {
+            return Some(format!("{} is outside {} privileges", range, class));
+        }
+        None
+    }
+}
+
+#[derive(Debug, Default)]
+struct SupervisedInstance {
+    was_transmitting: bool,
+    watchdog: bool,
+    unanswered_periods: u32,
+    // what we last halted for, so one problem sends one HaltTx
+    halted: Option<String>,
+}
+
+// seconds after one of our commands in which WSJT-X changing its DX call or enabling TX is taken as our doing
+const COMMAND_SECONDS: i64 = 30;
+
+// the operator picking a call or enabling TX in WSJT-X itself, rather than a Reply of ours doing it
+fn operator_action(previous: Option<&Status>, status: &Status, last_command: Option<DateTime<Utc>>, now: DateTime<Utc>) -> bool {
+    let previous = match previous {
+        Some(previous) => previous,
+        None => return false,
+    };
+    let changed = previous.dx_call != status.dx_call || (status.tx_enabled && !previous.tx_enabled);
+    changed && last_command.map(|sent| now - sent > Duration::seconds(COMMAND_SECONDS)).unwrap_or(true)
+}
+
+// watches each instance's Status and halts transmissions that shouldn't be happening
+#[derive(Debug, Default)]
+pub struct TxSupervisor {
+    instances: HashMap<String, SupervisedInstance>,
+}
+
+impl TxSupervisor {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // a decode addressed to us is an answer, whatever we were sending
+    pub fn replied(&mut self, id: &str) {
+        if let Some(instance) = self.instances.get_mut(id) {
+            instance.unanswered_periods = 0;
+        }
+    }
+
+    // the reason to halt this instance now, if there is a new one
+    fn check(&mut self, status: &Status, config: &SupervisorConfig, control_lost: Option<u64>) -> Option<String> {
+        let instance = self.instances.entry(status.id.clone()).or_default();
+        let active = status.transmitting || status.tx_enabled;
+        if status.transmitting && !instance.was_transmitting {
+            instance.unanswered_periods += 1;
+        }
+        instance.was_transmitting = status.transmitting;
+        let watchdog_tripped = status.tx_watchdog && !instance.watchdog;
+        instance.watchdog = status.tx_watchdog;
+        if !active {
+            instance.unanswered_periods = 0;
+            instance.halted = None;
+            return None;
+        }
+        let tx_hz = status.dial_frequency + status.tx_df as u64;
+        let mode = non_empty(&status.tx_mode).unwrap_or(&status.mode);
+        let problem = if watchdog_tripped && config.halt_on_watchdog {
+            Some("the TX watchdog tripped".to_string())
+        } else if let Some(problem) = config.frequency_problem(tx_hz, tx_hz + signal_width_hz(mode)) {
+            Some(problem)
+        } else if let Some(timeout) = control_lost {
+            Some(format!("no operator activity for over {} s", timeout))
+        } else {
+            config.max_unanswered_periods
+                .filter(|max| instance.unanswered_periods > *max)
+                .map(|max| format!("more than {} transmit periods without a reply", max))
+        };
+        match problem {
+            Some(problem) if instance.halted.as_ref() != Some(&problem) => {
+                instance.halted = Some(problem.clone());
+                Some(problem)
+            }
+            _ => None,
+        }
+    }
+}
+
+// called with every Status, sends HaltTx the first time each problem is seen
+pub fn supervise_tx(status: &Status, app_state: &AppState) {
+    let config = app_state.config();
+    let now = Utc::now();
+    // called before the instance takes this Status, so it still has the previous one
+    let (previous, last_command) = match app_state.instances.lock().unwrap().get(&status.id) {
+        Some(instance) => (instance.status.clone(), instance.last_command),
+        None => (None, None),
+    };
+    if operator_action(previous.as_ref(), status, last_command, now) {
+        app_state.control_activity();
+    }
+    let control_lost = config.tx_supervisor.control_timeout_seconds
+        .filter(|timeout| app_state.out_of_contact(now) > Duration::seconds(*timeout as i64));
+    let problem = app_state.tx_supervisor.lock().unwrap().check(status, &config.tx_supervisor, control_lost);
+    let problem = match problem {
+        Some(problem) => problem,
+        None => return,
+    };
+    let halt_tx = HaltTx { message_type: 8, id: status.id.clone(), auto_tx_only: false };
+    match send_to_instance(app_state, &status.id, encode_halt_tx(&halt_tx)) {
+        Ok(()) => app_state.log(&format!("TX halted on {}: {}", status.id, problem)),
+        Err(e) => app_state.log(&format!("Could not halt TX on {} ({}): {}", status.id, problem, e)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn status(dx_call: &str, tx_enabled: bool) -> Status {
+        Status { id: "WSJT-X".to_string(), dx_call: dx_call.to_string(), tx_enabled, ..Default::default() }
+    }
+
+    #[test]
+    fn operator_actions() {
+        let now = Utc::now();
+        let long_ago = Some(now - Duration::minutes(5));
+        let just_now = Some(now - Duration::seconds(3));
+        let idle = status("K1ABC", false);
+        // picking another call or enabling TX in WSJT-X is the operator at the rig
+        assert!(operator_action(Some(&idle), &status("JA1XYZ", false), None, now));
+        assert!(operator_action(Some(&idle), &status("K1ABC", true), long_ago, now));
+        // unless a Reply of ours just did the same
+        assert!(!operator_action(Some(&idle), &status("JA1XYZ", true), just_now, now));
+        // Status repeating itself, TX going off or a first Status are not
+        assert!(!operator_action(Some(&idle), &status("K1ABC", false), None, now));
+        assert!(!operator_action(Some(&status("K1ABC", true)), &idle, None, now));
+        assert!(!operator_action(None, &status("JA1XYZ", true), None, now));
+    }
+
+    fn on_air(dial_frequency: u64, tx_df: u32, transmitting: bool) -> Status {
+        Status {
+            id: "WSJT-X".to_string(),
+            dial_frequency,
+            tx_df,
+            mode: "FT8".to_string(),
+            tx_enabled: true,
+            transmitting,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn halts() {
+        let config = SupervisorConfig {
+            max_unanswered_periods: Some(2),
+            licence_class: Some("us_general".to_string()),
+            ..SupervisorConfig::default()
+        };
+        let mut supervisor = TxSupervisor::new();
+        // inside General privileges, then 1500 Hz up from the bottom of 20m where only Extras may be
+        assert_eq!(supervisor.check(&on_air(14_074_000, 1500, true), &config, None), None);
+        let outside = Some("14.001500-14.001550 is outside us_general privileges".to_string());
+        assert_eq!(supervisor.check(&on_air(14_000_000, 1500, true), &config, None), outside);
+        // one HaltTx per problem, until TX goes off
+        assert_eq!(supervisor.check(&on_air(14_000_000, 1500, true), &config, None), None);
+        assert_eq!(supervisor.check(&Status { tx_enabled: false, ..on_air(14_000_000, 1500, false) }, &config, None), None);
+        assert_eq!(supervisor.check(&on_air(14_000_000, 1500, true), &config, None), outside);
+        // the signal has to end inside the segment too
+        assert!(supervisor.check(&on_air(14_148_000, 1990, false), &config, None).is_some());
+
+        let mut supervisor = TxSupervisor::new();
+        assert_eq!(supervisor.check(&on_air(14_074_000, 1500, false), &config, Some(600)),
+            Some("no operator activity for over 600 s".to_string()));
+        let watchdog = Status { tx_watchdog: true, ..on_air(14_074_000, 1500, false) };
+        assert_eq!(supervisor.check(&watchdog, &config, None), Some("the TX watchdog tripped".to_string()));
+        let ignored = SupervisorConfig { halt_on_watchdog: false, ..config.clone() };
+        assert_eq!(TxSupervisor::new().check(&watchdog, &ignored, None), None);
+    }
+
+    #[test]
+    fn unanswered_periods() {
+        let config = SupervisorConfig { max_unanswered_periods: Some(2), ..SupervisorConfig::default() };
+        let mut supervisor = TxSupervisor::new();
+        let periods = |supervisor: &mut TxSupervisor, count: usize| -> Option<String> {
+            let mut halt = None;
+            for _ in 0..count {
+                halt = supervisor.check(&on_air(14_074_000, 1500, true), &config, None).or(halt);
+                halt = supervisor.check(&on_air(14_074_000, 1500, false), &config, None).or(halt);
+            }
+            halt
+        };
+        assert_eq!(periods(&mut supervisor, 2), None);
+        // a reply starts the count again
+        supervisor.replied("WSJT-X");
+        assert_eq!(periods(&mut supervisor, 2), None);
+        assert_eq!(periods(&mut supervisor, 1), Some("more than 2 transmit periods without a reply".to_string()));
+    }
+}
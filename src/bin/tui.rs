@@ -0,0 +1,452 @@
+use std::sync::Arc;
+use std::time::Duration as StdDuration;
+use chrono::{NaiveTime, Timelike, Utc, Duration};
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind};
+use ratatui::layout::{Constraint, Direction, Layout, Rect};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Block, Borders, List, ListItem, ListState, Paragraph};
+use ratatui::Frame;
+use super::*;
+
+// CQs older than this drop off the active CQ list
+const CQ_LIST_MINUTES: i64 = 2;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Pane {
+    BandActivity,
+    RxFrequency,
+    Cqs,
+    Callers,
+}
+
+// one line of a decode pane, either a period separator or a decode that can be replied to
+enum Row {
+    Period(NaiveTime),
+    Decode(Box<ActivityEntry>),
+}
+
+impl Row {
+    fn key(&self) -> RowKey {
+        match self {
+            Row::Period(period) => RowKey::Period(*period),
+            Row::Decode(entry) => RowKey::decode(&entry.decode),
+        }
+    }
+}
+
+// what a selection sticks to while the rows are rebuilt underneath it
+#[derive(Debug, Clone, PartialEq)]
+enum RowKey {
+    Period(NaiveTime),
+    Decode(String, NaiveTime, String),
+}
+
+impl RowKey {
+    fn decode(decode: &Decode) -> Self {
+        RowKey::Decode(decode.id.clone(), decode.time, decode.message.clone())
+    }
+}
+
+// the selected row of a pane, ListState only holds its index for drawing
+#[derive(Default)]
+struct Selection {
+    list: ListState,
+    key: Option<RowKey>,
+}
+
+impl Selection {
+    // a selected decode that has gone from the pane drops the selection, so the pane follows again
+    fn resolve(&mut self, keys: &[RowKey]) {
+        let index = self.key.as_ref().and_then(|key| keys.iter().position(|row| row == key));
+        if index.is_none() {
+            self.key = None;
+        }
+        self.list.select(index);
+    }
+
+    fn clear(&mut self) {
+        self.key = None;
+        self.list.select(None);
+    }
+}
+
+struct View {
+    band_activity: Vec<Row>,
+    rx_frequency: Vec<Row>,
+    cqs: Vec<ActivityEntry>,
+    callers: Vec<Caller>,
+    status: Option<Status>,
+    instance_id: Option<String>,
+    iaru_region: u8,
+    last_log: Option<String>,
+    // only while WSJT-X is in Hound mode
+    hound: Option<String>,
+}
+
+struct TuiState {
+    focus: Pane,
+    band_activity: Selection,
+    rx_frequency: Selection,
+    cqs: Selection,
+    callers: Selection,
+    feedback: String,
+}
+
+impl TuiState {
+    fn selection(&mut self, pane: Pane) -> &mut Selection {
+        match pane {
+            Pane::BandActivity => &mut self.band_activity,
+            Pane::RxFrequency => &mut self.rx_frequency,
+            Pane::Cqs => &mut self.cqs,
+            Pane::Callers => &mut self.callers,
+        }
+    }
+}
+
+fn period_start(time: NaiveTime, tr_period: u32) -> NaiveTime {
+    let tr_period = tr_period.max(1);
+    let seconds = time.num_seconds_from_midnight() / tr_period * tr_period;
+    NaiveTime::from_num_seconds_from_midnight_opt(seconds, 0).unwrap_or(time)
+}
+
+fn group_by_period<'a>(entries: impl Iterator<Item = &'a ActivityEntry>, tr_period: u32) -> Vec<Row> {
+    let mut rows = Vec::new();
+    let mut current = None;
+    for entry in entries {
+        let period = period_start(entry.decode.time, tr_period);
+        if current != Some(period) {
+            rows.push(Row::Period(period));
+            current = Some(period);
+        }
+        rows.push(Row::Decode(Box::new(entry.clone())));
+    }
+    rows
+}
+
+impl View {
+    fn collect(app_state: &AppState) -> Self {
+        let tr_period = app_state.qso_tracker.lock().unwrap().tr_period;
+        let activity = app_state.activity.lock().unwrap();
+        let band_activity = group_by_period(activity.band_activity.iter().filter(|entry| entry.shown), tr_period);
+        let rx_frequency = group_by_period(activity.rx_frequency.iter(), tr_period);
+        let cq_calls = app_state.qso_tracker.lock().unwrap().cqs.clone();
+        let cutoff = Utc::now() - Duration::minutes(CQ_LIST_MINUTES);
+        // the latest CQ decode from each station still calling
+        let mut cqs: Vec<ActivityEntry> = Vec::new();
+        for entry in activity.band_activity.iter().rev() {
+            if let Ft8Message::Cq { call, .. } = parse_ft8_message(&entry.decode.message) {
+                let still_calling = cq_calls.get(&call).map(|cq| cq.heard >= cutoff).unwrap_or(false);
+                let listed = cqs.iter().any(|listed| parse_ft8_message(&listed.decode.message).from_call() == Some(call.as_str()));
+                if still_calling && !listed {
+                    cqs.push(entry.clone());
+                }
+            }
+        }
+        let instances = app_state.instances.lock().unwrap();
+        let latest = instances.latest();
+        Self {
+            band_activity,
+            rx_frequency,
+            cqs,
+            callers: app_state.callers.lock().unwrap().sorted(),
+            status: latest.and_then(|instance| instance.status.clone()),
+            instance_id: latest.map(|instance| instance.id.clone()),
+            iaru_region: app_state.config().iaru_region,
+            last_log: app_state.last_log.lock().unwrap().clone(),
+            hound: app_state.hound.lock().unwrap().summary(Utc::now()),
+        }
+    }
+
+    fn keys(&self, pane: Pane) -> Vec<RowKey> {
+        match pane {
+            Pane::BandActivity => self.band_activity.iter().map(Row::key).collect(),
+            Pane::RxFrequency => self.rx_frequency.iter().map(Row::key).collect(),
+            Pane::Cqs => self.cqs.iter().map(|entry| RowKey::decode(&entry.decode)).collect(),
+            Pane::Callers => self.callers.iter().map(|caller| RowKey::decode(&caller.decode)).collect(),
+        }
+    }
+
+    fn selected_decode(&self, tui: &TuiState) -> Option<&Decode> {
+        let selection = match tui.focus {
+            Pane::BandActivity => &tui.band_activity,
+            Pane::RxFrequency => &tui.rx_frequency,
+            Pane::Cqs => &tui.cqs,
+            Pane::Callers => &tui.callers,
+        };
+        let key = selection.key.as_ref()?;
+        let decodes: Vec<&Decode> = match tui.focus {
+            Pane::BandActivity | Pane::RxFrequency => {
+                let rows = if tui.focus == Pane::BandActivity { &self.band_activity } else { &self.rx_frequency };
+                rows.iter().filter_map(|row| match row {
+                    Row::Decode(entry) => Some(&entry.decode),
+                    Row::Period(_) => None,
+                }).collect()
+            }
+            Pane::Cqs => self.cqs.iter().map(|entry| &entry.decode).collect(),
+            Pane::Callers => self.callers.iter().map(|caller| &caller.decode).collect(),
+        };
+        decodes.into_iter().find(|decode| RowKey::decode(decode) == *key)
+    }
+}
+
+fn need_colour(needs: Option<&Needs>) -> Color {
+    match needs.and_then(|needs| needs.headline()) {
+        Some((WorkedCategory::Dxcc, NeedStatus::New)) => Color::LightRed,
+        Some((WorkedCategory::Dxcc, _)) => Color::Red,
+        Some((WorkedCategory::Continent, _)) => Color::Cyan,
+        Some((WorkedCategory::CqZone, _)) => Color::Magenta,
+        Some((WorkedCategory::State, _)) => Color::Blue,
+        Some((WorkedCategory::Grid, _)) => Color::Yellow,
+        Some((WorkedCategory::Call, _)) => Color::Green,
+        None => Color::DarkGray,
+    }
+}
+
+fn decode_line(entry: &ActivityEntry) -> Line<'static> {
+    let decode = &entry.decode;
+    let snr_colour = if decode.snr >= 0 { Color::Green } else { Color::Red };
+    let label = entry.needs.as_ref().map(|needs| needs.label()).unwrap_or_default();
+    Line::from(vec![
+        Span::raw(format!("{} ", decode.time.format("%H%M%S"))),
+        Span::styled(format!("{:>3} ", decode.snr), Style::default().fg(snr_colour)),
+        Span::raw(format!("{:>4.1} {:>4} {} ", decode.delta_time_s, decode.delta_frequency_hz, decode.mode)),
+        Span::styled(decode.message.clone(), Style::default().fg(need_colour(entry.needs.as_ref()))),
+        Span::styled(format!("  {}", label), Style::default().fg(Color::DarkGray)),
+    ])
+}
+
+fn rows_to_items(rows: &[Row]) -> Vec<ListItem<'static>> {
+    rows.iter().map(|row| match row {
+        Row::Period(period) => ListItem::new(Line::styled(
+            format!("------------------ {} ------------------", period.format("%H:%M:%S")),
+            Style::default().fg(Color::DarkGray),
+        )),
+        Row::Decode(entry) => ListItem::new(decode_line(entry)),
+    }).collect()
+}
+
+fn pane_block(title: &str, focused: bool) -> Block<'static> {
+    let style = if focused { Style::default().fg(Color::Cyan) } else { Style::default() };
+    Block::default().borders(Borders::ALL).title(title.to_string()).border_style(style)
+}
+
+// the selection is found in this draw's rows, with nothing selected a pane follows the newest decodes
+fn place_selection(selection: &mut Selection, keys: &[RowKey], area: Rect) {
+    selection.resolve(keys);
+    let state = &mut selection.list;
+    let len = keys.len();
+    if state.selected().is_none() {
+        let visible = area.height.saturating_sub(2) as usize;
+        *state.offset_mut() = len.saturating_sub(visible);
+    }
+}
+
+fn draw_status(frame: &mut Frame, area: Rect, view: &View, tui: &TuiState) {
+    let status_line = match &view.status {
+        Some(status) => {
+            let tx = if status.transmitting {
+                Span::styled(" TRANSMITTING ", Style::default().fg(Color::White).bg(Color::Red).add_modifier(Modifier::BOLD))
+            } else if status.tx_enabled {
+                Span::styled(" TX ENABLED ", Style::default().fg(Color::Black).bg(Color::Yellow))
+            } else {
+                Span::styled(" TX OFF ", Style::default().fg(Color::DarkGray))
+            };
+            let mut spans = vec![
+                Span::styled(format!(" {} {} ", status.de_call, status.de_grid), Style::default().add_modifier(Modifier::BOLD)),
+                Span::raw(format!("| {} MHz {} ", format_frequency_mhz(status.dial_frequency),
+                    band_for_frequency(status.dial_frequency, view.iaru_region).unwrap_or(""))),
+                Span::raw(format!("| {} ", status.mode)),
+                tx,
+                Span::raw(format!(" | DX: {} ", non_empty(&status.dx_call).unwrap_or("-"))),
+                Span::raw(format!("| RX {} Hz TX {} Hz ", status.rx_df, status.tx_df)),
+                Span::styled(format!("| {}", view.instance_id.as_deref().unwrap_or("")), Style::default().fg(Color::DarkGray)),
+            ];
+            if let Some(hound) = &view.hound {
+                spans.push(Span::styled(format!(" | {}", hound), Style::default().fg(Color::Cyan)));
+            }
+            Line::from(spans)
+        }
+        None => Line::from(" Waiting for a Status message from WSJT-X"),
+    };
+    let help = Line::styled(
+        format!(" Tab pane  Up/Down select  Esc follow  Enter reply  h halt tx  a auto tx off  c/r/x clear  q quit   {}", tui.feedback),
+        Style::default().fg(Color::DarkGray),
+    );
+    let log = Line::styled(format!(" {}", view.last_log.as_deref().unwrap_or("")), Style::default().fg(Color::Gray));
+    frame.render_widget(Paragraph::new(vec![status_line, help, log]).block(Block::default().borders(Borders::ALL).title("WSJTX Message Server")), area);
+}
+
+fn draw(frame: &mut Frame, view: &View, tui: &mut TuiState) {
+    let rows = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Min(5), Constraint::Length(5)])
+        .split(frame.area());
+    let columns = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([Constraint::Percentage(45), Constraint::Percentage(35), Constraint::Percentage(20)])
+        .split(rows[0]);
+
+    let band_items = rows_to_items(&view.band_activity);
+    place_selection(&mut tui.band_activity, &view.keys(Pane::BandActivity), columns[0]);
+    let band_list = List::new(band_items)
+        .block(pane_block("Band Activity", tui.focus == Pane::BandActivity))
+        .highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+    frame.render_stateful_widget(band_list, columns[0], &mut tui.band_activity.list);
+
+    let rx_items = rows_to_items(&view.rx_frequency);
+    place_selection(&mut tui.rx_frequency, &view.keys(Pane::RxFrequency), columns[1]);
+    let rx_list = List::new(rx_items)
+        .block(pane_block("Rx Frequency", tui.focus == Pane::RxFrequency))
+        .highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+    frame.render_stateful_widget(rx_list, columns[1], &mut tui.rx_frequency.list);
+
+    let cq_items: Vec<ListItem> = view.cqs.iter().map(|entry| {
+        let call = parse_ft8_message(&entry.decode.message).from_call().unwrap_or("").to_string();
+        let entity = entry.needs.as_ref().and_then(|needs| needs.entity.as_ref()).map(|entity| entity.prefix.clone()).unwrap_or_default();
+        ListItem::new(Line::from(vec![
+            Span::styled(format!("{:<10}", call), Style::default().fg(need_colour(entry.needs.as_ref()))),
+            Span::raw(format!("{:>3} {}", entry.decode.snr, entity)),
+        ]))
+    }).collect();
+    let cq_list = List::new(cq_items)
+        .block(pane_block("Active CQs", tui.focus == Pane::Cqs))
+        .highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+    let right = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Percentage(60), Constraint::Percentage(40)])
+        .split(columns[2]);
+    tui.cqs.resolve(&view.keys(Pane::Cqs));
+    frame.render_stateful_widget(cq_list, right[0], &mut tui.cqs.list);
+
+    let caller_items: Vec<ListItem> = view.callers.iter().map(|caller| {
+        let entity = caller.needs.as_ref().and_then(|needs| needs.entity.as_ref()).map(|entity| entity.prefix.clone()).unwrap_or_default();
+        ListItem::new(Line::from(vec![
+            Span::styled(format!("{:<10}", caller.call), Style::default().fg(need_colour(caller.needs.as_ref()))),
+            Span::raw(format!("{:>3} {} ", caller.decode.snr, entity)),
+            Span::styled(caller.label(), Style::default().fg(Color::DarkGray)),
+        ]))
+    }).collect();
+    // someone waiting on us stands out even when the pane isn't focused
+    let mut callers_block = pane_block(&format!("Calling Me ({})", view.callers.len()), tui.focus == Pane::Callers);
+    if !view.callers.is_empty() {
+        callers_block = callers_block.title_style(Style::default().add_modifier(Modifier::REVERSED | Modifier::BOLD));
+    }
+    let caller_list = List::new(caller_items)
+        .block(callers_block)
+        .highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+    tui.callers.resolve(&view.keys(Pane::Callers));
+    frame.render_stateful_widget(caller_list, right[1], &mut tui.callers.list);
+
+    draw_status(frame, rows[1], view, tui);
+}
+
+fn move_selection(selection: &mut Selection, keys: &[RowKey], down: bool) {
+    if keys.is_empty() {
+        return;
+    }
+    selection.resolve(keys);
+    let len = keys.len();
+    let next = match (selection.list.selected(), down) {
+        (None, _) => len - 1,
+        (Some(index), true) => (index + 1).min(len - 1),
+        (Some(index), false) => index.saturating_sub(1),
+    };
+    selection.key = Some(keys[next].clone());
+    selection.list.select(Some(next));
+}
+
+fn send_reply(decode: &Decode, app_state: &AppState) -> String {
+    let reply = Reply::from_decode(decode, 0);
+    match send_to_instance(app_state, &decode.id, encode_reply(&reply)) {
+        Ok(()) => format!("Replied to {}", decode.message),
+        Err(e) => format!("Reply failed: {}", e),
+    }
+}
+
+fn send_halt_tx(instance_id: Option<&str>, auto_tx_only: bool, app_state: &AppState) -> String {
+    let id = match instance_id {
+        Some(id) => id,
+        None => return "No WSJT-X instance yet".to_string(),
+    };
+    let halt_tx = HaltTx { message_type: 8, id: id.to_string(), auto_tx_only };
+    match send_to_instance(app_state, id, encode_halt_tx(&halt_tx)) {
+        Ok(()) if auto_tx_only => "Auto TX disabled".to_string(),
+        Ok(()) => "TX halted".to_string(),
+        Err(e) => format!("Halt TX failed: {}", e),
+    }
+}
+
+fn send_clear(instance_id: Option<&str>, window: u8, app_state: &AppState) -> String {
+    app_state.activity.lock().unwrap().clear(window);
+    let id = match instance_id {
+        Some(id) => id,
+        None => return "Cleared".to_string(),
+    };
+    let clear = Clear { message_type: 3, id: id.to_string(), window };
+    match send_to_instance(app_state, id, encode_clear(&clear)) {
+        Ok(()) => "Cleared".to_string(),
+        Err(e) => format!("Clear failed: {}", e),
+    }
+}
+
+pub fn run_tui(app_state: Arc<AppState>) {
+    let mut terminal = ratatui::init();
+    let mut tui = TuiState {
+        focus: Pane::BandActivity,
+        band_activity: Selection::default(),
+        rx_frequency: Selection::default(),
+        cqs: Selection::default(),
+        callers: Selection::default(),
+        feedback: String::new(),
+    };
+    loop {
+        let view = View::collect(&app_state);
+        if let Err(e) = terminal.draw(|frame| draw(frame, &view, &mut tui)) {
+            tui.feedback = format!("Draw failed: {}", e);
+        }
+        if !event::poll(StdDuration::from_millis(250)).unwrap_or(false) {
+            continue;
+        }
+        let key = match event::read() {
+            Ok(Event::Key(key)) if key.kind == KeyEventKind::Press => key,
+            _ => continue,
+        };
+        app_state.control_activity();
+        let instance_id = view.instance_id.as_deref();
+        match key.code {
+            KeyCode::Char('q') => break,
+            KeyCode::Tab => {
+                tui.focus = match tui.focus {
+                    Pane::BandActivity => Pane::RxFrequency,
+                    Pane::RxFrequency => Pane::Cqs,
+                    Pane::Cqs => Pane::Callers,
+                    Pane::Callers => Pane::BandActivity,
+                };
+            }
+            KeyCode::Up | KeyCode::Down => {
+                let focus = tui.focus;
+                move_selection(tui.selection(focus), &view.keys(focus), key.code == KeyCode::Down);
+            }
+            KeyCode::Esc => {
+                for pane in [Pane::BandActivity, Pane::RxFrequency, Pane::Cqs, Pane::Callers] {
+                    tui.selection(pane).clear();
+                }
+            }
+            KeyCode::Enter => {
+                tui.feedback = match view.selected_decode(&tui) {
+                    Some(decode) => send_reply(decode, &app_state),
+                    None => "Select a decode to reply to".to_string(),
+                };
+            }
+            KeyCode::Char('h') => tui.feedback = send_halt_tx(instance_id, false, &app_state),
+            KeyCode::Char('a') => tui.feedback = send_halt_tx(instance_id, true, &app_state),
+            KeyCode::Char('c') => tui.feedback = send_clear(instance_id, 0, &app_state),
+            KeyCode::Char('r') => tui.feedback = send_clear(instance_id, 1, &app_state),
+            KeyCode::Char('x') => tui.feedback = send_clear(instance_id, 2, &app_state),
+            _ => {}
+        }
+    }
+    ratatui::restore();
+    std::process::exit(0);
+}
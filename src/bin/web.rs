@@ -0,0 +1,389 @@
+use std::io;
+use std::sync::{Arc, Mutex};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread;
+use chrono::{DateTime, Duration, Utc};
+use serde_json::{json, Value};
+use tiny_http::{Header, Method, ReadWrite, Request, Response, Server};
+use tungstenite::protocol::{Role, WebSocket};
+use tungstenite::handshake::derive_accept_key;
+use super::*;
+pub mod api;
+pub use api::*;
+
+const DASHBOARD_HTML: &str = include_str!("web/dashboard.html");
+
+// dashboards are pinged this often, and dropped when they haven't answered for PONG_TIMEOUT
+const PING_INTERVAL: std::time::Duration = std::time::Duration::from_secs(20);
+const PONG_TIMEOUT_SECONDS: i64 = 60;
+
+// an open WebSocket, its thread forwards what the sender gets to the browser and records its pongs
+struct WebClient {
+    sender: Sender<String>,
+    last_pong: Arc<Mutex<DateTime<Utc>>>,
+}
+
+// one client per open WebSocket
+pub struct WebClients {
+    clients: Vec<WebClient>,
+}
+
+impl WebClients {
+    pub fn new() -> Self {
+        Self { clients: Vec::new() }
+    }
+
+    pub fn add(&mut self) -> (Receiver<String>, Arc<Mutex<DateTime<Utc>>>) {
+        let (sender, receiver) = mpsc::channel();
+        let last_pong = Arc::new(Mutex::new(Utc::now()));
+        self.clients.push(WebClient { sender, last_pong: Arc::clone(&last_pong) });
+        (receiver, last_pong)
+    }
+
+    pub fn has_clients(&self) -> bool {
+        !self.clients.is_empty()
+    }
+
+    // connections that have gone away or stopped answering pings are dropped here
+    pub fn broadcast(&mut self, text: &str, now: DateTime<Utc>) {
+        self.clients.retain(|client| {
+            now - *client.last_pong.lock().unwrap() <= Duration::seconds(PONG_TIMEOUT_SECONDS)
+                && client.sender.send(text.to_string()).is_ok()
+        });
+    }
+}
+
+impl Default for WebClients {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+pub fn status_json(status: &Status) -> Value {
+    json!({ "type": "status", "status": status })
+}
+
+pub fn decode_json(decode: &Decode, needs: Option<&Needs>, app_state: &AppState) -> Value {
+    let message = parse_ft8_message(&decode.message);
+    let location = match message {
+        Ft8Message::Cq { .. } => decode.cq_location(app_state),
+        _ => None,
+    };
+    json!({
+        "type": "decode",
+        "decode": decode,
+        "mode_name": decode.mode_name(),
+        "cq": matches!(message, Ft8Message::Cq { .. }),
+        "from_call": message.from_call(),
+        "to_call": message.to_call(),
+        "location": location,
+        "dxcc": needs.and_then(|needs| needs.entity.as_ref()).map(|entity| entity.name.clone()),
+        "continent": needs.and_then(|needs| needs.entity.as_ref()).map(|entity| entity.continent.clone()),
+        "needed": needs.map(|needs| needs.headline().is_some()).unwrap_or(false),
+        "label": needs.map(|needs| needs.label()),
+    })
+}
+
+pub fn wspr_decode_json(wspr_decode: &WSPRDecode) -> Value {
+    json!({ "type": "wspr_decode", "wspr_decode": wspr_decode })
+}
+
+pub fn broadcast_json(app_state: &AppState, value: &Value) {
+    app_state.web_clients.lock().unwrap().broadcast(&value.to_string(), Utc::now());
+}
+
+// skips building the JSON, and the geocode it needs, when no browser is listening
+pub fn broadcast_decode(decode: &Decode, needs: Option<&Needs>, app_state: &AppState) {
+    if app_state.web_clients.lock().unwrap().has_clients() {
+        broadcast_json(app_state, &decode_json(decode, needs, app_state));
+    }
+}
+
+fn header(name: &str, value: &str) -> Header {
+    Header::from_bytes(name.as_bytes(), value.as_bytes()).unwrap()
+}
+
+fn json_response(status: u16, value: &Value) -> Response<io::Cursor<Vec<u8>>> {
+    Response::from_string(value.to_string())
+        .with_status_code(status)
+        .with_header(header("Content-Type", "application/json"))
+}
+
+fn error_response(status: u16, error: &str) -> Response<io::Cursor<Vec<u8>>> {
+    json_response(status, &json!({ "error": error }))
+}
+
+fn request_header(request: &Request, name: &str) -> Option<String> {
+    request.headers().iter().find(|header| header.field.to_string().eq_ignore_ascii_case(name)).map(|header| header.value.to_string())
+}
+
+fn read_body(request: &mut Request) -> Result<String, String> {
+    let mut body = String::new();
+    request.as_reader().read_to_string(&mut body).map_err(|e| format!("Could not read request body: {}", e))?;
+    Ok(body)
+}
+
+// "localhost:8080", "127.0.0.1:8080" or "[::1]:8080", the names a browser on this machine uses for us
+fn loopback_host(host: &str) -> bool {
+    let name = match host.strip_prefix('[') {
+        Some(bracketed) => bracketed.split(']').next().unwrap_or(""),
+        None => host.rsplit_once(':').map(|(name, _port)| name).unwrap_or(host),
+    };
+    name.eq_ignore_ascii_case("localhost") || name.parse::<std::net::IpAddr>().map(|ip| ip.is_loopback()).unwrap_or(false)
+}
+
+// control POSTs must be JSON from the dashboard's own origin, so a page on another site can't send them from
+// the operator's browser, and need the web_token when one is set, otherwise they have to come from this machine
+fn authorize(request: &Request, app_state: &AppState) -> Result<(), Response<io::Cursor<Vec<u8>>>> {
+    let json = request_header(request, "Content-Type")
+        .map(|content_type| content_type.trim().to_lowercase().starts_with("application/json"))
+        .unwrap_or(false);
+    if !json {
+        return Err(error_response(415, "Commands must be sent as Content-Type: application/json"));
+    }
+    let host = request_header(request, "Host").unwrap_or_default();
+    if let Some(origin) = request_header(request, "Origin") {
+        if origin != format!("http://{}", host) {
+            return Err(error_response(403, &format!("Commands from {} are refused", origin)));
+        }
+    }
+    let loopback = request.remote_addr().map(|address| address.ip().is_loopback()).unwrap_or(false);
+    match app_state.config().web_token.as_deref().filter(|token| !token.is_empty()) {
+        Some(token) if request_header(request, "Authorization").as_deref() == Some(format!("Bearer {}", token).as_str()) => Ok(()),
+        Some(_) => Err(error_response(401, "Missing or wrong web_token")),
+        // a loopback Host as well, so a page that has rebound its own name to 127.0.0.1 doesn't count as local
+        None if loopback && loopback_host(&host) => Ok(()),
+        None => Err(error_response(403, "Set web_token in the config to send commands from other machines")),
+    }
+}
+
+fn send_result(result: io::Result<()>) -> Response<io::Cursor<Vec<u8>>> {
+    match result {
+        Ok(()) => json_response(200, &json!({ "sent": true })),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => error_response(404, &e.to_string()),
+        Err(e) => error_response(502, &e.to_string()),
+    }
+}
+
+fn handle_command(id: &str, command: &str, request: &mut Request, app_state: &AppState) -> Response<io::Cursor<Vec<u8>>> {
+    let body = match read_body(request) {
+        Ok(body) => body,
+        Err(e) => return error_response(400, &e),
+    };
+    match encode_command(command, id, &body) {
+        Some(Ok(encoded)) => send_result(send_to_instance(app_state, id, encoded)),
+        Some(Err(e)) => error_response(400, &e),
+        None => error_response(404, &format!("Unknown command '{}'", command)),
+    }
+}
+
+// everything a freshly opened dashboard needs to catch up: the latest status of each instance and recent decodes
+fn snapshot(app_state: &AppState) -> Vec<Value> {
+    let mut values: Vec<Value> = app_state.instances.lock().unwrap().iter()
+        .filter_map(|instance| instance.status.as_ref())
+        .map(status_json)
+        .collect();
+    let entries: Vec<ActivityEntry> = app_state.activity.lock().unwrap().band_activity.iter().cloned().collect();
+    values.extend(entries.iter().map(|entry| decode_json(&entry.decode, entry.needs.as_ref(), app_state)));
+    values
+}
+
+fn handle_websocket(request: Request, app_state: Arc<AppState>) {
+    let key = match request_header(&request, "Sec-WebSocket-Key") {
+        Some(key) => key,
+        None => {
+            let _ = request.respond(error_response(400, "Missing Sec-WebSocket-Key"));
+            return;
+        }
+    };
+    let response = Response::empty(101).with_header(header("Sec-WebSocket-Accept", &derive_accept_key(key.as_bytes())));
+    let stream = request.upgrade("websocket", response);
+    let mut websocket = WebSocket::from_raw_socket(stream, Role::Server, None);
+    // register before taking the snapshot so nothing arriving in between is lost
+    let (receiver, last_pong) = app_state.web_clients.lock().unwrap().add();
+    stream_to_dashboard(&mut websocket, receiver, &last_pong, &app_state);
+}
+
+// the snapshot and then everything broadcast, until the browser goes away or is dropped for not answering
+fn stream_to_dashboard(websocket: &mut WebSocket<Box<dyn ReadWrite + Send>>, receiver: Receiver<String>, last_pong: &Mutex<DateTime<Utc>>, app_state: &AppState) {
+    for value in snapshot(app_state) {
+        if websocket.send(tungstenite::Message::text(value.to_string())).is_err() {
+            return;
+        }
+    }
+    loop {
+        let sent = match receiver.recv_timeout(PING_INTERVAL) {
+            Ok(text) => websocket.send(tungstenite::Message::text(text)).is_ok(),
+            Err(mpsc::RecvTimeoutError::Timeout) => ping(websocket, last_pong),
+            Err(mpsc::RecvTimeoutError::Disconnected) => false,
+        };
+        if !sent {
+            break;
+        }
+    }
+}
+
+// reads until the browser answers, which it does at once, a dead connection fails the read
+// once TCP gives up on the ping, and broadcast has dropped the client well before that
+fn ping(websocket: &mut WebSocket<Box<dyn ReadWrite + Send>>, last_pong: &Mutex<DateTime<Utc>>) -> bool {
+    if websocket.send(tungstenite::Message::Ping(Default::default())).is_err() {
+        return false;
+    }
+    loop {
+        match websocket.read() {
+            Ok(tungstenite::Message::Pong(_)) => {
+                *last_pong.lock().unwrap() = Utc::now();
+                return true;
+            }
+            Ok(tungstenite::Message::Close(_)) | Err(_) => return false,
+            // the dashboard sends nothing else, pings from the browser are answered by tungstenite
+            Ok(_) => {}
+        }
+    }
+}
+
+// GET /awards or /awards?mode=FT8
+fn awards_response(url: &str, app_state: &AppState) -> Response<io::Cursor<Vec<u8>>> {
+    let mode = url.split_once("?mode=").map(|(_, mode)| mode.to_uppercase());
+    let progress = all_award_progress(&app_state.worked_before.lock().unwrap(), mode.as_deref());
+    json_response(200, &json!(progress))
+}
+
+// GET /decodes?call=VK9X&band=20m&grid=FN&since=2024-01-01T00:00:00Z&until=...&min_snr=-15&limit=20
+fn stored_decodes(url: &str, app_state: &AppState) -> Response<io::Cursor<Vec<u8>>> {
+    let query = match decode_query_from_url(url) {
+        Ok(query) => query,
+        Err(e) => return error_response(400, &e),
+    };
+    if app_state.store.lock().unwrap().is_none() {
+        return error_response(404, "No database, set store.database in the config");
+    }
+    match app_state.with_store(|store| store.decodes(&query)) {
+        Some(decodes) => json_response(200, &json!(decodes)),
+        None => error_response(500, "Database error"),
+    }
+}
+
+fn handle_request(mut request: Request, app_state: Arc<AppState>) {
+    let segments = path_segments(request.url());
+    let segments: Vec<&str> = segments.iter().map(String::as_str).collect();
+    let method = request.method().clone();
+    let response = match (method, segments.as_slice()) {
+        (Method::Get, []) => Response::from_string(DASHBOARD_HTML).with_header(header("Content-Type", "text/html; charset=utf-8")),
+        (Method::Get, ["ws"]) => {
+            thread::spawn(move || handle_websocket(request, app_state));
+            return;
+        }
+        (Method::Get, ["instances"]) => json_response(200, &instances_json(&app_state)),
+        (Method::Get, ["awards"]) => awards_response(request.url(), &app_state),
+        (Method::Get, ["decodes"]) => stored_decodes(request.url(), &app_state),
+        (Method::Post, ["instances", id, command]) => match authorize(&request, &app_state) {
+            Ok(()) => {
+                app_state.control_activity();
+                handle_command(id, command, &mut request, &app_state)
+            }
+            Err(response) => response,
+        },
+        _ => error_response(404, "Not found"),
+    };
+    if let Err(e) = request.respond(response) {
+        eprintln!("Could not answer web request: {}", e);
+    }
+}
+
+// binds before returning so a bad address is reported at startup, requests are served on a background thread
+pub fn start_web_server(app_state: Arc<AppState>, address: &str) -> io::Result<()> {
+    let server = Server::http(address).map_err(|e| io::Error::new(io::ErrorKind::AddrNotAvailable, e.to_string()))?;
+    thread::spawn(move || {
+        for request in server.incoming_requests() {
+            handle_request(request, Arc::clone(&app_state));
+        }
+    });
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Read, Write};
+    use std::net::{TcpListener, TcpStream};
+
+    // a web server on a free port, returns the port
+    fn serve(config: &str) -> u16 {
+        let port = TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+        start_web_server(Arc::new(AppState::for_test(config)), &format!("127.0.0.1:{}", port)).unwrap();
+        port
+    }
+
+    // the status code of a halt-tx POST with the headers given, Host is filled in when left out
+    fn post(port: u16, headers: &[&str]) -> u16 {
+        let mut stream = TcpStream::connect(("127.0.0.1", port)).unwrap();
+        let mut request = "POST /instances/WSJT-X/halt-tx HTTP/1.1\r\nConnection: close\r\nContent-Length: 2\r\n".to_string();
+        if !headers.iter().any(|header| header.starts_with("Host:")) {
+            request += &format!("Host: 127.0.0.1:{}\r\n", port);
+        }
+        for header in headers {
+            request += &format!("{}\r\n", header);
+        }
+        stream.write_all(format!("{}\r\n{{}}", request).as_bytes()).unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        response.split_whitespace().nth(1).and_then(|code| code.parse().ok()).unwrap_or(0)
+    }
+
+    #[test]
+    fn cross_site_posts_are_refused() {
+        let port = serve("{}");
+        let json = "Content-Type: application/json";
+        let origin = format!("Origin: http://127.0.0.1:{}", port);
+        // what a page on another site can send from the operator's browser
+        assert_eq!(post(port, &[json, "Origin: http://evil.example"]), 403);
+        assert_eq!(post(port, &["Content-Type: text/plain", "Origin: http://evil.example"]), 415);
+        assert_eq!(post(port, &[]), 415);
+        assert_eq!(post(port, &[json, "Host: evil.example"]), 403);
+        // allowed through, there is no WSJT-X instance to send it to
+        assert_eq!(post(port, &[json, &origin]), 404);
+        assert_eq!(post(port, &[json]), 404);
+    }
+
+    #[test]
+    fn token_is_checked() {
+        let port = serve(r#"{"web_token": "secret"}"#);
+        let json = "Content-Type: application/json";
+        assert_eq!(post(port, &[json]), 401);
+        assert_eq!(post(port, &[json, "Authorization: Bearer guess"]), 401);
+        assert_eq!(post(port, &[json, "Authorization: Bearer secret", "Host: shack.local:8080"]), 404);
+        assert_eq!(post(port, &[json, "Authorization: Bearer secret", "Origin: http://evil.example"]), 403);
+    }
+
+    #[test]
+    fn silent_dashboards_are_dropped() {
+        let mut clients = WebClients::new();
+        let (receiver, last_pong) = clients.add();
+        let now = Utc::now();
+        clients.broadcast("one", now);
+        assert_eq!(receiver.try_recv().as_deref(), Ok("one"));
+        *last_pong.lock().unwrap() = now - Duration::seconds(PONG_TIMEOUT_SECONDS + 1);
+        clients.broadcast("two", now);
+        assert!(!clients.has_clients());
+        assert!(receiver.try_recv().is_err());
+    }
+
+    #[test]
+    fn open_dashboard_is_not_contact() {
+        let app_state = AppState::for_test("{}");
+        let (_receiver, _last_pong) = app_state.web_clients.lock().unwrap().add();
+        let later = Utc::now() + Duration::minutes(10);
+        assert!(app_state.out_of_contact(later) >= Duration::minutes(10));
+        app_state.control_activity();
+        assert!(app_state.out_of_contact(later) <= Duration::minutes(10));
+    }
+
+    #[test]
+    fn loopback_hosts() {
+        for (host, loopback) in [("localhost:8080", true), ("127.0.0.1:8080", true), ("[::1]:8080", true), ("127.0.0.1", true),
+            ("evil.example:8080", false), ("192.168.1.5:8080", false), ("[fe80::1]:8080", false), ("", false)] {
+            assert_eq!(loopback_host(host), loopback, "{}", host);
+        }
+    }
+}
@@ -0,0 +1,295 @@
+use chrono::{DateTime, NaiveTime, Utc};
+use serde::de::DeserializeOwned;
+use serde_derive::Deserialize;
+use serde_json::{json, Value};
+use super::*;
+
+// the JSON bodies accepted by POST /instances/{id}/<command>, the instance id always comes from the path
+
+#[derive(Debug, Deserialize)]
+struct ReplyRequest {
+    time: NaiveTime,
+    snr: i32,
+    delta_time_s: f64,
+    delta_frequency_hz: u32,
+    mode: String,
+    message: String,
+    #[serde(default)]
+    low_confidence: bool,
+    #[serde(default)]
+    modifiers: u8,
+}
+
+#[derive(Debug, Deserialize)]
+struct HaltTxRequest {
+    #[serde(default)]
+    auto_tx_only: bool,
+}
+
+#[derive(Debug, Deserialize)]
+struct FreeTextRequest {
+    text: String,
+    #[serde(default)]
+    send: bool,
+}
+
+#[derive(Debug, Deserialize)]
+struct ClearRequest {
+    window: u8,
+}
+
+#[derive(Debug, Deserialize)]
+struct LocationRequest {
+    location: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct HighlightRequest {
+    callsign: String,
+    #[serde(default)]
+    background_color: String,
+    #[serde(default)]
+    foreground_color: String,
+    #[serde(default)]
+    highlight_last: bool,
+}
+
+// every field is optional, anything left out stays as WSJT-X has it
+#[derive(Debug, Deserialize)]
+struct ConfigureRequest {
+    mode: Option<String>,
+    frequency_tolerance: Option<u32>,
+    submode: Option<String>,
+    #[serde(default)]
+    fast_mode: bool,
+    tr_period: Option<u32>,
+    rx_df: Option<u32>,
+    dx_call: Option<String>,
+    dx_grid: Option<String>,
+    #[serde(default)]
+    generate_messages: bool,
+}
+
+#[derive(Debug, Deserialize)]
+struct SwitchConfigurationRequest {
+    configuration_name: String,
+}
+
+// Qt keyboard modifiers shifted down 24 bits: shift, control, alt, meta, keypad and group switch
+const REPLY_MODIFIERS: u8 = 0x7e;
+// what fits in a 77 bit FT8/FT4 free text message
+const FREE_TEXT_MAX: usize = 13;
+const FREE_TEXT_CHARACTERS: &str = " 0123456789ABCDEFGHIJKLMNOPQRSTUVWXYZ+-./?";
+const MAX_RX_DF: u32 = 5000;
+// Configure sends the maximum value for numbers that should be left unchanged
+const NO_CHANGE: u32 = u32::MAX;
+
+fn parse_json<T: DeserializeOwned>(body: &str) -> Result<T, String> {
+    let body = if body.trim().is_empty() { "{}" } else { body };
+    serde_json::from_str(body).map_err(|e| format!("Bad request body: {}", e))
+}
+
+// WSJT-X wants at least a 4 character locator
+fn is_locator(text: &str) -> bool {
+    text.len() >= 4 && valid_grid(text)
+}
+
+fn check_colour(name: &str, colour: &str) -> Result<(), String> {
+    if colour.is_empty() || parse_colour(colour).is_some() {
+        Ok(())
+    } else {
+        Err(format!("{} must be \"#rrggbb\" or empty, not '{}'", name, colour))
+    }
+}
+
+fn reply(id: &str, body: &str) -> Result<Vec<u8>, String> {
+    let request: ReplyRequest = parse_json(body)?;
+    if request.message.trim().is_empty() {
+        return Err("message must not be empty".to_string());
+    }
+    if request.mode.is_empty() {
+        return Err("mode must not be empty".to_string());
+    }
+    if request.modifiers & !REPLY_MODIFIERS != 0 {
+        return Err(format!("Unknown modifiers {:#04x}", request.modifiers));
+    }
+    Ok(encode_reply(&Reply {
+        message_type: 4,
+        id: id.to_string(),
+        time: request.time,
+        snr: request.snr,
+        delta_time_s: request.delta_time_s,
+        delta_frequency_hz: request.delta_frequency_hz,
+        mode: request.mode,
+        message: request.message,
+        low_confidence: request.low_confidence,
+        modifiers: request.modifiers,
+    }))
+}
+
+fn halt_tx(id: &str, body: &str) -> Result<Vec<u8>, String> {
+    let request: HaltTxRequest = parse_json(body)?;
+    Ok(encode_halt_tx(&HaltTx { message_type: 8, id: id.to_string(), auto_tx_only: request.auto_tx_only }))
+}
+
+fn free_text(id: &str, body: &str) -> Result<Vec<u8>, String> {
+    let request: FreeTextRequest = parse_json(body)?;
+    let text = request.text.to_uppercase();
+    if text.len() > FREE_TEXT_MAX {
+        return Err(format!("Free text is limited to {} characters", FREE_TEXT_MAX));
+    }
+    if let Some(c) = text.chars().find(|c| !FREE_TEXT_CHARACTERS.contains(*c)) {
+        return Err(format!("'{}' cannot be sent in free text", c));
+    }
+    Ok(encode_free_text(&FreeText { message_type: 9, id: id.to_string(), text, send: request.send }))
+}
+
+fn clear(id: &str, body: &str) -> Result<Vec<u8>, String> {
+    let request: ClearRequest = parse_json(body)?;
+    if request.window > 2 {
+        return Err("window must be 0 (band activity), 1 (rx frequency) or 2 (both)".to_string());
+    }
+    Ok(encode_clear(&Clear { message_type: 3, id: id.to_string(), window: request.window }))
+}
+
+fn location(id: &str, body: &str) -> Result<Vec<u8>, String> {
+    let request: LocationRequest = parse_json(body)?;
+    if !is_locator(&request.location) {
+        return Err(format!("'{}' is not a Maidenhead locator", request.location));
+    }
+    Ok(encode_location(&Location { message_type: 11, id: id.to_string(), location: request.location.to_uppercase() }))
+}
+
+fn highlight(id: &str, body: &str) -> Result<Vec<u8>, String> {
+    let request: HighlightRequest = parse_json(body)?;
+    let callsign = request.callsign.to_uppercase();
+    if !valid_callsign(&callsign) {
+        return Err(format!("'{}' is not a callsign", request.callsign));
+    }
+    check_colour("background_color", &request.background_color)?;
+    check_colour("foreground_color", &request.foreground_color)?;
+    Ok(encode_highlight_callsign_in(&HighlightCallsignIn {
+        message_type: 13,
+        id: id.to_string(),
+        callsign,
+        background_color: request.background_color,
+        foreground_color: request.foreground_color,
+        highlight_last: request.highlight_last,
+    }))
+}
+
+fn configure(id: &str, body: &str) -> Result<Vec<u8>, String> {
+    let request: ConfigureRequest = parse_json(body)?;
+    if request.tr_period == Some(0) {
+        return Err("tr_period must be positive".to_string());
+    }
+    if let Some(rx_df) = request.rx_df.filter(|rx_df| *rx_df > MAX_RX_DF) {
+        return Err(format!("rx_df {} is outside 0-{} Hz", rx_df, MAX_RX_DF));
+    }
+    let dx_call = request.dx_call.map(|call| call.to_uppercase()).unwrap_or_default();
+    if !dx_call.is_empty() && !valid_callsign(&dx_call) {
+        return Err(format!("'{}' is not a callsign", dx_call));
+    }
+    let dx_grid = request.dx_grid.map(|grid| grid.to_uppercase()).unwrap_or_default();
+    if !dx_grid.is_empty() && !is_locator(&dx_grid) {
+        return Err(format!("'{}' is not a Maidenhead locator", dx_grid));
+    }
+    Ok(encode_configure(&Configure {
+        message_type: 15,
+        id: id.to_string(),
+        mode: request.mode.unwrap_or_default(),
+        frequency_tolerance: request.frequency_tolerance.unwrap_or(NO_CHANGE),
+        submode: request.submode.unwrap_or_default(),
+        fast_mode: request.fast_mode,
+        tr_period: request.tr_period.unwrap_or(NO_CHANGE) as i32,
+        rx_df: request.rx_df.unwrap_or(NO_CHANGE) as i32,
+        dx_call,
+        dx_grid,
+        generate_messages: request.generate_messages,
+    }))
+}
+
+fn switch_configuration(id: &str, body: &str) -> Result<Vec<u8>, String> {
+    let request: SwitchConfigurationRequest = parse_json(body)?;
+    if request.configuration_name.trim().is_empty() {
+        return Err("configuration_name must not be empty".to_string());
+    }
+    Ok(encode_switch_configuration(&SwitchConfiguration {
+        message_type: 14,
+        id: id.to_string(),
+        configuration_name: request.configuration_name,
+    }))
+}
+
+// validates and encodes one command, None when the command does not exist
+pub fn encode_command(command: &str, id: &str, body: &str) -> Option<Result<Vec<u8>, String>> {
+    let encoded = match command {
+        "reply" => reply(id, body),
+        "halt-tx" => halt_tx(id, body),
+        "free-text" => free_text(id, body),
+        "clear" => clear(id, body),
+        "location" => location(id, body),
+        "highlight" => highlight(id, body),
+        "configure" => configure(id, body),
+        "switch-configuration" => switch_configuration(id, body),
+        _ => return None,
+    };
+    Some(encoded)
+}
+
+pub fn instances_json(app_state: &AppState) -> Value {
+    let instances = app_state.instances.lock().unwrap();
+    let mut list: Vec<&Instance> = instances.iter().collect();
+    list.sort_by(|a, b| a.id.cmp(&b.id));
+    json!({ "instances": list })
+}
+
+// query strings also use '+' for a space, paths don't
+fn percent_decode(text: &str, plus_is_space: bool) -> String {
+    let bytes = text.as_bytes();
+    let hex = |index: usize| bytes.get(index).and_then(|byte| (*byte as char).to_digit(16));
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut index = 0;
+    while index < bytes.len() {
+        match (bytes[index], hex(index + 1), hex(index + 2)) {
+            (b'%', Some(high), Some(low)) => {
+                decoded.push((high * 16 + low) as u8);
+                index += 2;
+            }
+            (b'+', _, _) if plus_is_space => decoded.push(b' '),
+            (byte, _, _) => decoded.push(byte),
+        }
+        index += 1;
+    }
+    String::from_utf8_lossy(&decoded).to_string()
+}
+
+// the path of a request URL split at '/' with each segment decoded, so ids like "WSJT-X - rig2" match
+pub fn path_segments(url: &str) -> Vec<String> {
+    let path = url.split('?').next().unwrap_or("");
+    path.split('/').filter(|segment| !segment.is_empty()).map(|segment| percent_decode(segment, false)).collect()
+}
+
+// the query string of GET /decodes
+pub fn decode_query_from_url(url: &str) -> Result<DecodeQuery, String> {
+    let mut query = DecodeQuery::default();
+    let query_string = url.split_once('?').map(|(_, query_string)| query_string).unwrap_or("");
+    for pair in query_string.split('&').filter(|pair| !pair.is_empty()) {
+        let (name, value) = pair.split_once('=').unwrap_or((pair, ""));
+        let value = percent_decode(value, true);
+        let time = |value: &str| DateTime::parse_from_rfc3339(value)
+            .map(|time| time.with_timezone(&Utc))
+            .map_err(|e| format!("{}: {}", name, e));
+        match name {
+            "call" => query.call = Some(value),
+            "grid" => query.grid = Some(value),
+            "band" => query.band = Some(value),
+            "since" => query.since = Some(time(&value)?),
+            "until" => query.until = Some(time(&value)?),
+            "min_snr" => query.min_snr = Some(value.parse().map_err(|_| format!("min_snr: '{}' is not a number", value))?),
+            "limit" => query.limit = Some(value.parse::<u32>().map_err(|_| format!("limit: '{}' is not a number", value))?.min(10_000)),
+            _ => return Err(format!("Unknown parameter '{}'", name)),
+        }
+    }
+    Ok(query)
+}
@@ -0,0 +1,302 @@
+use std::collections::HashMap;
+use colored::*;
+use super::*;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum WorkedCategory {
+    Call,
+    Grid,
+    Dxcc,
+    State,
+    CqZone,
+    Continent,
+}
+
+impl WorkedCategory {
+    // the lowercase names used by filters and alert rules
+    pub fn parse(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "call" => Some(WorkedCategory::Call),
+            "grid" => Some(WorkedCategory::Grid),
+            "dxcc" => Some(WorkedCategory::Dxcc),
+            "state" => Some(WorkedCategory::State),
+            "zone" => Some(WorkedCategory::CqZone),
+            "continent" => Some(WorkedCategory::Continent),
+            _ => None,
+        }
+    }
+}
+
+impl std::fmt::Display for WorkedCategory {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let name = match self {
+            WorkedCategory::Call => "CALL",
+            WorkedCategory::Grid => "GRID",
+            WorkedCategory::Dxcc => "DXCC",
+            WorkedCategory::State => "STATE",
+            WorkedCategory::CqZone => "ZONE",
+            WorkedCategory::Continent => "CONTINENT",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum NeedStatus {
+    // never worked on any band or mode
+    New,
+    // worked, but not on this band and mode
+    NewBandMode,
+    Worked,
+    Confirmed,
+}
+
+impl NeedStatus {
+    pub fn is_needed(&self) -> bool {
+        *self <= NeedStatus::NewBandMode
+    }
+}
+
+impl std::fmt::Display for NeedStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let name = match self {
+            NeedStatus::New => "new",
+            NeedStatus::NewBandMode => "new band/mode",
+            NeedStatus::Worked => "worked",
+            NeedStatus::Confirmed => "confirmed",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct WorkedQso {
+    pub call: String,
+    pub grid: Option<String>,
+    pub band: Option<String>,
+    pub mode: String,
+    pub state: Option<String>,
+    pub confirmed: bool,
+}
+
+impl WorkedQso {
+    pub fn from_adif(qso: &AdifQso, iaru_region: u8) -> Self {
+        Self {
+            call: qso.call.clone(),
+            grid: qso.gridsquare.clone().filter(|grid| grid.len() >= 4),
+            band: qso.band(iaru_region),
+            mode: qso.mode_name().to_string(),
+            state: qso.state.clone(),
+            confirmed: qso.confirmed,
+        }
+    }
+
+    // WSJT-X doesn't log a state, for its QSOs it comes from the grid the same way as for decodes
+    pub fn from_logged_adif(qso: &AdifQso, iaru_region: u8, app_state: &AppState) -> Self {
+        let mut worked = Self::from_adif(qso, iaru_region);
+        if worked.state.is_none() {
+            worked.state = worked.grid.as_deref().and_then(|grid| grid_state(grid, app_state)).map(|state| state.to_string());
+        }
+        worked
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Needs {
+    pub entity: Option<DxccEntity>,
+    pub call: NeedStatus,
+    pub grid: Option<NeedStatus>,
+    pub dxcc: Option<NeedStatus>,
+    pub state: Option<NeedStatus>,
+    pub cq_zone: Option<NeedStatus>,
+    pub continent: Option<NeedStatus>,
+}
+
+impl Needs {
+    // the most important thing this station would give us, in JTAlert's priority order
+    pub fn headline(&self) -> Option<(WorkedCategory, NeedStatus)> {
+        [
+            (WorkedCategory::Dxcc, self.dxcc),
+            (WorkedCategory::Continent, self.continent),
+            (WorkedCategory::CqZone, self.cq_zone),
+            (WorkedCategory::State, self.state),
+            (WorkedCategory::Grid, self.grid),
+            (WorkedCategory::Call, Some(self.call)),
+        ]
+        .into_iter()
+        .find_map(|(category, status)| status.filter(|status| status.is_needed()).map(|status| (category, status)))
+    }
+
+    pub fn status(&self, category: WorkedCategory) -> Option<NeedStatus> {
+        match category {
+            WorkedCategory::Call => Some(self.call),
+            WorkedCategory::Grid => self.grid,
+            WorkedCategory::Dxcc => self.dxcc,
+            WorkedCategory::State => self.state,
+            WorkedCategory::CqZone => self.cq_zone,
+            WorkedCategory::Continent => self.continent,
+        }
+    }
+
+    pub fn is_needed(&self, category: WorkedCategory) -> bool {
+        self.status(category).map(|status| status.is_needed()).unwrap_or(false)
+    }
+
+    pub fn colour_call(&self, call: &str) -> ColoredString {
+        match self.headline() {
+            Some((WorkedCategory::Dxcc, NeedStatus::New)) => call.white().bold().on_red(),
+            Some((WorkedCategory::Dxcc, _)) => call.red().bold(),
+            Some((WorkedCategory::Continent, _)) => call.cyan().bold(),
+            Some((WorkedCategory::CqZone, _)) => call.magenta().bold(),
+            Some((WorkedCategory::State, _)) => call.blue().bold(),
+            Some((WorkedCategory::Grid, _)) => call.yellow(),
+            Some((WorkedCategory::Call, _)) => call.green(),
+            None => call.bright_black(),
+        }
+    }
+
+    pub fn label(&self) -> String {
+        match self.headline() {
+            Some((category, NeedStatus::New)) => format!("NEW {}", category),
+            Some((category, _)) => format!("NEW {} BAND/MODE", category),
+            None if self.call == NeedStatus::Confirmed => "CFM".to_string(),
+            None => "B4".to_string(),
+        }
+    }
+}
+
+// per category value, the (band, mode) slots it has been worked on and whether each is confirmed
+#[derive(Debug, Default)]
+pub struct WorkedBefore {
+    entries: HashMap<(WorkedCategory, String), HashMap<(String, String), bool>>,
+    pub qso_count: usize,
+}
+
+impl WorkedBefore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn add(&mut self, category: WorkedCategory, value: &str, band: &str, mode: &str, confirmed: bool) {
+        let slots = self.entries.entry((category, value.to_uppercase())).or_default();
+        let slot = slots.entry((band.to_string(), mode.to_string())).or_insert(false);
+        *slot |= confirmed;
+    }
+
+    pub fn add_qso(&mut self, qso: &WorkedQso, dxcc: &DxccTable) {
+        let band = qso.band.clone().unwrap_or_default();
+        let mode = qso.mode.to_uppercase();
+        self.add(WorkedCategory::Call, &qso.call, &band, &mode, qso.confirmed);
+        if let Some(grid) = qso.grid.as_ref().filter(|grid| grid.len() >= 4) {
+            self.add(WorkedCategory::Grid, &grid[..4], &band, &mode, qso.confirmed);
+        }
+        if let Some(state) = &qso.state {
+            self.add(WorkedCategory::State, state, &band, &mode, qso.confirmed);
+        }
+        if let Some(entity) = dxcc.lookup(&qso.call) {
+            self.add(WorkedCategory::Dxcc, &entity.name, &band, &mode, qso.confirmed);
+            self.add(WorkedCategory::CqZone, &entity.cq_zone.to_string(), &band, &mode, qso.confirmed);
+            self.add(WorkedCategory::Continent, &entity.continent, &band, &mode, qso.confirmed);
+        }
+        self.qso_count += 1;
+    }
+
+    // band or mode of None matches any slot
+    pub fn status(&self, category: WorkedCategory, value: &str, band: Option<&str>, mode: Option<&str>) -> NeedStatus {
+        let slots = match self.entries.get(&(category, value.to_uppercase())) {
+            Some(slots) => slots,
+            None => return NeedStatus::New,
+        };
+        let mut matching = slots.iter().filter(|((slot_band, slot_mode), _)| {
+            band.map(|band| band == slot_band).unwrap_or(true) && mode.map(|mode| mode == slot_mode).unwrap_or(true)
+        }).peekable();
+        if matching.peek().is_none() {
+            return NeedStatus::NewBandMode;
+        }
+        if matching.any(|(_, confirmed)| *confirmed) {
+            NeedStatus::Confirmed
+        } else {
+            NeedStatus::Worked
+        }
+    }
+
+    // how many values of a category count, were worked and were confirmed on a band and mode, None for any
+    pub fn progress(&self, category: WorkedCategory, band: Option<&str>, mode: Option<&str>, counts: impl Fn(&str) -> bool) -> (usize, usize) {
+        let mut worked = 0;
+        let mut confirmed = 0;
+        for ((entry_category, value), slots) in &self.entries {
+            if *entry_category != category || !counts(value) {
+                continue;
+            }
+            let mut matching = slots.iter().filter(|((slot_band, slot_mode), _)| {
+                band.map(|band| band == slot_band).unwrap_or(true) && mode.map(|mode| mode == slot_mode).unwrap_or(true)
+            }).peekable();
+            if matching.peek().is_some() {
+                worked += 1;
+                if matching.any(|(_, confirmed)| *confirmed) {
+                    confirmed += 1;
+                }
+            }
+        }
+        (worked, confirmed)
+    }
+
+    // every band and mode something has been worked on
+    pub fn slots(&self) -> (Vec<String>, Vec<String>) {
+        let mut bands = Vec::new();
+        let mut modes = Vec::new();
+        for (band, mode) in self.entries.values().flat_map(|slots| slots.keys()) {
+            if !band.is_empty() && !bands.contains(band) {
+                bands.push(band.clone());
+            }
+            if !mode.is_empty() && !modes.contains(mode) {
+                modes.push(mode.clone());
+            }
+        }
+        bands.sort_by(|a, b| band_wavelength_m(b).total_cmp(&band_wavelength_m(a)));
+        modes.sort();
+        (bands, modes)
+    }
+
+    pub fn needs(&self, call: &str, grid: Option<&str>, state: Option<&str>, band: Option<&str>, mode: Option<&str>, dxcc: &DxccTable) -> Needs {
+        let entity = dxcc.lookup(call).cloned();
+        Needs {
+            call: self.status(WorkedCategory::Call, call, band, mode),
+            grid: grid.filter(|grid| grid.len() >= 4).map(|grid| self.status(WorkedCategory::Grid, &grid[..4], band, mode)),
+            state: state.map(|state| self.status(WorkedCategory::State, state, band, mode)),
+            dxcc: entity.as_ref().map(|entity| self.status(WorkedCategory::Dxcc, &entity.name, band, mode)),
+            cq_zone: entity.as_ref().map(|entity| self.status(WorkedCategory::CqZone, &entity.cq_zone.to_string(), band, mode)),
+            continent: entity.as_ref().map(|entity| self.status(WorkedCategory::Continent, &entity.continent, band, mode)),
+            entity,
+        }
+    }
+
+    pub fn add_adif(&mut self, import: &AdifImport, dxcc: &DxccTable, iaru_region: u8) {
+        for qso in &import.qsos {
+            self.add_qso(&WorkedQso::from_adif(qso, iaru_region), dxcc);
+        }
+    }
+}
+
+// the 50 states of WAS, name and ADIF STATE code
+const US_STATES: &[(&str, &str)] = &[
+    ("Alabama", "AL"), ("Alaska", "AK"), ("Arizona", "AZ"), ("Arkansas", "AR"), ("California", "CA"),
+    ("Colorado", "CO"), ("Connecticut", "CT"), ("Delaware", "DE"), ("Florida", "FL"), ("Georgia", "GA"),
+    ("Hawaii", "HI"), ("Idaho", "ID"), ("Illinois", "IL"), ("Indiana", "IN"), ("Iowa", "IA"),
+    ("Kansas", "KS"), ("Kentucky", "KY"), ("Louisiana", "LA"), ("Maine", "ME"), ("Maryland", "MD"),
+    ("Massachusetts", "MA"), ("Michigan", "MI"), ("Minnesota", "MN"), ("Mississippi", "MS"), ("Missouri", "MO"),
+    ("Montana", "MT"), ("Nebraska", "NE"), ("Nevada", "NV"), ("New Hampshire", "NH"), ("New Jersey", "NJ"),
+    ("New Mexico", "NM"), ("New York", "NY"), ("North Carolina", "NC"), ("North Dakota", "ND"), ("Ohio", "OH"),
+    ("Oklahoma", "OK"), ("Oregon", "OR"), ("Pennsylvania", "PA"), ("Rhode Island", "RI"), ("South Carolina", "SC"),
+    ("South Dakota", "SD"), ("Tennessee", "TN"), ("Texas", "TX"), ("Utah", "UT"), ("Vermont", "VT"),
+    ("Virginia", "VA"), ("Washington", "WA"), ("West Virginia", "WV"), ("Wisconsin", "WI"), ("Wyoming", "WY"),
+];
+
+// US state from the grid's reverse geocode, matched against ADIF STATE via the two letter code
+pub fn us_state_code(admin1: &str) -> Option<&'static str> {
+    US_STATES.iter().find(|(name, _)| *name == admin1).map(|(_, code)| *code)
+}
+
+pub fn is_us_state_code(code: &str) -> bool {
+    US_STATES.iter().any(|(_, state)| state.eq_ignore_ascii_case(code))
+}
@@ -1,17 +1,25 @@
 //anything using qcolor or qtypes is tested yet, proceed with caution if using these objects.
 pub mod receivemessages;
 pub mod sendmessages;
-use chrono::{DateTime, TimeZone, NaiveTime, Timelike};
+use chrono::{DateTime, TimeZone, NaiveDate, NaiveTime, Timelike};
 use chrono::offset::Utc;
 // use receivemessages::*;
 // use sendmessages::*;
 use byteorder::{ByteOrder, BigEndian};
 use serde_derive::Serialize;
 use maidenhead::{grid_to_longlat, MHError};
-use reverse_geocoder::{ReverseGeocoder, SearchResult};
+use reverse_geocoder::SearchResult;
 use iso3166_1::CountryCode;
 use super::*;
 
+// get_string_from_payload turns null and empty QStrings into "n/a" and "empty"
+pub fn non_empty(value: &str) -> Option<&str> {
+    match value {
+        "" | "empty" | "n/a" => None,
+        value => Some(value),
+    }
+}
+
 #[derive(Debug, Serialize)]
 pub struct Message {
     pub magic_number: u32,
@@ -21,11 +29,11 @@ pub struct Message {
 
 #[derive(Debug)]
 pub struct Heartbeat {
-    message_type: u32,
-    id: String,
-    maximum_schema_number: u32,
-    version: String,
-    revision: String,
+    pub message_type: u32,
+    pub id: String,
+    pub maximum_schema_number: u32,
+    pub version: String,
+    pub revision: String,
 }
 
 impl std::fmt::Display for Heartbeat {
@@ -35,31 +43,31 @@ impl std::fmt::Display for Heartbeat {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Default, Serialize)]
 pub struct Status {
-    message_type: u32,
-    id: String,
-    dial_frequency: u64,
-    mode: String,
-    dx_call: String,
-    report: String,
-    tx_mode: String,
-    tx_enabled: bool,
-    transmitting: bool,
-    decoding: bool,
-    rx_df: u32,
-    tx_df: u32,
-    de_call: String,
-    de_grid: String,
-    dx_grid: String,
-    tx_watchdog: bool,
-    sub_mode: String,
-    fast_mode: bool,
-    special_operation_mode: u8,
-    frequency_tolerance: u32,
-    tr_period: u32,
-    configuration_name: String,
-    tx_message: String,
+    pub message_type: u32,
+    pub id: String,
+    pub dial_frequency: u64,
+    pub mode: String,
+    pub dx_call: String,
+    pub report: String,
+    pub tx_mode: String,
+    pub tx_enabled: bool,
+    pub transmitting: bool,
+    pub decoding: bool,
+    pub rx_df: u32,
+    pub tx_df: u32,
+    pub de_call: String,
+    pub de_grid: String,
+    pub dx_grid: String,
+    pub tx_watchdog: bool,
+    pub sub_mode: String,
+    pub fast_mode: bool,
+    pub special_operation_mode: u8,
+    pub frequency_tolerance: u32,
+    pub tr_period: u32,
+    pub configuration_name: String,
+    pub tx_message: String,
 }
 impl std::fmt::Display for Status{
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
@@ -68,40 +76,76 @@ impl std::fmt::Display for Status{
     }
 }
 
-// #[derive(Serialize, Deserialize, Debug, Display)]
-#[derive(Debug)]
+#[derive(Debug, Clone, Default, Serialize)]
 pub struct Decode {
-    message_type: u32,
-    id: String, 
-    new: bool,
-    time: NaiveTime,
-    snr: i32,
-    delta_time_s: f64,
-    delta_frequency_hz: u32,
-    mode: String,
-    message: String,
-    low_confidence: bool,
-    off_air: bool,
+    pub message_type: u32,
+    pub id: String, 
+    pub new: bool,
+    pub time: NaiveTime,
+    pub snr: i32,
+    pub delta_time_s: f64,
+    pub delta_frequency_hz: u32,
+    pub mode: String,
+    pub message: String,
+    pub low_confidence: bool,
+    pub off_air: bool,
+    // not part of the message, filled in from the instance's last Status dial frequency
+    pub rf_frequency_hz: Option<u64>,
+    pub band: Option<&'static str>,
 }
 impl std::fmt::Display for Decode{
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
-        write!(f, "Message Type: {}, Id: {}, New: {}, Time: {}, SNR: {}, Delta Time: {}, Delta Frequency: {}, Mode: {}, Message: {}, Low Confidence: {}, Off Air: {}", self.message_type
-        , self.id, self.new, self.time, self.snr, self.delta_time_s, self.delta_frequency_hz, self.mode, self.message, self.low_confidence, self.off_air)
+        write!(f, "Message Type: {}, Id: {}, New: {}, Time: {}, SNR: {}, Delta Time: {}, Delta Frequency: {}, Mode: {}, Message: {}, Low Confidence: {}, Off Air: {}, RF Frequency: {:?}, Band: {:?}", self.message_type
+        , self.id, self.new, self.time, self.snr, self.delta_time_s, self.delta_frequency_hz, self.mode, self.message, self.low_confidence, self.off_air, self.rf_frequency_hz, self.band)
     }
 }
 impl Decode {
-    pub fn print_message(&self, app_state: &AppState) {
+    pub fn annotate_frequency(&mut self, dial_frequency: u64, iaru_region: u8) {
+        let rf_frequency_hz = dial_frequency + self.delta_frequency_hz as u64;
+        self.rf_frequency_hz = Some(rf_frequency_hz);
+        self.band = band_for_frequency(rf_frequency_hz, iaru_region);
+    }
+
+    fn format_frequency(&self) -> String {
+        match (self.band, self.rf_frequency_hz) {
+            (Some(band), Some(frequency)) => format!("{} {} ", band.cyan(), format_frequency_mhz(frequency)),
+            (None, Some(frequency)) => format!("{} ", format_frequency_mhz(frequency)),
+            _ => String::new(),
+        }
+    }
+
+    // WSJT-X sends the mode as the single character shown in its band activity window
+    pub fn mode_name(&self) -> Option<&'static str> {
+        match self.mode.as_str() {
+            "~" => Some("FT8"),
+            "+" => Some("FT4"),
+            "`" => Some("FST4"),
+            ":" => Some("Q65"),
+            "#" => Some("JT65"),
+            "@" => Some("JT9"),
+            "$" => Some("JT4"),
+            "&" => Some("MSK144"),
+            _ => None,
+        }
+    }
+
+    pub fn needs(&self, call: &str, grid: Option<&str>, state: Option<&str>, app_state: &AppState) -> Needs {
+        let worked_before = app_state.worked_before.lock().unwrap();
+        worked_before.needs(call, grid, state, self.band, self.mode_name(), &app_state.dxcc)
+    }
+
+    pub fn print_message(&self, alerts: &[AlertMatch], app_state: &AppState) {
         let parts: Vec<&str> = self.message.split_whitespace().collect();
         if self.message.starts_with("CQ") {
             if parts.len() >= 3 {
-                self.handle_cq_message(parts, &app_state);
+                self.handle_cq_message(parts, alerts, app_state);
             }
         } else {
-            self.print_non_cq_message(parts, &app_state);
+            self.print_non_cq_message(parts, alerts, app_state);
         }
     }
 
-    fn handle_cq_message(&self, parts: Vec<&str>, app_state: &AppState) {
+    fn handle_cq_message(&self, parts: Vec<&str>, alerts: &[AlertMatch], app_state: &AppState) {
         let gridsquare;
         let typical;
         if parts.len() == 3 {
@@ -112,39 +156,72 @@ impl Decode {
             typical = false;
             gridsquare = parts[3];
         }
-        match grid_to_longlat(&gridsquare) {
+        let call = strip_call(parts[parts.len() - 2]);
+        match grid_to_longlat(gridsquare) {
             Ok((lat, lon)) => {
-                let geocoder = ReverseGeocoder::new();
-                let search_result = geocoder.search((lon,lat));
+                let search_result = app_state.geocoder.search((lon,lat));
                 let country = iso3166_1::alpha2(&search_result.record.cc).unwrap();
-                self.print_cq_message(parts, country, &search_result, typical, &app_state);
+                let state = if search_result.record.cc == "US" { us_state_code(&search_result.record.admin1) } else { None };
+                let needs = self.needs(&call, Some(gridsquare), state, app_state);
+                self.print_cq_message(parts, country, &search_result, typical, &needs, alerts);
             }
             Err(e) => {
-                self.print_error_message(e, parts, &app_state);
+                self.print_error_message(e, parts, alerts, app_state);
             }
         }
     }
 
-    fn alert_designated_callsign(&self, parts: Vec<&str>, app_state: &AppState) -> Vec<String> {
+    // the country, state and city print_cq_message shows for a CQ, for consumers that are not the console
+    pub fn cq_location(&self, app_state: &AppState) -> Option<GridLocation> {
+        let grid = parse_ft8_message(&self.message).grid()?.to_string();
+        let (lat, lon) = grid_to_longlat(&grid).ok()?;
+        let search_result = app_state.geocoder.search((lon, lat));
+        Some(GridLocation {
+            country: iso3166_1::alpha2(&search_result.record.cc).map(|country| country.name.to_string()).unwrap_or_default(),
+            state: search_result.record.admin1.clone(),
+            city: search_result.record.name.clone(),
+            grid,
+        })
+    }
+
+    // calls an alert rule matched are shown in the rule's highlight colour, the bell and other actions are up to the rule
+    fn highlight_alerted_calls(&self, parts: Vec<&str>, alerts: &[AlertMatch]) -> Vec<String> {
         let mut highlighted_parts = Vec::new();
         for part in parts {
-            if app_state.designated_callsigns.contains(&part.to_string()) {
-                print!("\x07"); // bell character
-                highlighted_parts.push(part.black().on_white().to_string()); // highlight the callsign
-            } else {
-                highlighted_parts.push(part.to_string());
+            let call = strip_call(part);
+            match alerts.iter().find(|alert| alert.call == call).and_then(|alert| alert.highlight) {
+                Some(colour) => highlighted_parts.push(part.black().on_color(colour).to_string()),
+                None => highlighted_parts.push(part.to_string()),
             }
         }
         highlighted_parts
     }
-    fn print_non_cq_message(&self, parts: Vec<&str>, app_state: &AppState) {  
-        let highlighted_parts = self.alert_designated_callsign(parts, &app_state);
+    fn print_non_cq_message(&self, parts: Vec<&str>, alerts: &[AlertMatch], app_state: &AppState) {  
+        let mut highlighted_parts = self.highlight_alerted_calls(parts.clone(), alerts);
+        // colour the sending station by what it would give us
+        let message = parse_ft8_message(&self.message);
+        if let Some(from) = message.from_call() {
+            if let Some(index) = parts.iter().position(|part| strip_call(part) == from) {
+                if highlighted_parts[index] == parts[index] {
+                    let needs = self.needs(from, message.grid(), None, app_state);
+                    highlighted_parts[index] = needs.colour_call(parts[index]).to_string();
+                }
+            }
+        }
+        // a call to us is shown inverse, whoever it's from
+        let own_call = app_state.instances.lock().unwrap().status(&self.id)
+            .and_then(|status| non_empty(&status.de_call).map(strip_call));
+        if let Some(own_call) = own_call.filter(|own_call| message.to_calls().contains(&own_call.as_str())) {
+            if let Some(index) = parts.iter().position(|part| strip_call(part) == own_call) {
+                highlighted_parts[index] = parts[index].reversed().bold().to_string();
+            }
+        }
         let message = highlighted_parts.join(" ");
-        println!("{}: SNR: {} {}", self.time, self.format_snr(), message);
+        println!("{}: {}SNR: {} {}", self.time, self.format_frequency(), self.format_snr(), message);
     }
 
-    fn print_error_message(&self, e: MHError, parts: Vec<&str>, app_state: &AppState) {
-        self.print_non_cq_message(parts, &app_state);
+    fn print_error_message(&self, e: MHError, parts: Vec<&str>, alerts: &[AlertMatch], app_state: &AppState) {
+        self.print_non_cq_message(parts, alerts, app_state);
         println!("Error: {}", e);
     }
     fn format_snr(&self) -> ColoredString {
@@ -154,21 +231,37 @@ impl Decode {
             self.snr.to_string().red()
         }
     }
-    fn print_cq_message(&self, parts: Vec<&str>, country: CountryCode, search_result: &SearchResult, typical: bool, app_state: &AppState) {
-        let highlighted_parts = self.alert_designated_callsign(parts, &app_state);
+    fn colour_part(part: &str, highlighted: &str, needs: &Needs) -> ColoredString {
+        if part == highlighted {
+            needs.colour_call(part)
+        } else {
+            highlighted.normal()
+        }
+    }
+    fn print_cq_message(&self, parts: Vec<&str>, country: CountryCode, search_result: &SearchResult, typical: bool, needs: &Needs, alerts: &[AlertMatch]) {
+        let highlighted_parts = self.highlight_alerted_calls(parts.clone(), alerts);
+        let label = needs.colour_call(&needs.label());
 
         if typical {
-            println!("{}: SNR: {} CQ de {} {}, Country: {}, State: {}, City: {}",
-            self.time, self.format_snr(), highlighted_parts[1].green(), highlighted_parts[2].green(), country.name.green(), 
-            search_result.record.admin1.green(), search_result.record.name.green());
+            println!("{}: {}SNR: {} CQ de {} {}, Country: {}, State: {}, City: {} {}",
+            self.time, self.format_frequency(), self.format_snr(), Self::colour_part(parts[1], &highlighted_parts[1], needs), highlighted_parts[2].green(), country.name.green(), 
+            search_result.record.admin1.green(), search_result.record.name.green(), label);
         } else {
-            println!("{}: SNR: {} CQ {} {} {}, Country: {}, State: {}, City: {}",
-            self.time, self.format_snr(), highlighted_parts[1].bold().blue(), highlighted_parts[2].green(), highlighted_parts[3].green(), country.name.green(), 
-            search_result.record.admin1.green(), search_result.record.name.green());
+            println!("{}: {}SNR: {} CQ {} {} {}, Country: {}, State: {}, City: {} {}",
+            self.time, self.format_frequency(), self.format_snr(), highlighted_parts[1].bold().blue(), Self::colour_part(parts[2], &highlighted_parts[2], needs), highlighted_parts[3].green(), country.name.green(), 
+            search_result.record.admin1.green(), search_result.record.name.green(), label);
         }
     }
 }
 
+#[derive(Debug, Clone, Serialize)]
+pub struct GridLocation {
+    pub grid: String,
+    pub country: String,
+    pub state: String,
+    pub city: String,
+}
+
 #[derive(Debug)]
 pub struct Clear {
     pub message_type: u32,
@@ -184,16 +277,33 @@ impl std::fmt::Display for Clear{
 
 #[derive(Debug)]
 pub struct Reply {
-    message_type: u32,
-    id: String,
-    time: NaiveTime,
-    snr: i32,
-    delta_time_s: f64,
-    delta_frequency_hz: u32,
-    mode: String,
-    message: String,
-    low_confidence: bool,
-    modifiers: u8,
+    pub message_type: u32,
+    pub id: String,
+    pub time: NaiveTime,
+    pub snr: i32,
+    pub delta_time_s: f64,
+    pub delta_frequency_hz: u32,
+    pub mode: String,
+    pub message: String,
+    pub low_confidence: bool,
+    pub modifiers: u8,
+}
+impl Reply {
+    // WSJT-X only acts on a Reply that repeats the decode exactly as it was sent to us
+    pub fn from_decode(decode: &Decode, modifiers: u8) -> Self {
+        Self {
+            message_type: 4,
+            id: decode.id.clone(),
+            time: decode.time,
+            snr: decode.snr,
+            delta_time_s: decode.delta_time_s,
+            delta_frequency_hz: decode.delta_frequency_hz,
+            mode: decode.mode.clone(),
+            message: decode.message.clone(),
+            low_confidence: decode.low_confidence,
+            modifiers,
+        }
+    }
 }
 impl std::fmt::Display for Reply{
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
@@ -202,27 +312,27 @@ impl std::fmt::Display for Reply{
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Default)]
 pub struct LogData {
-    message_type: u32,
-    id: String,
-    date_time_off: DateTime<Utc>,
-    dx_call: String,
-    dx_grid: String,
-    tx_frequency_hz: u64,
-    mode: String,
-    report_sent: String,
-    report_received: String,
-    tx_power: String,
-    comments: String,
-    name: String,
-    date_time_on: DateTime<Utc>,
-    operator_call: String,
-    my_call: String,
-    my_grid: String,
-    exchange_sent: String,
-    exchange_received: String,
-    adif_propagation_mode: String,
+    pub message_type: u32,
+    pub id: String,
+    pub date_time_off: DateTime<Utc>,
+    pub dx_call: String,
+    pub dx_grid: String,
+    pub tx_frequency_hz: u64,
+    pub mode: String,
+    pub report_sent: String,
+    pub report_received: String,
+    pub tx_power: String,
+    pub comments: String,
+    pub name: String,
+    pub date_time_on: DateTime<Utc>,
+    pub operator_call: String,
+    pub my_call: String,
+    pub my_grid: String,
+    pub exchange_sent: String,
+    pub exchange_received: String,
+    pub adif_propagation_mode: String,
 }
 impl std::fmt::Display for LogData{
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
@@ -254,9 +364,9 @@ impl std::fmt::Display for Replay{
 }
 #[derive(Debug)]
 pub struct HaltTx {
-    message_type: u32,
-    id: String,
-    auto_tx_only: bool,
+    pub message_type: u32,
+    pub id: String,
+    pub auto_tx_only: bool,
 }
 impl std::fmt::Display for HaltTx{
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
@@ -278,20 +388,20 @@ impl std::fmt::Display for FreeText{
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Serialize)]
 pub struct WSPRDecode {
-    message_type: u32,
-    id: String,
-    new: bool,
-    time: NaiveTime,
-    snr: i32,
-    delta_time_s: f64,
-    frequency_hz: u64,  
-    drift: i32,
-    callsign: String,
-    grid: String,
-    power_dbm: i32,
-    off_air: bool,
+    pub message_type: u32,
+    pub id: String,
+    pub new: bool,
+    pub time: NaiveTime,
+    pub snr: i32,
+    pub delta_time_s: f64,
+    pub frequency_hz: u64,
+    pub drift: i32,
+    pub callsign: String,
+    pub grid: String,
+    pub power_dbm: i32,
+    pub off_air: bool,
 }
 impl std::fmt::Display for WSPRDecode{
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
@@ -301,9 +411,9 @@ impl std::fmt::Display for WSPRDecode{
 }
 #[derive(Debug)]
 pub struct Location {
-    message_type: u32,
-    id: String,
-    location: String,
+    pub message_type: u32,
+    pub id: String,
+    pub location: String,
 }
 impl std::fmt::Display for Location{
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
@@ -313,9 +423,9 @@ impl std::fmt::Display for Location{
 }
 #[derive(Debug)]
 pub struct LoggedADIF {
-    message_type: u32,
-    id: String,
-    adif: String,
+    pub message_type: u32,
+    pub id: String,
+    pub adif: String,
 }
 impl std::fmt::Display for LoggedADIF{
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
@@ -325,12 +435,13 @@ impl std::fmt::Display for LoggedADIF{
 }
 #[derive(Debug)]
 pub struct HighlightCallsignIn {
-    message_type: u32,
-    id: String,
-    callsign: String,
-    background_color: String,  // are QCOLOR
-    foreground_color: String,
-    highlight_last: bool
+    pub message_type: u32,
+    pub id: String,
+    pub callsign: String,
+    // QColor on the wire, "#rrggbb" here with an empty string for no colour
+    pub background_color: String,
+    pub foreground_color: String,
+    pub highlight_last: bool,
 }
 impl std::fmt::Display for HighlightCallsignIn{
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
@@ -340,9 +451,9 @@ impl std::fmt::Display for HighlightCallsignIn{
 }
 #[derive(Debug)]
 pub struct SwitchConfiguration {
-    message_type: u32,
-    id: String,
-    configuration_name: String,
+    pub message_type: u32,
+    pub id: String,
+    pub configuration_name: String,
 }
 impl std::fmt::Display for SwitchConfiguration{
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
@@ -352,17 +463,17 @@ impl std::fmt::Display for SwitchConfiguration{
 }
 #[derive(Debug)]
 pub struct Configure {
-    message_type: u32,
-    id: String,
-    mode: String,
-    frequency_tolerance: u32,
-    submode: String,
-    fast_mode: bool,
-    tr_period: i32,
-    rx_df: i32,
-    dx_call: String,
-    dx_grid: String,
-    generate_messages: bool,
+    pub message_type: u32,
+    pub id: String,
+    pub mode: String,
+    pub frequency_tolerance: u32,
+    pub submode: String,
+    pub fast_mode: bool,
+    pub tr_period: i32,
+    pub rx_df: i32,
+    pub dx_call: String,
+    pub dx_grid: String,
+    pub generate_messages: bool,
 }
 impl std::fmt::Display for Configure{
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
@@ -1,50 +1,61 @@
 use super::*;
 
-fn get_string_from_payload(payload: &[u8]) -> (String, &[u8]) {
-    let (len_bytes, mut rest) = payload.split_at(4);
-    let len = BigEndian::read_u32(len_bytes);
-    let str;
-    if len == 4294967295 {
-        str = "n/a".to_string();
+// every reader returns None when the payload is too short for its value, so a truncated
+// or foreign datagram is dropped instead of panicking
+fn take_from_payload(payload: &[u8], len: usize) -> Option<(&[u8], &[u8])> {
+    (payload.len() >= len).then(|| payload.split_at(len))
+}
+
+fn get_string_from_payload(payload: &[u8]) -> Option<(String, &[u8])> {
+    let (len, rest) = get_u32_from_payload(payload)?;
+    if len == u32::MAX {
+        Some(("n/a".to_string(), rest))
     } else if len == 0 {
-        str = "empty".to_string(); 
+        Some(("empty".to_string(), rest))
     } else {
-        let (str_bytes, rest_new) = rest.split_at(len as usize);
-        rest = rest_new;
-        str = String::from_utf8(str_bytes.to_vec()).unwrap();
+        let (str_bytes, rest) = take_from_payload(rest, len as usize)?;
+        Some((String::from_utf8_lossy(str_bytes).to_string(), rest))
     }
-    (str, rest)
 }
 
-fn get_u64_from_payload(payload: &[u8]) -> (u64, &[u8]) {
-    let (bytes, rest) = payload.split_at(8);
-    let value = BigEndian::read_u64(bytes);
-    (value, rest)
+fn get_u64_from_payload(payload: &[u8]) -> Option<(u64, &[u8])> {
+    let (bytes, rest) = take_from_payload(payload, 8)?;
+    Some((BigEndian::read_u64(bytes), rest))
 }
-fn get_u32_from_payload(payload: &[u8]) -> (u32, &[u8]) {
-    let (bytes, rest) = payload.split_at(4);
-    let value = BigEndian::read_u32(bytes);
-    (value, rest)
+fn get_u32_from_payload(payload: &[u8]) -> Option<(u32, &[u8])> {
+    let (bytes, rest) = take_from_payload(payload, 4)?;
+    Some((BigEndian::read_u32(bytes), rest))
 }
-fn get_bool_from_payload(payload: &[u8]) -> (bool, &[u8]) {
-    let (bytes, rest) = payload.split_at(1);
-    let value = bytes[0] != 0;
-    (value, rest)
+fn get_bool_from_payload(payload: &[u8]) -> Option<(bool, &[u8])> {
+    let (bytes, rest) = take_from_payload(payload, 1)?;
+    Some((bytes[0] != 0, rest))
 }
-fn get_u8_from_payload(payload: &[u8]) -> (u8, &[u8]) {
-    if payload.is_empty() {
-        (0, &[])
-    } else {
-        let (bytes, rest) = payload.split_at(1);
-        let value = bytes[0];
-        (value, rest)
-    }
+fn get_u8_from_payload(payload: &[u8]) -> Option<(u8, &[u8])> {
+    let (bytes, rest) = take_from_payload(payload, 1)?;
+    Some((bytes[0], rest))
 }
 
-fn get_i32_from_payload(payload: &[u8]) -> (i32, &[u8]) {
-    let (bytes, rest) = payload.split_at(4);
-    let value = BigEndian::read_i32(bytes);
-    (value, rest)
+fn get_i32_from_payload(payload: &[u8]) -> Option<(i32, &[u8])> {
+    let (bytes, rest) = take_from_payload(payload, 4)?;
+    Some((BigEndian::read_i32(bytes), rest))
+}
+fn get_u16_from_payload(payload: &[u8]) -> Option<(u16, &[u8])> {
+    let (bytes, rest) = take_from_payload(payload, 2)?;
+    Some((BigEndian::read_u16(bytes), rest))
+}
+// the inverse of add_qcolor_to_payload, invalid colours come back as an empty string
+fn get_qcolor_from_payload(payload: &[u8]) -> Option<(String, &[u8])> {
+    let (spec, rest) = get_u8_from_payload(payload)?;
+    let (_alpha, rest) = get_u16_from_payload(rest)?;
+    let (red, rest) = get_u16_from_payload(rest)?;
+    let (green, rest) = get_u16_from_payload(rest)?;
+    let (blue, rest) = get_u16_from_payload(rest)?;
+    let (_pad, rest) = get_u16_from_payload(rest)?;
+    if spec == 0 {
+        Some((String::new(), rest))
+    } else {
+        Some((format!("#{:02x}{:02x}{:02x}", red >> 8, green >> 8, blue >> 8), rest))
+    }
 }
 fn get_time_from_milliseconds_since_midnight(ms: u32) -> NaiveTime {
     let total_seconds = ms / 1000;
@@ -53,64 +64,97 @@ fn get_time_from_milliseconds_since_midnight(ms: u32) -> NaiveTime {
     let seconds = total_seconds % 60;
     let milliseconds = ms % 1000;
 
-    NaiveTime::from_hms_milli(hours, minutes, seconds, milliseconds)
+    NaiveTime::from_hms_milli_opt(hours, minutes, seconds, milliseconds).unwrap_or_default()
 }
-fn get_f64_from_payload(payload: &[u8]) -> (f64, &[u8]) {
-    let (bytes, rest) = payload.split_at(8);
-    let value = BigEndian::read_f64(bytes);
-    (value, rest)
+fn get_i64_from_payload(payload: &[u8]) -> Option<(i64, &[u8])> {
+    let (bytes, rest) = take_from_payload(payload, 8)?;
+    Some((BigEndian::read_i64(bytes), rest))
+}
+// Qt streams a QDateTime as a julian day, milliseconds since midnight and a timespec,
+// 0 local time, 1 UTC, 2 an offset from UTC in seconds that follows, 3 a time zone id that follows
+fn get_qdatetime_from_payload(payload: &[u8]) -> Option<(DateTime<Utc>, &[u8])> {
+    let (julian_day, rest) = get_i64_from_payload(payload)?;
+    let (ms, rest) = get_u32_from_payload(rest)?;
+    let (timespec, rest) = get_u8_from_payload(rest)?;
+    let (offset_seconds, rest) = match timespec {
+        2 => {
+            let (offset, rest) = get_i32_from_payload(rest)?;
+            (offset as i64, rest)
+        }
+        3 => {
+            // a QByteArray, we don't resolve zone ids and take the time as UTC
+            let (len, rest) = get_u32_from_payload(rest)?;
+            let len = if len == u32::MAX { 0 } else { len as usize };
+            let (_zone, rest) = take_from_payload(rest, len)?;
+            (0, rest)
+        }
+        // WSJT-X sends UTC, local time is taken as UTC too
+        _ => (0, rest),
+    };
+    // julian day 2440588 is 1970-01-01, day 719163 counting from 0001-01-01
+    let date = i32::try_from(julian_day - 1_721_425).ok().and_then(NaiveDate::from_num_days_from_ce_opt);
+    let date_time = match date {
+        Some(date) => Utc.from_utc_datetime(&date.and_time(get_time_from_milliseconds_since_midnight(ms)))
+            - chrono::Duration::seconds(offset_seconds),
+        None => DateTime::<Utc>::default(),
+    };
+    Some((date_time, rest))
+}
+fn get_f64_from_payload(payload: &[u8]) -> Option<(f64, &[u8])> {
+    let (bytes, rest) = take_from_payload(payload, 8)?;
+    Some((BigEndian::read_f64(bytes), rest))
 }
 
-pub fn decode_heartbeat(payload: &[u8], debug: bool) -> Heartbeat{
+pub fn decode_heartbeat(payload: &[u8], debug: bool) -> Option<Heartbeat> {
     if debug {
         println!("Heartbeat message");
     }
-    let (message_type, rest) = get_u32_from_payload(payload);
-    let (id, rest) = get_string_from_payload(rest);
-    let (maximum_schema_number, rest) = get_u32_from_payload(rest);      
-    let (version, rest) = get_string_from_payload(rest);
-    let (revision, _rest) = get_string_from_payload(rest);
+    let (message_type, rest) = get_u32_from_payload(payload)?;
+    let (id, rest) = get_string_from_payload(rest)?;
+    let (maximum_schema_number, rest) = get_u32_from_payload(rest)?;      
+    let (version, rest) = get_string_from_payload(rest)?;
+    let (revision, _rest) = get_string_from_payload(rest)?;
     let heartbeat = Heartbeat {
         message_type,
         id,
         maximum_schema_number,
-        version: version,
-        revision: revision,
+        version,
+        revision,
         };
     if debug {
         println!("Heartbeat: {}", heartbeat)
     }
-    heartbeat
+    Some(heartbeat)
 }
 
-pub fn decode_status(payload: &[u8], debug: bool) -> Status {
+pub fn decode_status(payload: &[u8], debug: bool) -> Option<Status> {
     if debug {
         println!("Status message");
     }
     // println!("Payload: {:?}", payload);
-    let (message_type, rest) = get_u32_from_payload(payload);
-    let (id, rest) = get_string_from_payload(rest);
-    let (dial_frequency, rest) = get_u64_from_payload(rest);
-    let (mode, rest) = get_string_from_payload(rest);
-    let (dx_call, rest) = get_string_from_payload(rest);
-    let (report, rest) = get_string_from_payload(rest);
-    let (tx_mode, rest) = get_string_from_payload(rest);
-    let (tx_enabled, rest) = get_bool_from_payload(rest);
-    let (transmitting, rest) = get_bool_from_payload(rest);
-    let (decoding, rest) = get_bool_from_payload(rest);
-    let (rx_df, rest) = get_u32_from_payload(rest);
-    let (tx_df, rest) = get_u32_from_payload(rest);
-    let (de_call, rest) = get_string_from_payload(rest);
-    let (de_grid, rest) = get_string_from_payload(rest);
-    let (dx_grid, rest) = get_string_from_payload(rest);
-    let (tx_watchdog, rest) = get_bool_from_payload(rest);
-    let (sub_mode, rest) = get_string_from_payload(rest);
-    let (fast_mode, rest) = get_bool_from_payload(rest);
-    let (special_operation_mode, rest) = get_u8_from_payload(rest);
-    let (frequency_tolerance, rest) = get_u32_from_payload(rest);
-    let (tr_period, rest) = get_u32_from_payload(rest);
-    let (configuration_name, rest) = get_string_from_payload(rest);
-    let (tx_message, _rest) = get_string_from_payload(rest);
+    let (message_type, rest) = get_u32_from_payload(payload)?;
+    let (id, rest) = get_string_from_payload(rest)?;
+    let (dial_frequency, rest) = get_u64_from_payload(rest)?;
+    let (mode, rest) = get_string_from_payload(rest)?;
+    let (dx_call, rest) = get_string_from_payload(rest)?;
+    let (report, rest) = get_string_from_payload(rest)?;
+    let (tx_mode, rest) = get_string_from_payload(rest)?;
+    let (tx_enabled, rest) = get_bool_from_payload(rest)?;
+    let (transmitting, rest) = get_bool_from_payload(rest)?;
+    let (decoding, rest) = get_bool_from_payload(rest)?;
+    let (rx_df, rest) = get_u32_from_payload(rest)?;
+    let (tx_df, rest) = get_u32_from_payload(rest)?;
+    let (de_call, rest) = get_string_from_payload(rest)?;
+    let (de_grid, rest) = get_string_from_payload(rest)?;
+    let (dx_grid, rest) = get_string_from_payload(rest)?;
+    let (tx_watchdog, rest) = get_bool_from_payload(rest)?;
+    let (sub_mode, rest) = get_string_from_payload(rest)?;
+    let (fast_mode, rest) = get_bool_from_payload(rest)?;
+    let (special_operation_mode, rest) = get_u8_from_payload(rest)?;
+    let (frequency_tolerance, rest) = get_u32_from_payload(rest)?;
+    let (tr_period, rest) = get_u32_from_payload(rest)?;
+    let (configuration_name, rest) = get_string_from_payload(rest)?;
+    let (tx_message, _rest) = get_string_from_payload(rest)?;
 
     let status = Status {
         message_type,
@@ -140,51 +184,53 @@ pub fn decode_status(payload: &[u8], debug: bool) -> Status {
     if debug {
         println!("Status: {}", status);
     }
-    status
+    Some(status)
 }
 
-pub fn decode_decode(payload: &[u8], debug: bool) -> Decode {
+pub fn decode_decode(payload: &[u8], debug: bool) -> Option<Decode> {
     if debug {
         println!("Decode message");
     }
-    let (message_type, rest) = get_u32_from_payload(payload);
-    let (id, rest) = get_string_from_payload(rest);
-    let (new, rest) = get_bool_from_payload(rest);
-    let (time, rest) = get_u32_from_payload(rest);
+    let (message_type, rest) = get_u32_from_payload(payload)?;
+    let (id, rest) = get_string_from_payload(rest)?;
+    let (new, rest) = get_bool_from_payload(rest)?;
+    let (time, rest) = get_u32_from_payload(rest)?;
     let time = get_time_from_milliseconds_since_midnight(time);
-    let (snr, rest) = get_i32_from_payload(rest);
-    let (delta_time_s, rest) = get_f64_from_payload(rest);
-    let (delta_frequency_hz, rest) = get_u32_from_payload(rest);
-    let (mode, rest) = get_string_from_payload(rest);
-    let (message, rest) = get_string_from_payload(rest);
-    let (low_confidence, rest) = get_bool_from_payload(rest);
-    let (off_air, _rest) = get_bool_from_payload(rest);
+    let (snr, rest) = get_i32_from_payload(rest)?;
+    let (delta_time_s, rest) = get_f64_from_payload(rest)?;
+    let (delta_frequency_hz, rest) = get_u32_from_payload(rest)?;
+    let (mode, rest) = get_string_from_payload(rest)?;
+    let (message, rest) = get_string_from_payload(rest)?;
+    let (low_confidence, rest) = get_bool_from_payload(rest)?;
+    let (off_air, _rest) = get_bool_from_payload(rest)?;
     let decode = Decode {
         message_type,
         id,
         new,
         time,
         snr,
-        delta_time_s: delta_time_s as f64,
+        delta_time_s,
         delta_frequency_hz,
         mode,
         message,
         low_confidence,
         off_air,
+        rf_frequency_hz: None,
+        band: None,
     };
     if debug {
         println!("Decode: {}", decode);
     }
-    decode
+    Some(decode)
 }
 
-pub fn decode_clear(payload: &[u8], debug: bool) -> Clear{
+pub fn decode_clear(payload: &[u8], debug: bool) -> Option<Clear> {
     if debug {
         println!("Clear message");
     }
-    let (message_type, rest) = get_u32_from_payload(payload);
-    let (id, rest) = get_string_from_payload(rest);
-    let (window, _rest) = get_u8_from_payload(rest);
+    let (message_type, rest) = get_u32_from_payload(payload)?;
+    let (id, rest) = get_string_from_payload(rest)?;
+    let (window, _rest) = get_u8_from_payload(rest)?;
     let clear = Clear {
         message_type,
         id,
@@ -193,30 +239,30 @@ pub fn decode_clear(payload: &[u8], debug: bool) -> Clear{
     if debug {
         println!("Clear: {}", clear);
     }
-    clear
+    Some(clear)
 }
 
-pub fn decode_reply(payload: &[u8], debug: bool) -> Reply{
+pub fn decode_reply(payload: &[u8], debug: bool) -> Option<Reply> {
     if debug {
         println!("Reply message");
     }
-    let (message_type, rest) = get_u32_from_payload(payload);
-    let (id, rest) = get_string_from_payload(rest);
-    let (time, rest) = get_u32_from_payload(rest);
+    let (message_type, rest) = get_u32_from_payload(payload)?;
+    let (id, rest) = get_string_from_payload(rest)?;
+    let (time, rest) = get_u32_from_payload(rest)?;
     let time = get_time_from_milliseconds_since_midnight(time);
-    let (snr, rest) = get_i32_from_payload(rest);
-    let (delta_time_s, rest) = get_f64_from_payload(rest);
-    let (delta_frequency_hz, rest) = get_u32_from_payload(rest);
-    let (mode, rest) = get_string_from_payload(rest);
-    let (message, rest) = get_string_from_payload(rest);
-    let (low_confidence, rest) = get_bool_from_payload(rest);
-    let (modifiers, _rest) = get_u8_from_payload(rest);
+    let (snr, rest) = get_i32_from_payload(rest)?;
+    let (delta_time_s, rest) = get_f64_from_payload(rest)?;
+    let (delta_frequency_hz, rest) = get_u32_from_payload(rest)?;
+    let (mode, rest) = get_string_from_payload(rest)?;
+    let (message, rest) = get_string_from_payload(rest)?;
+    let (low_confidence, rest) = get_bool_from_payload(rest)?;
+    let (modifiers, _rest) = get_u8_from_payload(rest)?;
     let reply = Reply {
         message_type,
         id,
         time,
         snr,
-        delta_time_s: delta_time_s as f64,
+        delta_time_s,
         delta_frequency_hz,
         mode,
         message,
@@ -226,34 +272,32 @@ pub fn decode_reply(payload: &[u8], debug: bool) -> Reply{
     if debug {
         println!("Reply: {}", reply);
     }
-    reply
+    Some(reply)
 }
 
-pub fn decode_logdata(payload: &[u8], debug: bool) -> LogData{
+pub fn decode_logdata(payload: &[u8], debug: bool) -> Option<LogData> {
     if debug {
         println!("LogData message");
     }
-    let (message_type, rest) = get_u32_from_payload(payload);
-    let (id, rest) = get_string_from_payload(rest);
-    let (date_time_off, rest) = get_u64_from_payload(rest);
-    let date_time_off = Utc.timestamp(date_time_off as i64, 0);
-    let (dx_call, rest) = get_string_from_payload(rest);
-    let (dx_grid, rest) = get_string_from_payload(rest);
-    let (tx_frequency_hz, rest) = get_u64_from_payload(rest);
-    let (mode, rest) = get_string_from_payload(rest);
-    let (report_sent, rest) = get_string_from_payload(rest);
-    let (report_received, rest) = get_string_from_payload(rest);
-    let (tx_power, rest) = get_string_from_payload(rest);
-    let (comments, rest) = get_string_from_payload(rest);
-    let (name, rest) = get_string_from_payload(rest);
-    let (date_time_on, rest) = get_u64_from_payload(rest);
-    let date_time_on = Utc.timestamp(date_time_on as i64, 0);
-    let (operator_call, rest) = get_string_from_payload(rest);
-    let (my_call, rest) = get_string_from_payload(rest);
-    let (my_grid, rest) = get_string_from_payload(rest);
-    let (exchange_sent, rest) = get_string_from_payload(rest);
-    let (exchange_received, rest) = get_string_from_payload(rest);
-    let (adif_propagation_mode, _rest) = get_string_from_payload(rest);
+    let (message_type, rest) = get_u32_from_payload(payload)?;
+    let (id, rest) = get_string_from_payload(rest)?;
+    let (date_time_off, rest) = get_qdatetime_from_payload(rest)?;
+    let (dx_call, rest) = get_string_from_payload(rest)?;
+    let (dx_grid, rest) = get_string_from_payload(rest)?;
+    let (tx_frequency_hz, rest) = get_u64_from_payload(rest)?;
+    let (mode, rest) = get_string_from_payload(rest)?;
+    let (report_sent, rest) = get_string_from_payload(rest)?;
+    let (report_received, rest) = get_string_from_payload(rest)?;
+    let (tx_power, rest) = get_string_from_payload(rest)?;
+    let (comments, rest) = get_string_from_payload(rest)?;
+    let (name, rest) = get_string_from_payload(rest)?;
+    let (date_time_on, rest) = get_qdatetime_from_payload(rest)?;
+    let (operator_call, rest) = get_string_from_payload(rest)?;
+    let (my_call, rest) = get_string_from_payload(rest)?;
+    let (my_grid, rest) = get_string_from_payload(rest)?;
+    let (exchange_sent, rest) = get_string_from_payload(rest)?;
+    let (exchange_received, rest) = get_string_from_payload(rest)?;
+    let (adif_propagation_mode, _rest) = get_string_from_payload(rest)?;
     let logdata = LogData {
         message_type,
         id,
@@ -278,15 +322,15 @@ pub fn decode_logdata(payload: &[u8], debug: bool) -> LogData{
     if debug {
         println!("LogData: {}", logdata);
     }
-    logdata
+    Some(logdata)
 }
 
-pub fn decode_close(payload: &[u8], debug: bool) -> Close{
+pub fn decode_close(payload: &[u8], debug: bool) -> Option<Close> {
     if debug {
         println!("Close message");
     }
-    let (message_type, rest) = get_u32_from_payload(payload);
-    let (id, _rest) = get_string_from_payload(rest);
+    let (message_type, rest) = get_u32_from_payload(payload)?;
+    let (id, _rest) = get_string_from_payload(rest)?;
     let close = Close {
         message_type,
         id,
@@ -294,15 +338,15 @@ pub fn decode_close(payload: &[u8], debug: bool) -> Close{
     if debug {
         println!("Close: {}", close);
     }
-    close
+    Some(close)
 }
 
-pub fn decode_replay(payload: &[u8], debug: bool) -> Replay{
+pub fn decode_replay(payload: &[u8], debug: bool) -> Option<Replay> {
     if debug {
         println!("Replay message");
     }
-    let (message_type, rest) = get_u32_from_payload(payload);
-    let (id, _rest) = get_string_from_payload(rest);
+    let (message_type, rest) = get_u32_from_payload(payload)?;
+    let (id, _rest) = get_string_from_payload(rest)?;
     let replay = Replay {
         message_type,
         id,
@@ -310,16 +354,16 @@ pub fn decode_replay(payload: &[u8], debug: bool) -> Replay{
     if debug {
         println!("Replay: {}", replay);
     }
-    replay
+    Some(replay)
 }
 
-pub fn decode_halt_tx(payload: &[u8], debug: bool) -> HaltTx{
+pub fn decode_halt_tx(payload: &[u8], debug: bool) -> Option<HaltTx> {
     if debug {
         println!("Halt Tx message");
     }
-    let (message_type, rest) = get_u32_from_payload(payload);
-    let (id, rest) = get_string_from_payload(rest);
-    let (auto_tx_only, _rest) = get_bool_from_payload(rest);
+    let (message_type, rest) = get_u32_from_payload(payload)?;
+    let (id, rest) = get_string_from_payload(rest)?;
+    let (auto_tx_only, _rest) = get_bool_from_payload(rest)?;
     let halt_tx = HaltTx {
         message_type,
         id,
@@ -328,16 +372,16 @@ pub fn decode_halt_tx(payload: &[u8], debug: bool) -> HaltTx{
     if debug {
         println!("HaltTx: {}", halt_tx);
     }
-    halt_tx
+    Some(halt_tx)
 }
-pub fn decode_free_text(payload: &[u8], debug: bool) -> FreeText{
+pub fn decode_free_text(payload: &[u8], debug: bool) -> Option<FreeText> {
     if debug {
         println!("Free Text message");
     }
-    let (message_type, rest) = get_u32_from_payload(payload);
-    let (id, rest) = get_string_from_payload(rest);
-    let (text, rest) = get_string_from_payload(rest);
-    let (send, _rest) = get_bool_from_payload(rest);
+    let (message_type, rest) = get_u32_from_payload(payload)?;
+    let (id, rest) = get_string_from_payload(rest)?;
+    let (text, rest) = get_string_from_payload(rest)?;
+    let (send, _rest) = get_bool_from_payload(rest)?;
     let freetext = FreeText {
         message_type,
         id,
@@ -347,33 +391,33 @@ pub fn decode_free_text(payload: &[u8], debug: bool) -> FreeText{
     if debug {
         println!("FreeText: {}", freetext);
     }
-    freetext
+    Some(freetext)
 }
 
-pub fn decode_wspr_decode(payload: &[u8], debug: bool) -> WSPRDecode{
+pub fn decode_wspr_decode(payload: &[u8], debug: bool) -> Option<WSPRDecode> {
     if debug {
         println!("WSPR Decode message");
     }
-    let (message_type, rest) = get_u32_from_payload(payload);
-    let (id, rest) = get_string_from_payload(rest);
-    let (new, rest) = get_bool_from_payload(rest);
-    let (time, rest) = get_u32_from_payload(rest);
+    let (message_type, rest) = get_u32_from_payload(payload)?;
+    let (id, rest) = get_string_from_payload(rest)?;
+    let (new, rest) = get_bool_from_payload(rest)?;
+    let (time, rest) = get_u32_from_payload(rest)?;
     let time = get_time_from_milliseconds_since_midnight(time);
-    let (snr, rest) = get_i32_from_payload(rest);
-    let (delta_time_s, rest) = get_f64_from_payload(rest);
-    let (frequency_hz, rest) = get_u64_from_payload(rest);
-    let (drift, rest) = get_i32_from_payload(rest);
-    let (callsign, rest) = get_string_from_payload(rest);
-    let (grid, rest) = get_string_from_payload(rest);
-    let (power_dbm, rest) = get_i32_from_payload(rest);
-    let (off_air, _rest) = get_bool_from_payload(rest);
+    let (snr, rest) = get_i32_from_payload(rest)?;
+    let (delta_time_s, rest) = get_f64_from_payload(rest)?;
+    let (frequency_hz, rest) = get_u64_from_payload(rest)?;
+    let (drift, rest) = get_i32_from_payload(rest)?;
+    let (callsign, rest) = get_string_from_payload(rest)?;
+    let (grid, rest) = get_string_from_payload(rest)?;
+    let (power_dbm, rest) = get_i32_from_payload(rest)?;
+    let (off_air, _rest) = get_bool_from_payload(rest)?;
     let wsprdecode = WSPRDecode {
         message_type,
         id,
         new,
         time,
         snr,
-        delta_time_s: delta_time_s as f64,
+        delta_time_s,
         frequency_hz,
         drift,
         callsign,
@@ -384,16 +428,16 @@ pub fn decode_wspr_decode(payload: &[u8], debug: bool) -> WSPRDecode{
     if debug {
         println!("WSPRDecode: {}", wsprdecode);
     }
-    wsprdecode
+    Some(wsprdecode)
 }
 
-pub fn decode_location(payload: &[u8], debug: bool) -> Location { 
+pub fn decode_location(payload: &[u8], debug: bool) -> Option<Location> { 
     if debug {
         println!("Location message");
     }
-    let (message_type, rest) = get_u32_from_payload(payload);
-    let (id, rest) = get_string_from_payload(rest);
-    let (location, _rest) = get_string_from_payload(rest);
+    let (message_type, rest) = get_u32_from_payload(payload)?;
+    let (id, rest) = get_string_from_payload(rest)?;
+    let (location, _rest) = get_string_from_payload(rest)?;
     let location = Location {
         message_type,
         id,
@@ -402,16 +446,16 @@ pub fn decode_location(payload: &[u8], debug: bool) -> Location {
     if debug {
         println!("Location: {}", location);
     }
-    location
+    Some(location)
 }
 
-pub fn decode_logged_adif(payload: &[u8], debug: bool) -> LoggedADIF{
+pub fn decode_logged_adif(payload: &[u8], debug: bool) -> Option<LoggedADIF> {
     if debug {
         println!("Logged ADIF message");
     }
-    let (message_type, rest) = get_u32_from_payload(payload);
-    let (id, rest) = get_string_from_payload(rest);
-    let (adif, _rest) = get_string_from_payload(rest);
+    let (message_type, rest) = get_u32_from_payload(payload)?;
+    let (id, rest) = get_string_from_payload(rest)?;
+    let (adif, _rest) = get_string_from_payload(rest)?;
     let loggedadif = LoggedADIF {
         message_type,
         id,
@@ -420,19 +464,19 @@ pub fn decode_logged_adif(payload: &[u8], debug: bool) -> LoggedADIF{
     if debug {
         println!("LoggedADIF: {}", loggedadif);
     }
-    loggedadif
+    Some(loggedadif)
 }
 
-pub fn decode_highlight_callsign_in(payload: &[u8], debug: bool) -> HighlightCallsignIn{
+pub fn decode_highlight_callsign_in(payload: &[u8], debug: bool) -> Option<HighlightCallsignIn> {
     if debug {
         println!("Highlight Callsign In message");
     }
-    let (message_type, rest) = get_u32_from_payload(payload);
-    let (id, rest) = get_string_from_payload(rest);
-    let (callsign, rest) = get_string_from_payload(rest);
-    let (background_color, rest) = get_string_from_payload(rest);
-    let (foreground_color, rest) = get_string_from_payload(rest);
-    let (highlight_last, _rest) = get_bool_from_payload(rest);
+    let (message_type, rest) = get_u32_from_payload(payload)?;
+    let (id, rest) = get_string_from_payload(rest)?;
+    let (callsign, rest) = get_string_from_payload(rest)?;
+    let (background_color, rest) = get_qcolor_from_payload(rest)?;
+    let (foreground_color, rest) = get_qcolor_from_payload(rest)?;
+    let (highlight_last, _rest) = get_bool_from_payload(rest)?;
     let highlightcallsignin = HighlightCallsignIn {
         message_type,
         id,
@@ -444,16 +488,16 @@ pub fn decode_highlight_callsign_in(payload: &[u8], debug: bool) -> HighlightCal
     if debug {
         println!("HighlightCallsignIn: {}", highlightcallsignin);
     }
-    highlightcallsignin
+    Some(highlightcallsignin)
 }
 
-pub fn decode_switch_configuration(payload: &[u8], debug: bool) -> SwitchConfiguration{
+pub fn decode_switch_configuration(payload: &[u8], debug: bool) -> Option<SwitchConfiguration> {
     if debug {
         println!("Switch Configuration message");
     }
-    let (message_type, rest) = get_u32_from_payload(payload);
-    let (id, rest) = get_string_from_payload(rest);
-    let (configuration_name, _rest) = get_string_from_payload(rest);
+    let (message_type, rest) = get_u32_from_payload(payload)?;
+    let (id, rest) = get_string_from_payload(rest)?;
+    let (configuration_name, _rest) = get_string_from_payload(rest)?;
     let switchconfiguration = SwitchConfiguration {
         message_type,
         id,
@@ -462,24 +506,24 @@ pub fn decode_switch_configuration(payload: &[u8], debug: bool) -> SwitchConfigu
     if debug {
         println!("SwitchConfiguration: {}", switchconfiguration);
     }
-    switchconfiguration
+    Some(switchconfiguration)
 }
 
-pub fn decode_configure(payload: &[u8], debug: bool) -> Configure{
+pub fn decode_configure(payload: &[u8], debug: bool) -> Option<Configure> {
     if debug {
         println!("Configure message");
     }
-    let (message_type, rest) = get_u32_from_payload(payload);
-    let (id, rest) = get_string_from_payload(rest);
-    let (mode, rest) = get_string_from_payload(rest);
-    let (frequency_tolerance, rest) = get_u32_from_payload(rest);
-    let (submode, rest) = get_string_from_payload(rest);
-    let (fast_mode, rest) = get_bool_from_payload(rest);
-    let (tr_period, rest) = get_i32_from_payload(rest);
-    let (rx_df, rest) = get_i32_from_payload(rest);
-    let (dx_call, rest) = get_string_from_payload(rest);
-    let (dx_grid, rest) = get_string_from_payload(rest);
-    let (generate_messages, _rest) = get_bool_from_payload(rest);
+    let (message_type, rest) = get_u32_from_payload(payload)?;
+    let (id, rest) = get_string_from_payload(rest)?;
+    let (mode, rest) = get_string_from_payload(rest)?;
+    let (frequency_tolerance, rest) = get_u32_from_payload(rest)?;
+    let (submode, rest) = get_string_from_payload(rest)?;
+    let (fast_mode, rest) = get_bool_from_payload(rest)?;
+    let (tr_period, rest) = get_i32_from_payload(rest)?;
+    let (rx_df, rest) = get_i32_from_payload(rest)?;
+    let (dx_call, rest) = get_string_from_payload(rest)?;
+    let (dx_grid, rest) = get_string_from_payload(rest)?;
+    let (generate_messages, _rest) = get_bool_from_payload(rest)?;
     let configure = Configure {
         message_type,
         id,
@@ -496,52 +540,284 @@ pub fn decode_configure(payload: &[u8], debug: bool) -> Configure{
     if debug {
         println!("Configure: {}", configure);
     }
-    configure
+    Some(configure)
 }
 
-pub fn handle_incoming_data(data: &[u8], app_state: &AppState) {
-    if data.len() < 8 {
-        eprintln!("Data too short to be a valid message");
+// decodes within this many Hz of our RX offset also go to the RX frequency view
+const RX_WINDOW_HZ: u32 = 50;
+
+// a station calling us, unless it's the one we are already working
+fn track_caller(decode: &Decode, context: &FilterContext, app_state: &AppState) {
+    let call = match context.message.from_call() {
+        Some(call) => call,
+        None => return,
+    };
+    let working = app_state.instances.lock().unwrap().status(&decode.id)
+        .and_then(|status| non_empty(&status.dx_call).map(|dx_call| strip_call(dx_call) == call))
+        .unwrap_or(false);
+    if working {
         return;
     }
-    //split header from payload
-    let (header, payload) = data.split_at(8);
-    //get magic number and schema number from header
-    let magic_number = u32::from_be_bytes([header[0], header[1], header[2], header[3]]);
-    let schema_number = u32::from_be_bytes([header[4], header[5], header[6], header[7]]);
-    // create a message object from the data
-    let message = Message {
-        magic_number,
-        schema_number,
-        payload: payload.to_vec(),
+    let new_caller = app_state.callers.lock().unwrap().heard(call, decode, context.needs.as_ref(), Utc::now());
+    if new_caller {
+        called_me_alert(call, decode, context.needs.as_ref(), app_state);
+        app_state.log(&format!("{} is calling, {} dB", call, decode.snr));
+    }
+}
+
+const MAGIC_NUMBER: u32 = 0xadbccbda;
+const LAST_MESSAGE_TYPE: u32 = 15;
+
+// the message type and the payload it starts, None for anything that isn't a WSJT-X datagram
+fn parse_header(data: &[u8]) -> Option<(Message, u32)> {
+    let (magic_number, rest) = get_u32_from_payload(data)?;
+    let (schema_number, payload) = get_u32_from_payload(rest)?;
+    let (messagetype, _rest) = get_u32_from_payload(payload)?;
+    let message = Message { magic_number, schema_number, payload: payload.to_vec() };
+    (magic_number == MAGIC_NUMBER).then_some((message, messagetype))
+}
+
+pub fn handle_incoming_data(data: &[u8], src: SocketAddr, app_state: &AppState) {
+    let (message, messagetype) = match parse_header(data) {
+        Some(header) => header,
+        None => return app_state.log(&format!("Ignored a datagram from {} that isn't a WSJT-X message", src)),
     };
     if DEBUG {
         println!("Message: {:?}", message);
     }
-    // println!("Magic Number: {:#x}", magic_number);
-    // println!("Schema Number: {:x}", schema_number);
-    // println!("Received message: {:?}", data);
-    // println!("xxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxxx");
-    //get messagetype from the payload
-    let messagetype = u32::from_be_bytes([payload[0], payload[1], payload[2], payload[3]]);
-    // println!("Message Type: {:x}", messagetype); 
+    let payload = &message.payload[..];
+    if messagetype > LAST_MESSAGE_TYPE {
+        eprintln!("Unknown Message Type");
+        return;
+    }
+    let malformed = || app_state.log(&format!("Ignored a truncated or malformed message type {} from {}", messagetype, src));
+    let id = match get_string_from_payload(&payload[4..]) {
+        Some((id, _rest)) => id,
+        None => return malformed(),
+    };
+    app_state.instances.lock().unwrap().heard(&id, src);
     match messagetype {
-        0 => { decode_heartbeat(payload, DEBUG); }
-        1 => { decode_status(payload, DEBUG); }
-        2 => { decode_decode(payload, DEBUG).print_message(&app_state) ; }
-        3 => { decode_clear(payload, true); }
+        0 => {
+            let heartbeat = match decode_heartbeat(payload, DEBUG) {
+                Some(heartbeat) => heartbeat,
+                None => return malformed(),
+            };
+            app_state.instances.lock().unwrap().heard(&id, src).version = Some(heartbeat.version);
+        }
+        1 => {
+            let status = match decode_status(payload, DEBUG) {
+                Some(status) => status,
+                None => return malformed(),
+            };
+            let qsos = app_state.qso_tracker.lock().unwrap().track_status(&status, Utc::now());
+            if !app_state.tui {
+                qsos.iter().for_each(print_qso_update);
+            }
+            broadcast_json(app_state, &status_json(&status));
+            supervise_tx(&status, app_state);
+            fox_hound_status(&status, app_state);
+            app_state.with_store(|store| store.add_status(&status, app_state.config().iaru_region, Utc::now()));
+            update_contest(&status, app_state);
+            // once we are calling them they are no longer waiting
+            if let Some(dx_call) = non_empty(&status.dx_call) {
+                app_state.callers.lock().unwrap().remove(&strip_call(dx_call));
+            }
+            confirm_rotation(&status, app_state);
+            update_clear_frequency(&status, app_state);
+            update_hunt(&status, app_state);
+            answer_best_cq(&status, app_state);
+            app_state.instances.lock().unwrap().heard(&id, src).status = Some(status);
+        }
+        2 => {
+            let mut decode = match decode_decode(payload, DEBUG) {
+                Some(decode) => decode,
+                None => return malformed(),
+            };
+            let rx_df = match app_state.instances.lock().unwrap().status(&decode.id) {
+                Some(status) => {
+                    decode.annotate_frequency(status.dial_frequency, app_state.config().iaru_region);
+                    Some(status.rx_df)
+                }
+                None => None,
+            };
+            let context = FilterContext::new(&decode, app_state);
+            let display_filter = app_state.display_filter.lock().unwrap().clone();
+            let shown = display_filter.map(|filter| filter.matches(&context)).unwrap_or(true);
+            let alerts = app_state.alerts.lock().unwrap().check(&context, app_state, Utc::now());
+            if let Some(call) = context.message.from_call() {
+                app_state.wsjtx_highlights.lock().unwrap().update(&decode, call, context.message.grid(), context.needs.as_ref(), app_state);
+            }
+            if shown && !app_state.tui {
+                decode.print_message(&alerts, app_state);
+            }
+            if context.is_to_me() {
+                track_caller(&decode, &context, app_state);
+                app_state.tx_supervisor.lock().unwrap().replied(&decode.id);
+            }
+            hound_decode(&context, app_state);
+            hunt_decode(&context, app_state);
+            rotation_decode(&decode, app_state);
+            occupancy_decode(&decode, app_state);
+            consider_cq(&context, app_state);
+            psk_reporter_decode(&context, app_state);
+            cluster_decode(&context, app_state);
+            app_state.callers.lock().unwrap().prune(Utc::now());
+            let in_rx_window = context.is_to_me()
+                || rx_df.map(|rx_df| decode.delta_frequency_hz.abs_diff(rx_df) <= RX_WINDOW_HZ).unwrap_or(false);
+            broadcast_decode(&decode, context.needs.as_ref(), app_state);
+            app_state.with_store(|store| {
+                store.add_decode(&decode, &context.message, Utc::now())?;
+                store.prune(&app_state.config().store, Utc::now())
+            });
+            let entry = ActivityEntry { decode: decode.clone(), needs: context.needs.clone(), shown };
+            app_state.activity.lock().unwrap().push(entry, in_rx_window);
+            let mut qso_tracker = app_state.qso_tracker.lock().unwrap();
+            let qsos = qso_tracker.track_decode(&decode, Utc::now());
+            if !app_state.tui {
+                qsos.iter().for_each(print_qso_update);
+            }
+            qso_tracker.prune(Utc::now());
+        }
+        3 => {
+            let clear = match decode_clear(payload, !app_state.tui) {
+                Some(clear) => clear,
+                None => return malformed(),
+            };
+            app_state.activity.lock().unwrap().clear(clear.window);
+        }
         4 => { decode_reply(payload, DEBUG); }
-        5 => { decode_logdata(payload, DEBUG); }
-        6 => { decode_close(payload, true); }
+        5 => {
+            let logdata = match decode_logdata(payload, DEBUG) {
+                Some(logdata) => logdata,
+                None => return malformed(),
+            };
+            let config = app_state.config();
+            if let Some(path) = config.adif_log_path(&app_state.config_path) {
+                match append_adif_record(&path, &logdata_to_adif(&logdata, config.iaru_region)) {
+                    Ok(()) => app_state.log(&format!("Logged {} to {}", logdata.dx_call, path.display())),
+                    Err(e) => app_state.log(&format!("Could not write {}: {}", path.display(), e)),
+                }
+            }
+            // worked-before and the contest count the LoggedADIF record WSJT-X sends for the same QSO
+            app_state.with_store(|store| store.add_qso(&logdata, config.iaru_region));
+            app_state.callers.lock().unwrap().remove(&logdata.dx_call);
+            hunt_logged(&logdata.dx_call, app_state);
+        }
+        6 => { decode_close(payload, !app_state.tui); }
         7 => { decode_replay(payload, DEBUG); }
         8 => { decode_halt_tx(payload, DEBUG); }
         9 => { decode_free_text(payload, DEBUG); }
-        10 => { decode_wspr_decode(payload, DEBUG); }
+        10 => {
+            let wspr_decode = match decode_wspr_decode(payload, DEBUG) {
+                Some(wspr_decode) => wspr_decode,
+                None => return malformed(),
+            };
+            handle_wspr_decode(&wspr_decode, app_state);
+            broadcast_json(app_state, &wspr_decode_json(&wspr_decode));
+            app_state.with_store(|store| store.add_wspr_decode(&wspr_decode, app_state.config().iaru_region, Utc::now()));
+        }
         11 => { decode_location(payload, DEBUG); }
-        12 => { decode_logged_adif(payload, DEBUG); }
+        12 => {
+            let logged_adif = match decode_logged_adif(payload, DEBUG) {
+                Some(logged_adif) => logged_adif,
+                None => return malformed(),
+            };
+            // the validated record is what counts, the LogData WSJT-X sends for the same QSO only goes to the files
+            let import = AdifImport::from_text(&logged_adif.adif);
+            for problem in import.rejected.iter().chain(&import.warnings) {
+                app_state.log(&format!("LoggedADIF from {}: {}", logged_adif.id, problem));
+            }
+            let iaru_region = app_state.config().iaru_region;
+            for qso in &import.qsos {
+                let worked = WorkedQso::from_logged_adif(qso, iaru_region, app_state);
+                app_state.worked_before.lock().unwrap().add_qso(&worked, &app_state.dxcc);
+                log_contest_qso(qso, app_state);
+            }
+            if !import.qsos.is_empty() {
+                app_state.wsjtx_highlights.lock().unwrap().refresh(app_state);
+            }
+        }
         13 => { decode_highlight_callsign_in(payload, DEBUG); }
         14 => { decode_switch_configuration(payload, DEBUG); }
         15 => { decode_configure(payload, DEBUG); }
         _ => eprintln!("Unknown Message Type"),
     };
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // a Decode payload as WSJT-X streams it, after the header
+    fn decode_payload() -> Vec<u8> {
+        let mut payload = Vec::new();
+        add_u32_to_payload(&mut payload, 2);
+        add_string_to_payload(&mut payload, "WSJT-X");
+        add_bool_to_payload(&mut payload, true);
+        add_u32_to_payload(&mut payload, 45_015_000);
+        add_i32_to_payload(&mut payload, -12);
+        add_f64_to_payload(&mut payload, 0.3);
+        add_u32_to_payload(&mut payload, 1234);
+        add_string_to_payload(&mut payload, "~");
+        add_string_to_payload(&mut payload, "CQ K1ABC FN42");
+        add_bool_to_payload(&mut payload, false);
+        add_bool_to_payload(&mut payload, false);
+        payload
+    }
+
+    #[test]
+    fn truncated_payloads_are_dropped() {
+        let payload = decode_payload();
+        let decode = decode_decode(&payload, false).unwrap();
+        assert_eq!((decode.id.as_str(), decode.snr, decode.message.as_str()), ("WSJT-X", -12, "CQ K1ABC FN42"));
+        assert_eq!(decode.time, NaiveTime::from_hms_opt(12, 30, 15).unwrap());
+        for len in 0..payload.len() {
+            assert!(decode_decode(&payload[..len], false).is_none(), "{} of {} bytes", len, payload.len());
+        }
+        // a string claiming more bytes than are left
+        let mut payload = Vec::new();
+        add_u32_to_payload(&mut payload, 6);
+        add_u32_to_payload(&mut payload, 1000);
+        payload.extend_from_slice(b"WSJT-X");
+        assert!(decode_close(&payload, false).is_none());
+    }
+
+    #[test]
+    fn headers() {
+        let close = encode_message(encode_close(&Close { message_type: 6, id: "WSJT-X".to_string() }));
+        assert_eq!(parse_header(&close).map(|(message, messagetype)| (message.schema_number, messagetype)), Some((2, 6)));
+        assert!(parse_header(b"Hello, server!").is_none());
+        assert!(parse_header(&close[..11]).is_none());
+        assert!(parse_header(&[]).is_none());
+    }
+
+    #[test]
+    fn garbage_does_not_panic() {
+        let app_state = AppState::for_test("{}");
+        let src: SocketAddr = "127.0.0.1:2237".parse().unwrap();
+        let mut header = Vec::new();
+        add_u32_to_payload(&mut header, MAGIC_NUMBER);
+        add_u32_to_payload(&mut header, 2);
+        let with_header = |tail: &[u8]| [header.as_slice(), tail].concat();
+        let packets = [
+            b"Hello, server!".to_vec(),
+            Vec::new(),
+            vec![0xad],
+            header.clone(),
+            with_header(&[0, 0, 0, 1]),
+            with_header(&[0, 0, 0, 2, 0, 0, 0, 6, b'W']),
+            with_header(&[0, 0, 0, 99, 0xff, 0xff]),
+            with_header(&decode_payload()[..20]),
+            with_header(&[0xff; 64]),
+        ];
+        for packet in packets {
+            handle_incoming_data(&packet, src, &app_state);
+        }
+        assert!(app_state.instances.lock().unwrap().get("WSJT-X").and_then(|instance| instance.version.as_ref()).is_none());
+
+        let heartbeat = Heartbeat { message_type: 0, id: "WSJT-X".to_string(), maximum_schema_number: 3, version: "2.7.0".to_string(), revision: "abc".to_string() };
+        handle_incoming_data(&encode_message(encode_heartbeat(&heartbeat)), src, &app_state);
+        let version = app_state.instances.lock().unwrap().get("WSJT-X").and_then(|instance| instance.version.clone());
+        assert_eq!(version.as_deref(), Some("2.7.0"));
+    }
+}
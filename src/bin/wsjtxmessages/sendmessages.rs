@@ -41,11 +41,46 @@ pub fn add_bool_to_payload(payload: &mut Vec<u8>, value: bool) {
 //     add_u32_to_payload(payload, total_milliseconds);
 // }
 
+// QColor streams as a spec byte then alpha, red, green, blue and padding as u16s,
+// colours are "#rrggbb" and an empty string sends an invalid colour, which WSJT-X treats as "clear"
+pub fn add_qcolor_to_payload(payload: &mut Vec<u8>, colour: &str) {
+    match parse_colour(colour) {
+        Some((red, green, blue)) => {
+            add_u8_to_payload(payload, 1);
+            for component in [255, red, green, blue] {
+                add_u16_to_payload(payload, component as u16 * 0x101);
+            }
+        }
+        None => {
+            add_u8_to_payload(payload, 0);
+            for _ in 0..4 {
+                add_u16_to_payload(payload, 0);
+            }
+        }
+    }
+    add_u16_to_payload(payload, 0);
+}
+
+pub fn parse_colour(colour: &str) -> Option<(u8, u8, u8)> {
+    let hex = colour.strip_prefix('#')?;
+    if hex.len() != 6 || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        return None;
+    }
+    let component = |range| u8::from_str_radix(&hex[range], 16).ok();
+    Some((component(0..2)?, component(2..4)?, component(4..6)?))
+}
+
+pub fn add_u16_to_payload(payload: &mut Vec<u8>, value: u16) {
+    let mut bytes = [0u8; 2];
+    BigEndian::write_u16(&mut bytes, value);
+    payload.extend_from_slice(&bytes);
+}
+
 pub fn add_naive_time_to_payload(payload: &mut Vec<u8>, time: NaiveTime) {
     let total_seconds = time.num_seconds_from_midnight();
     let nanoseconds_within_last_second = time.nanosecond();
     let total_milliseconds = total_seconds * 1000 + nanoseconds_within_last_second / 1_000_000;
-    add_u32_to_payload(payload, total_milliseconds as u32);
+    add_u32_to_payload(payload, total_milliseconds);
 }
 
 pub fn encode_message(encoded_message: Vec<u8>) -> Vec<u8> {
@@ -211,10 +246,10 @@ pub fn encode_highlight_callsign_in(highlight_callsign_in: &HighlightCallsignIn)
     add_string_to_payload(&mut payload, &highlight_callsign_in.callsign);
 
     // Add background color to payload
-    add_string_to_payload(&mut payload, &highlight_callsign_in.background_color);
+    add_qcolor_to_payload(&mut payload, &highlight_callsign_in.background_color);
 
     // Add foreground color to payloud 
-    add_string_to_payload(&mut payload, &highlight_callsign_in.foreground_color);
+    add_qcolor_to_payload(&mut payload, &highlight_callsign_in.foreground_color);
 
     // Add highlight last to payload
     add_bool_to_payload(&mut payload, highlight_callsign_in.highlight_last);
@@ -274,9 +309,73 @@ pub fn encode_configure(configure: &Configure) -> Vec<u8> {
     payload
 }
 
+pub fn send_to_instance(app_state: &AppState, id: &str, encoded_message: Vec<u8>) -> io::Result<()> {
+    let address = match app_state.instances.lock().unwrap().instances.get_mut(id) {
+        Some(instance) => {
+            instance.last_command = Some(Utc::now());
+            instance.address
+        }
+        None => return Err(io::Error::new(io::ErrorKind::NotFound, format!("No WSJT-X instance with id {}", id))),
+    };
+    if DEBUG {
+        println!("Sending {} bytes to {} at {}", encoded_message.len(), id, address);
+    }
+    app_state.socket.send_to(&encode_message(encoded_message), address)?;
+    Ok(())
+}
+
 pub fn send_encoded_message(socket: &UdpSocket, message: Vec<u8>, address: SocketAddr) -> io::Result<()> {
     println!("Sending message: {:?}", message);
     println!("To address: {:?}", address);
     socket.send_to(&message, address)?;
     Ok(())
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn qcolor(colour: &str) -> Vec<u8> {
+        let mut payload = Vec::new();
+        add_qcolor_to_payload(&mut payload, colour);
+        payload
+    }
+
+    #[test]
+    fn qcolor_is_argb() {
+        // spec 1 (RGB), then alpha, red, green and blue scaled to 16 bits, then the pad
+        assert_eq!(qcolor("#ff0000"), [1, 0xff, 0xff, 0xff, 0xff, 0, 0, 0, 0, 0, 0]);
+        assert_eq!(qcolor("#12aBcD"), [1, 0xff, 0xff, 0x12, 0x12, 0xab, 0xab, 0xcd, 0xcd, 0, 0]);
+        assert_eq!(parse_colour("#000000"), Some((0, 0, 0)));
+    }
+
+    #[test]
+    fn bad_colours_are_invalid() {
+        // an invalid QColor has spec 0, WSJT-X takes it as "clear the highlight"
+        let invalid = [0; 11];
+        for colour in ["", "ff0000", "#ff00", "#ff00000", "#gg0000", "red"] {
+            assert_eq!(qcolor(colour), invalid, "'{}'", colour);
+            assert_eq!(parse_colour(colour), None, "'{}'", colour);
+        }
+    }
+
+    #[test]
+    fn highlight_payload() {
+        let payload = encode_highlight_callsign_in(&HighlightCallsignIn {
+            message_type: 13,
+            id: "WSJT-X".to_string(),
+            callsign: "K1ABC".to_string(),
+            background_color: "#ffff00".to_string(),
+            foreground_color: String::new(),
+            highlight_last: true,
+        });
+        let mut expected = vec![0, 0, 0, 13, 0, 0, 0, 6];
+        expected.extend_from_slice(b"WSJT-X");
+        expected.extend_from_slice(&[0, 0, 0, 5]);
+        expected.extend_from_slice(b"K1ABC");
+        expected.extend(qcolor("#ffff00"));
+        expected.extend(qcolor(""));
+        expected.push(1);
+        assert_eq!(payload, expected);
+    }
+}
@@ -0,0 +1,374 @@
+use std::collections::VecDeque;
+use std::fs::OpenOptions;
+use std::io::{self, Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::thread;
+use chrono::{DateTime, Duration, Utc};
+use colored::*;
+use serde_derive::Deserialize;
+use super::*;
+
+const TICK: std::time::Duration = std::time::Duration::from_secs(1);
+const HTTP_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(10);
+// WSPRnet's mode number for two minute WSPR
+const WSPR2_MODE: u32 = 2;
+
+// "wspr" in config.json, WSPR spots saved in WSPRnet's format and uploaded there, both off unless set
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct WsprConfig {
+    // a file each spot is appended to as a WSPRnet upload line, relative to the config file
+    pub spots_file: Option<String>,
+    pub upload: bool,
+    // WSPRnet's spot query interface, only plain http
+    pub endpoint: String,
+    // spots kept for retrying while WSPRnet can't be reached, the oldest are dropped beyond this
+    pub max_queue: usize,
+    pub retry_seconds: u64,
+}
+
+impl Default for WsprConfig {
+    fn default() -> Self {
+        Self {
+            spots_file: None,
+            upload: false,
+            endpoint: "http://wsprnet.org/post".to_string(),
+            max_queue: 1000,
+            retry_seconds: 60,
+        }
+    }
+}
+
+impl WsprConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        HttpUrl::parse(&self.endpoint).map(|_| ()).map_err(|e| format!("wspr: endpoint {}", e))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct HttpUrl {
+    host: String,
+    port: u16,
+    path: String,
+}
+
+impl HttpUrl {
+    fn parse(url: &str) -> Result<Self, String> {
+        let rest = url.strip_prefix("http://").ok_or(format!("'{}' has to start with http://", url))?;
+        let (authority, path) = match rest.find('/') {
+            Some(index) => (&rest[..index], &rest[index..]),
+            None => (rest, "/"),
+        };
+        let (host, port) = match authority.rsplit_once(':') {
+            Some((host, port)) => (host, port.parse::<u16>().map_err(|_| format!("'{}' has a bad port", url))?),
+            None => (authority, 80),
+        };
+        if host.is_empty() {
+            return Err(format!("'{}' has no host", url));
+        }
+        Ok(Self { host: host.to_string(), port, path: path.to_string() })
+    }
+}
+
+fn url_encode(text: &str) -> String {
+    text.bytes().map(|byte| match byte {
+        b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => (byte as char).to_string(),
+        _ => format!("%{:02X}", byte),
+    }).collect()
+}
+
+// a GET that only cares whether the server accepted it
+fn http_get(url: &HttpUrl, query: &str) -> io::Result<()> {
+    let address = (url.host.as_str(), url.port).to_socket_addrs()?
+        .next()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, format!("could not resolve {}", url.host)))?;
+    let mut stream = TcpStream::connect_timeout(&address, HTTP_TIMEOUT)?;
+    stream.set_read_timeout(Some(HTTP_TIMEOUT))?;
+    stream.set_write_timeout(Some(HTTP_TIMEOUT))?;
+    let separator = if url.path.contains('?') { '&' } else { '?' };
+    write!(stream, "GET {}{}{} HTTP/1.0\r\nHost: {}\r\nUser-Agent: {}/{}\r\nConnection: close\r\n\r\n",
+        url.path, separator, query, url.host, env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"))?;
+    let mut response = String::new();
+    stream.read_to_string(&mut response)?;
+    let status_line = response.lines().next().unwrap_or("");
+    match status_line.split_whitespace().nth(1).and_then(|code| code.parse::<u16>().ok()) {
+        Some(code) if (200..300).contains(&code) => Ok(()),
+        _ => Err(io::Error::other(format!("server answered '{}'", status_line))),
+    }
+}
+
+// a decode with the time of day made into a date and time
+#[derive(Debug, Clone)]
+pub struct WsprSpot {
+    pub decode: WSPRDecode,
+    pub time: DateTime<Utc>,
+    // our call, grid and dial frequency from the instance's Status when it was decoded
+    pub receiver_call: String,
+    pub receiver_grid: String,
+    pub dial_frequency: u64,
+}
+
+impl WsprSpot {
+    // date, time, snr, dt, freq MHz, call, grid, dBm, drift as WSPRnet takes them in an upload file
+    pub fn wsprnet_line(&self) -> String {
+        let decode = &self.decode;
+        format!("{} {:>3} {:>4.1} {:>10.6}  {} {} {:>2} {:>2}",
+            self.time.format("%y%m%d %H%M"), decode.snr, decode.delta_time_s, decode.frequency_hz as f64 / 1e6,
+            strip_call(&decode.callsign), decode.grid, decode.power_dbm, decode.drift)
+    }
+
+    fn query(&self) -> String {
+        let decode = &self.decode;
+        [
+            ("function", "wspr".to_string()),
+            ("rcall", self.receiver_call.clone()),
+            ("rgrid", self.receiver_grid.clone()),
+            ("rqrg", format!("{:.6}", self.dial_frequency as f64 / 1e6)),
+            ("date", self.time.format("%y%m%d").to_string()),
+            ("time", self.time.format("%H%M").to_string()),
+            ("sig", decode.snr.to_string()),
+            ("dt", format!("{:.1}", decode.delta_time_s)),
+            ("drift", decode.drift.to_string()),
+            ("tqrg", format!("{:.6}", decode.frequency_hz as f64 / 1e6)),
+            ("tcall", strip_call(&decode.callsign)),
+            ("tgrid", decode.grid.clone()),
+            ("dbm", decode.power_dbm.to_string()),
+            ("version", format!("{} {}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"))),
+            ("mode", WSPR2_MODE.to_string()),
+        ].iter().map(|(name, value)| format!("{}={}", name, url_encode(value))).collect::<Vec<_>>().join("&")
+    }
+}
+
+pub fn append_wspr_spot(path: &Path, spot: &WsprSpot) -> io::Result<()> {
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+    writeln!(file, "{}", spot.wsprnet_line())
+}
+
+// spots waiting for WSPRnet, oldest first
+#[derive(Debug, Default)]
+pub struct WsprUploads {
+    queue: VecDeque<WsprSpot>,
+    // after a failure nothing is sent until then
+    retry_at: Option<DateTime<Utc>>,
+    pub uploaded: u64,
+    pub dropped: u64,
+    pub last_error: Option<String>,
+}
+
+impl WsprUploads {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push(&mut self, spot: WsprSpot, max_queue: usize) {
+        self.queue.push_back(spot);
+        while self.queue.len() > max_queue {
+            self.queue.pop_front();
+            self.dropped += 1;
+        }
+    }
+}
+
+impl std::fmt::Display for WsprUploads {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        writeln!(f, "WSPRnet: {} queued, {} uploaded, {} dropped", self.queue.len(), self.uploaded, self.dropped)?;
+        if let (Some(error), Some(retry_at)) = (&self.last_error, self.retry_at) {
+            writeln!(f, "Last error: {}, retrying at {}", error, retry_at.format("%H:%M:%S"))?;
+        }
+        Ok(())
+    }
+}
+
+fn print_wspr_decode(spot: &WsprSpot, iaru_region: u8) {
+    let decode = &spot.decode;
+    let band = band_for_frequency(decode.frequency_hz, iaru_region).unwrap_or("");
+    let snr = if decode.snr >= 0 { format!("+{}", decode.snr).green() } else { decode.snr.to_string().red() };
+    println!("{}: {} {} SNR: {} DT: {:.1} Drift: {} WSPR {} {} {} dBm", decode.time, band.cyan(), format_frequency_mhz(decode.frequency_hz),
+        snr, decode.delta_time_s, decode.drift, strip_call(&decode.callsign).bold(), decode.grid.green(), decode.power_dbm);
+}
+
+// shows, saves and queues a WSPR decode, the store and the web dashboard get it separately
+pub fn handle_wspr_decode(decode: &WSPRDecode, app_state: &AppState) {
+    let config = app_state.config();
+    let (receiver_call, receiver_grid, dial_frequency) = match app_state.instances.lock().unwrap().status(&decode.id) {
+        Some(status) => (non_empty(&status.de_call).map(strip_call).unwrap_or_default(), status.de_grid.clone(), status.dial_frequency),
+        None => (String::new(), String::new(), 0),
+    };
+    let spot = WsprSpot { decode: decode.clone(), time: decode_timestamp(decode.time, Utc::now()), receiver_call, receiver_grid, dial_frequency };
+    if !app_state.tui {
+        print_wspr_decode(&spot, config.iaru_region);
+    }
+    // replays and our own transmissions aren't spots
+    if !decode.new || decode.off_air {
+        return;
+    }
+    if let Some(path) = config.wspr_spots_path(&app_state.config_path) {
+        if let Err(e) = append_wspr_spot(&path, &spot) {
+            app_state.log(&format!("Could not write {}: {}", path.display(), e));
+        }
+    }
+    if config.wspr.upload {
+        if spot.receiver_call.is_empty() {
+            return app_state.log("WSPRnet: no Status from WSJT-X yet, spot not uploaded");
+        }
+        app_state.wspr_uploads.lock().unwrap().push(spot, config.wspr.max_queue);
+    }
+}
+
+// sends what is queued, stopping at the first failure and trying again after retry_seconds,
+// the lines to log are returned: the first failure and the recovery
+fn upload_wspr(uploads: &Mutex<WsprUploads>, config: &WsprConfig, now: DateTime<Utc>) -> Vec<String> {
+    let mut log = Vec::new();
+    let url = match HttpUrl::parse(&config.endpoint) {
+        Ok(url) => url,
+        Err(_) => return log,
+    };
+    loop {
+        let spot = {
+            let uploads = uploads.lock().unwrap();
+            if uploads.retry_at.map(|retry_at| now < retry_at).unwrap_or(false) {
+                return log;
+            }
+            match uploads.queue.front() {
+                Some(spot) => spot.clone(),
+                None => return log,
+            }
+        };
+        // the lock isn't held while we wait on the network
+        let result = http_get(&url, &spot.query());
+        let mut uploads = uploads.lock().unwrap();
+        match result {
+            Ok(()) => {
+                uploads.queue.pop_front();
+                uploads.uploaded += 1;
+                uploads.retry_at = None;
+                if uploads.last_error.take().is_some() {
+                    log.push(format!("WSPRnet upload working again, {} spots still queued", uploads.queue.len()));
+                }
+            }
+            Err(e) => {
+                if uploads.last_error.is_none() {
+                    log.push(format!("WSPRnet upload failed, {} spots queued: {}", uploads.queue.len(), e));
+                }
+                uploads.retry_at = Some(now + Duration::seconds(config.retry_seconds as i64));
+                uploads.last_error = Some(e.to_string());
+                return log;
+            }
+        }
+    }
+}
+
+pub fn start_wspr_uploader(app_state: Arc<AppState>) {
+    thread::spawn(move || loop {
+        thread::sleep(TICK);
+        for line in upload_wspr(&app_state.wspr_uploads, &app_state.config().wspr, Utc::now()) {
+            app_state.log(&line);
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::io::{BufRead, BufReader};
+    use std::net::TcpListener;
+    use std::sync::mpsc;
+    use chrono::{NaiveTime, TimeZone};
+    use super::*;
+
+    fn spot(callsign: &str, snr: i32, delta_time_s: f64, frequency_hz: u64, grid: &str, power_dbm: i32, drift: i32) -> WsprSpot {
+        WsprSpot {
+            decode: WSPRDecode {
+                message_type: 10,
+                id: "WSJT-X".to_string(),
+                new: true,
+                time: NaiveTime::from_hms_opt(12, 0, 0).unwrap(),
+                snr,
+                delta_time_s,
+                frequency_hz,
+                drift,
+                callsign: callsign.to_string(),
+                grid: grid.to_string(),
+                power_dbm,
+                off_air: false,
+            },
+            time: Utc.with_ymd_and_hms(2026, 10, 19, 12, 0, 0).unwrap(),
+            receiver_call: "KE8TKS".to_string(),
+            receiver_grid: "EN80".to_string(),
+            dial_frequency: 14_095_600,
+        }
+    }
+
+    // answers one request with response and hands back its request line
+    fn serve_once(response: &'static str) -> (String, mpsc::Receiver<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let endpoint = format!("http://{}/post", listener.local_addr().unwrap());
+        let (sender, receiver) = mpsc::channel();
+        thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut request_line = String::new();
+            reader.read_line(&mut request_line).unwrap();
+            let mut line = String::new();
+            while reader.read_line(&mut line).unwrap() > 0 && line != "\r\n" {
+                line.clear();
+            }
+            (&stream).write_all(response.as_bytes()).unwrap();
+            sender.send(request_line).unwrap();
+        });
+        (endpoint, receiver)
+    }
+
+    #[test]
+    fn wsprnet_lines() {
+        let cases = [
+            (spot("K1ABC", -21, 0.3, 14_097_071, "FN42", 37, 0), "261019 1200 -21  0.3  14.097071  K1ABC FN42 37  0"),
+            (spot("<G4ABC/P>", 3, -1.2, 7_040_112, "IO91", 23, -1), "261019 1200   3 -1.2   7.040112  G4ABC/P IO91 23 -1"),
+            (spot("VK2XYZ", -28, 2.0, 474_261, "QF56", 5, 2), "261019 1200 -28  2.0   0.474261  VK2XYZ QF56  5  2"),
+        ];
+        for (spot, line) in cases {
+            assert_eq!(spot.wsprnet_line(), line);
+        }
+    }
+
+    #[test]
+    fn upload_sends_query() {
+        let (endpoint, request) = serve_once("HTTP/1.0 200 OK\r\n\r\n");
+        let config = WsprConfig { upload: true, endpoint, ..WsprConfig::default() };
+        let uploads = Mutex::new(WsprUploads::new());
+        uploads.lock().unwrap().push(spot("K1ABC", -21, 0.3, 14_097_071, "FN42", 37, 0), config.max_queue);
+        assert!(upload_wspr(&uploads, &config, Utc::now()).is_empty());
+        assert_eq!(uploads.lock().unwrap().uploaded, 1);
+
+        let request = request.recv().unwrap();
+        let target = request.split_whitespace().nth(1).unwrap();
+        let (path, query) = target.split_once('?').unwrap();
+        assert_eq!(path, "/post");
+        let fields: HashMap<&str, &str> = query.split('&').filter_map(|pair| pair.split_once('=')).collect();
+        let expected = [
+            ("function", "wspr"), ("rcall", "KE8TKS"), ("rgrid", "EN80"), ("rqrg", "14.095600"), ("date", "261019"), ("time", "1200"),
+            ("sig", "-21"), ("dt", "0.3"), ("drift", "0"), ("tqrg", "14.097071"), ("tcall", "K1ABC"), ("tgrid", "FN42"),
+            ("dbm", "37"), ("mode", "2"),
+        ];
+        for (name, value) in expected {
+            assert_eq!(fields.get(name), Some(&value), "{}", name);
+        }
+    }
+
+    #[test]
+    fn server_error_keeps_spot() {
+        let (endpoint, _request) = serve_once("HTTP/1.0 500 Internal Server Error\r\n\r\n");
+        let config = WsprConfig { upload: true, endpoint, retry_seconds: 60, ..WsprConfig::default() };
+        let uploads = Mutex::new(WsprUploads::new());
+        uploads.lock().unwrap().push(spot("K1ABC", -21, 0.3, 14_097_071, "FN42", 37, 0), config.max_queue);
+        let now = Utc::now();
+        assert_eq!(upload_wspr(&uploads, &config, now).len(), 1);
+        let uploads = uploads.lock().unwrap();
+        assert_eq!(uploads.queue.len(), 1);
+        assert_eq!(uploads.uploaded, 0);
+        assert_eq!(uploads.retry_at, Some(now + Duration::seconds(60)));
+        assert!(uploads.last_error.as_deref().unwrap().contains("500"));
+    }
+}